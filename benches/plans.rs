@@ -0,0 +1,120 @@
+//! Serialization through compiled plans, against the reflection walker and serde_derive.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serde_derive::{Deserialize, Serialize};
+use serde_reflect::*;
+
+#[derive(Reflect, Serialize, Deserialize)]
+#[repr(u16)]
+enum Event {
+    Idle,
+    Click { x: i32, y: i32, button: u8 },
+    Key(char),
+    Scroll(i16, i16),
+}
+
+#[derive(Reflect, Serialize, Deserialize)]
+struct Frame {
+    sequence: u64,
+    timestamp: u64,
+    source: String,
+    focused: bool,
+    events: [Event; 8],
+    bounds: (u32, u32, u32, u32),
+}
+
+fn frame() -> Frame {
+    let click = || Event::Click {
+        x: -120,
+        y: 4000,
+        button: 1,
+    };
+    Frame {
+        sequence: 1 << 40,
+        timestamp: 1_600_000_000,
+        source: "main-window".into(),
+        focused: true,
+        events: [
+            Event::Idle,
+            click(),
+            Event::Key('q'),
+            Event::Scroll(-3, 0),
+            click(),
+            Event::Key('w'),
+            Event::Idle,
+            Event::Scroll(0, 12),
+        ],
+        bounds: (0, 0, 1920, 1080),
+    }
+}
+
+fn db() -> Db<'static> {
+    let mut db = Db::new();
+    Frame::register(&mut db);
+    Event::register(&mut db);
+    db.register_serde_leaf::<String>();
+    db
+}
+
+fn serialize(c: &mut Criterion) {
+    let db = db();
+    let plans = db.compile();
+    let frame = frame();
+    let mut group = c.benchmark_group("serialize");
+    group.bench_function("serde_derive", |b| {
+        b.iter(|| serde_json::to_vec(black_box(&frame)).unwrap())
+    });
+    group.bench_function("walker", |b| {
+        b.iter(|| {
+            let mut out = Vec::new();
+            db.serialize(
+                &mut serde_json::Serializer::new(&mut out),
+                black_box(&frame),
+            )
+            .unwrap();
+            out
+        })
+    });
+    group.bench_function("plans", |b| {
+        b.iter(|| {
+            let mut out = Vec::new();
+            plans
+                .serialize(
+                    &mut serde_json::Serializer::new(&mut out),
+                    black_box(&frame),
+                )
+                .unwrap();
+            out
+        })
+    });
+    group.finish();
+}
+
+fn deserialize(c: &mut Criterion) {
+    let db = db();
+    let plans = db.compile();
+    let json = serde_json::to_vec(&frame()).unwrap();
+    let mut group = c.benchmark_group("deserialize");
+    group.bench_function("serde_derive", |b| {
+        b.iter(|| serde_json::from_slice::<Frame>(black_box(&json)).unwrap())
+    });
+    group.bench_function("walker", |b| {
+        b.iter(|| {
+            db.deserialize::<Frame, _>(&mut serde_json::Deserializer::from_slice(black_box(&json)))
+                .unwrap()
+        })
+    });
+    group.bench_function("plans", |b| {
+        b.iter(|| {
+            plans
+                .deserialize::<Frame, _>(&mut serde_json::Deserializer::from_slice(black_box(
+                    &json,
+                )))
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, serialize, deserialize);
+criterion_main!(benches);
@@ -0,0 +1,62 @@
+//! Generates `SYNTHETIC_TYPES` (default 100) record types, each with an enum of its own.
+
+use std::fmt::Write;
+use std::{env, fs, path::Path};
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=SYNTHETIC_TYPES");
+    let count: usize = env::var("SYNTHETIC_TYPES")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(100);
+
+    let derives = "#[cfg_attr(feature = \"derive-serde\", derive(serde_derive::Serialize, serde_derive::Deserialize))]\n\
+                   #[cfg_attr(feature = \"derive-reflect\", derive(serde_reflect::Reflect))]\n";
+    let mut out = String::new();
+    let mut each = String::new();
+    for n in 0..count {
+        let _ = write!(
+            out,
+            "{derives}#[repr(u16)]\n\
+             pub enum Choice{n} {{ Empty, Count(u16), Point {{ x: i32, y: i32 }} }}\n\n\
+             {derives}pub struct Record{n} {{\n    \
+                 pub id: u64,\n    \
+                 pub label: String,\n    \
+                 pub enabled: bool,\n    \
+                 pub weights: [u8; 4],\n    \
+                 pub offset: (i16, i16),\n    \
+                 pub choice: Choice{n},\n\
+             }}\n\n\
+             pub fn sample{n}() -> Record{n} {{\n    \
+                 Record{n} {{\n        \
+                     id: {n},\n        \
+                     label: \"record {n}\".into(),\n        \
+                     enabled: {enabled},\n        \
+                     weights: [1, 2, 3, {weight}],\n        \
+                     offset: (-1, {offset}),\n        \
+                     choice: {choice},\n    \
+                 }}\n\
+             }}\n\n",
+            derives = derives,
+            n = n,
+            enabled = n % 2 == 0,
+            weight = n % 256,
+            offset = n % 1000,
+            choice = match n % 3 {
+                0 => format!("Choice{}::Empty", n),
+                1 => format!("Choice{}::Count({})", n, n % 1000),
+                _ => format!("Choice{}::Point {{ x: {}, y: -{} }}", n, n, n),
+            },
+        );
+        let _ = writeln!(each, "        $m!(Record{n}, Choice{n}, sample{n});", n = n);
+    }
+    // `each_type!(m)` invokes `m!(Record, Choice, sample)` for every generated type
+    let _ = write!(
+        out,
+        "#[allow(unused_macros)]\nmacro_rules! each_type {{\n    ($m:ident) => {{\n{}    }};\n}}\n",
+        each
+    );
+
+    let dst = Path::new(&env::var("OUT_DIR").unwrap()).join("types.rs");
+    fs::write(dst, out).unwrap();
+}
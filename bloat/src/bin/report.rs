@@ -0,0 +1,104 @@
+//! Builds the synthetic binary with each derive and reports its size, how long the build took,
+//! and its round-trip throughput.
+//!
+//! Usage: `cargo run --release -p serde-reflect-bloat --bin report -- [types] [iterations]`
+
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+struct Measurement {
+    derive: &'static str,
+    size: u64,
+    compile: Duration,
+    bytes: u64,
+    nanos: u64,
+}
+
+fn main() -> Result<(), String> {
+    let mut args = env::args().skip(1);
+    let types = args.next().unwrap_or_else(|| "100".into());
+    let iterations = args.next().unwrap_or_else(|| "100".into());
+    let target = Path::new(env!("CARGO_MANIFEST_DIR")).join("../target/bloat");
+
+    let mut results = Vec::new();
+    for &(derive, feature) in &[
+        ("serde_derive", "derive-serde"),
+        ("reflect", "derive-reflect"),
+    ] {
+        let target_dir = target.join(feature);
+        let features = format!("serde-reflect-bloat/{}", feature);
+        let build = ["build", "--bin", "synthetic", "--features", &features];
+        // build the dependencies first, so only the synthetic types are timed
+        cargo(&build, &types, &target_dir)?;
+        cargo(&["clean", "-p", "serde-reflect-bloat"], &types, &target_dir)?;
+        let start = Instant::now();
+        cargo(&build, &types, &target_dir)?;
+        let compile = start.elapsed();
+
+        let binary = target_dir
+            .join("release")
+            .join(format!("synthetic{}", env::consts::EXE_SUFFIX));
+        let size = fs::metadata(&binary)
+            .map_err(|e| format!("{}: {}", binary.display(), e))?
+            .len();
+        let output = Command::new(&binary)
+            .arg(&iterations)
+            .output()
+            .map_err(|e| e.to_string())?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let line = stdout
+            .lines()
+            .find(|line| line.starts_with(derive))
+            .ok_or_else(|| format!("no measurement from {}", binary.display()))?;
+        let numbers: Vec<u64> = line
+            .split_whitespace()
+            .skip(1)
+            .filter_map(|n| n.parse().ok())
+            .collect();
+        let (bytes, nanos) = match numbers[..] {
+            [bytes, nanos] => (bytes, nanos),
+            _ => return Err(format!("malformed measurement {:?}", line)),
+        };
+        results.push(Measurement {
+            derive,
+            size,
+            compile,
+            bytes,
+            nanos,
+        });
+    }
+
+    println!("{} types, {} iterations", types, iterations);
+    println!(
+        "{:<14} {:>12} {:>12} {:>12}",
+        "derive", "binary", "compile", "throughput"
+    );
+    for m in &results {
+        println!(
+            "{:<14} {:>9} KiB {:>10.2} s {:>7.1} MB/s",
+            m.derive,
+            m.size / 1024,
+            m.compile.as_secs_f64(),
+            m.bytes as f64 * 1e3 / m.nanos.max(1) as f64
+        );
+    }
+    Ok(())
+}
+
+/// Run a cargo subcommand on this package, in release mode.
+fn cargo(args: &[&str], types: &str, target_dir: &Path) -> Result<(), String> {
+    let status = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".into()))
+        .args(args)
+        .args(["--release", "-p", "serde-reflect-bloat", "--target-dir"])
+        .arg(target_dir)
+        .env("SYNTHETIC_TYPES", types)
+        .status()
+        .map_err(|e| e.to_string())?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("`cargo {}` failed", args.join(" ")))
+    }
+}
@@ -0,0 +1,88 @@
+//! Round-trips every synthetic type through JSON with whichever derives are enabled, printing
+//! one `<derive> <bytes> <nanoseconds>` line for each.
+
+#![allow(dead_code)]
+
+#[cfg(any(feature = "derive-serde", feature = "derive-reflect"))]
+use std::time::Instant;
+
+include!(concat!(env!("OUT_DIR"), "/types.rs"));
+
+fn main() {
+    let iterations: usize = std::env::args()
+        .nth(1)
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(100);
+
+    #[cfg(feature = "derive-serde")]
+    {
+        let (bytes, nanos) = serde_derive_roundtrips(iterations);
+        println!("serde_derive {} {}", bytes, nanos);
+    }
+    #[cfg(feature = "derive-reflect")]
+    {
+        let (bytes, nanos) = reflect_roundtrips(iterations);
+        println!("reflect {} {}", bytes, nanos);
+    }
+    #[cfg(not(any(feature = "derive-serde", feature = "derive-reflect")))]
+    {
+        let _ = iterations;
+        eprintln!("enable the derive-serde or derive-reflect feature to measure anything");
+    }
+}
+
+/// Bytes of JSON written and read, and how long that took.
+#[cfg(feature = "derive-serde")]
+fn serde_derive_roundtrips(iterations: usize) -> (usize, u128) {
+    let mut bytes = 0;
+    let start = Instant::now();
+    for _ in 0..iterations {
+        macro_rules! roundtrip {
+            ($record:ident, $choice:ident, $sample:ident) => {
+                let json = serde_json::to_vec(&$sample()).unwrap();
+                let back: $record = serde_json::from_slice(&json).unwrap();
+                std::hint::black_box(back);
+                bytes += 2 * json.len();
+            };
+        }
+        each_type!(roundtrip);
+    }
+    (bytes, start.elapsed().as_nanos())
+}
+
+/// Bytes of JSON written and read through compiled plans, and how long that took.
+#[cfg(feature = "derive-reflect")]
+fn reflect_roundtrips(iterations: usize) -> (usize, u128) {
+    use serde_reflect::{Db, Reflect};
+
+    let mut db = Db::new();
+    db.register_serde_leaf::<String>();
+    macro_rules! register {
+        ($record:ident, $choice:ident, $sample:ident) => {
+            $record::register(&mut db);
+            $choice::register(&mut db);
+        };
+    }
+    each_type!(register);
+    let plans = db.compile();
+
+    let mut bytes = 0;
+    let start = Instant::now();
+    for _ in 0..iterations {
+        macro_rules! roundtrip {
+            ($record:ident, $choice:ident, $sample:ident) => {
+                let mut json = Vec::new();
+                plans
+                    .serialize(&mut serde_json::Serializer::new(&mut json), &$sample())
+                    .unwrap();
+                let back: $record = plans
+                    .deserialize(&mut serde_json::Deserializer::from_slice(&json))
+                    .unwrap();
+                std::hint::black_box(back);
+                bytes += 2 * json.len();
+            };
+        }
+        each_type!(roundtrip);
+    }
+    (bytes, start.elapsed().as_nanos())
+}
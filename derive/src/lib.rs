@@ -1,8 +1,9 @@
 //! Automatically derive reflection metadata with `#[derive(Reflect)]`
 
 use itertools::Itertools;
-use proc_macro2::TokenStream;
-use quote::{format_ident, quote, ToTokens};
+use proc_macro2::{Literal, TokenStream};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
+use syn::spanned::Spanned;
 extern crate alloc;
 
 use syn::{Data, DataEnum, DataStruct, Fields, Ident};
@@ -17,6 +18,29 @@ struct DeriveReflect<'a> {
     nightly_const: bool,
     generics: &'a syn::Generics,
     seen_types: Vec<&'a syn::Type>,
+    // the field types of the enum variant being visited, whose field offsets come from
+    // the layout of `#[repr(u16)]` rather than `offset_of!`
+    variant_fields: Option<Vec<&'a syn::Type>>,
+    // one `__field_hook!` per `#[reflect(with = "...")]`, which fails to compile without the
+    // `attrs` feature that hooks are found through
+    hook_checks: Vec<TokenStream>,
+    // the `#[serde(rename_all = "...")]` that applies to the fields being visited
+    rename_all: Option<String>,
+}
+
+/// The `#[reflect(with = "...")]` among `attrs`, if there is one.
+fn hook_attr(attrs: &[syn::Attribute]) -> Option<&syn::Attribute> {
+    let is_with = |nested: &syn::NestedMeta| match nested {
+        syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) => nv.path.is_ident("with"),
+        _ => false,
+    };
+    attrs.iter().find(|a| {
+        a.path.is_ident("reflect")
+            && match a.parse_meta() {
+                Ok(syn::Meta::List(ml)) => ml.nested.iter().any(is_with),
+                _ => false,
+            }
+    })
 }
 
 fn primitive(p: &syn::Lit) -> TokenStream {
@@ -44,6 +68,103 @@ fn primitive(p: &syn::Lit) -> TokenStream {
     }
 }
 
+/// The string value of `#[serde(<key> = "...")]` among `attrs`, if there is one. For
+/// `#[serde(<key>(serialize = "...", ...))]`, it's the `serialize` one.
+fn serde_str(attrs: &[syn::Attribute], key: &str) -> Option<String> {
+    let lit_str = |lit: &syn::Lit| match lit {
+        syn::Lit::Str(s) => Some(s.value()),
+        _ => None,
+    };
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("serde"))
+        .filter_map(|a| match a.parse_meta() {
+            Ok(syn::Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .find_map(|nested| match nested {
+            syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) if nv.path.is_ident(key) => {
+                lit_str(&nv.lit)
+            }
+            syn::NestedMeta::Meta(syn::Meta::List(list)) if list.path.is_ident(key) => {
+                list.nested.iter().find_map(|nested| match nested {
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(nv))
+                        if nv.path.is_ident("serialize") =>
+                    {
+                        lit_str(&nv.lit)
+                    }
+                    _ => None,
+                })
+            }
+            _ => None,
+        })
+}
+
+/// The label serde gives `name`, which has `attrs`, when its container has
+/// `#[serde(rename_all = "<rule>")]`. Variant names are `PascalCase`, and field names
+/// `snake_case`, as serde assumes.
+fn serde_label(attrs: &[syn::Attribute], name: &str, rule: Option<&str>, variant: bool) -> String {
+    if let Some(rename) = serde_str(attrs, "rename") {
+        return rename;
+    }
+    let words: Vec<String> = if variant {
+        let mut words = Vec::new();
+        for c in name.chars() {
+            if c.is_uppercase() || words.is_empty() {
+                words.push(String::new());
+            }
+            words.last_mut().unwrap().push(c);
+        }
+        words
+    } else {
+        name.split('_').map(String::from).collect()
+    };
+    let lower = |w: &String| w.to_lowercase();
+    let upper = |w: &String| w.to_uppercase();
+    let capital = |w: &String| {
+        let mut cs = w.chars();
+        cs.next()
+            .map_or(String::new(), |c| c.to_uppercase().chain(cs).collect())
+    };
+    match rule {
+        Some("lowercase") => name.to_lowercase(),
+        Some("UPPERCASE") => name.to_uppercase(),
+        Some("PascalCase") => words.iter().map(capital).collect(),
+        Some("camelCase") => {
+            let pascal: String = words.iter().map(capital).collect();
+            let mut cs = pascal.chars();
+            cs.next()
+                .map_or(String::new(), |c| c.to_lowercase().chain(cs).collect())
+        }
+        Some("snake_case") => words.iter().map(lower).join("_"),
+        Some("SCREAMING_SNAKE_CASE") => words.iter().map(upper).join("_"),
+        Some("kebab-case") => words.iter().map(lower).join("-"),
+        Some("SCREAMING-KEBAB-CASE") => words.iter().map(upper).join("-"),
+        _ => name.to_string(),
+    }
+}
+
+/// Whether `attrs` has `#[repr(u16)]`, which gives an enum the layout of a union of
+/// `#[repr(C)]` structs that each start with a `u16` tag.
+fn is_repr_u16(attrs: &[syn::Attribute]) -> bool {
+    let reprs: Vec<String> = attrs
+        .iter()
+        .filter(|a| a.path.is_ident("repr"))
+        .filter_map(|a| match a.parse_meta() {
+            Ok(syn::Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|nested| match nested {
+            syn::NestedMeta::Meta(syn::Meta::Path(p)) => p.get_ident().map(|i| i.to_string()),
+            _ => None,
+        })
+        .collect();
+    // `#[repr(C, u16)]` is laid out differently
+    reprs.iter().any(|r| r == "u16") && !reprs.iter().any(|r| r == "C")
+}
+
 impl<'a> DeriveReflect<'a> {
     fn parent(&self) -> Ident {
         format_ident!(
@@ -67,7 +188,7 @@ impl<'a> DeriveReflect<'a> {
         match m {
             syn::Meta::Path(p) => {
                 let p = p.to_token_stream().to_string();
-                quote! { Attr::Name(#p)  }
+                quote! { _reflect::Attr::Name(#p)  }
             }
             syn::Meta::List(ml) => {
                 let nesteds = ml
@@ -75,24 +196,20 @@ impl<'a> DeriveReflect<'a> {
                     .iter()
                     .map(|nested| match nested {
                         syn::NestedMeta::Meta(m) => self.nested(ix, |me| me.meta(0, m)),
-                        syn::NestedMeta::Lit(l) => l.to_token_stream(),
+                        syn::NestedMeta::Lit(l) => {
+                            let lit = primitive(l);
+                            quote! { _reflect::Attr::NameValue("", &#lit) }
+                        }
                     })
                     .collect::<Vec<_>>();
                 let path = ml.path.to_token_stream().to_string();
-                let nesteds_ident = format_ident!(
-                    "{}_META_{}_{}",
-                    self.parent(),
-                    ix,
-                    self.nesting.as_ref().unwrap_or(&String::new())
-                );
-                self.consts.push(quote!{const #nesteds_ident : &'static [_reflect::Attr<'static>] = &[#(#nesteds),*]; });
-                let list = self.list_reference(nesteds_ident, nesteds);
-                quote! { _reflect::Attr::List(#path, #list) }
+                // the nested attributes are `&'static`, so they are always consts
+                quote! { _reflect::Attr::List(#path, &[#(&#nesteds),*]) }
             }
             syn::Meta::NameValue(mnv) => {
                 let path = mnv.path.to_token_stream().to_string();
                 let lit = primitive(&mnv.lit);
-                quote! { _reflect::Attr::NameValue(#path, #lit) }
+                quote! { _reflect::Attr::NameValue(#path, &#lit) }
             }
         }
     }
@@ -100,7 +217,7 @@ impl<'a> DeriveReflect<'a> {
         if self.nightly_const {
             quote! { alloc::borrow::Cow::Borrowed(#id) }
         } else {
-            quote! { alloc::borrow::Cow::Owned(vec![#(#elts),*]) }
+            quote! { alloc::borrow::Cow::Owned(alloc::vec![#(#elts),*]) }
         }
     }
 
@@ -112,6 +229,19 @@ impl<'a> DeriveReflect<'a> {
             .collect()
     }
 
+    /// The `attrs` of some metadata, which are only kept with the `attrs` feature.
+    fn attrs_field(&mut self, attrs_name: Ident, attrs: &[syn::Attribute]) -> TokenStream {
+        if cfg!(feature = "attrs") {
+            let attrs = self.attrs(attrs);
+            self.consts
+                .push(quote! { const #attrs_name : &'static [_reflect::Attr] = &[#(#attrs),*]; });
+            let attrs = self.list_reference(attrs_name, attrs);
+            quote! { attrs: #attrs, }
+        } else {
+            quote! {}
+        }
+    }
+
     fn field(&mut self, ix: usize, f: &'a syn::Field) -> TokenStream {
         let attrs_name = format_ident!(
             "{}_{}_ATTRS",
@@ -120,25 +250,30 @@ impl<'a> DeriveReflect<'a> {
                 .clone()
                 .map_or_else(|| ix.to_string(), |f| f.to_string())
         );
+        let variant_offset = self.variant_fields.as_ref().map(|tys| {
+            quote! {
+                _reflect::__variant_field_offset(&[#(core::alloc::Layout::new::<#tys>()),*], #ix)
+            }
+        });
         let ix = syn::Index::from(ix);
 
-        let attrs = self.attrs(&f.attrs);
-        self.consts.push(
-            quote! { const #attrs_name : &'static [_reflect::Attr<'static>] = & [#(#attrs),*] },
-        );
-        let attrs = self.list_reference(attrs_name, attrs);
+        if let Some(hook) = hook_attr(&f.attrs) {
+            self.hook_checks
+                .push(quote_spanned! { hook.span() => _reflect::__field_hook!(); });
+        }
+        let attrs = self.attrs_field(attrs_name, &f.attrs);
 
-        let offset = match &f.ident {
-            Some(name) => {
+        let offset = match (variant_offset, &f.ident) {
+            (Some(offset), _) => offset,
+            (None, Some(name)) => {
                 quote! { memoffset::offset_of!(Self, #name) }
             }
-            None => {
+            (None, None) => {
                 quote! { memoffset::offset_of!(Self, #ix) }
             }
         };
 
-        let field_ty = &f.ty;
-        let shape = self.visit_ty(offset.clone(), field_ty);
+        let shape = self.visit_ty(&f.ty);
 
         match &f.ident {
             Some(name) => quote! {
@@ -146,15 +281,15 @@ impl<'a> DeriveReflect<'a> {
                     offset: #offset,
                     shape: #shape,
                     name: stringify!(#name),
-                    attrs: #attrs,
+                    #attrs
                 }
             },
             None => {
                 quote! {
                     _reflect::TupleField {
                         offset: #offset,
-                        shape: #shape,
-                        attrs: #attrs,
+                        shape: alloc::boxed::Box::new(#shape),
+                        #attrs
                     }
                 }
             }
@@ -163,30 +298,27 @@ impl<'a> DeriveReflect<'a> {
 
     /// having traversed through all of the "item" components of a type declaration,
     /// we find ourselves at the very bottom looking at a field containing a structural rust type.
-    fn visit_ty(&mut self, offset: TokenStream, ty: &'a syn::Type) -> TokenStream {
+    fn visit_ty(&mut self, ty: &'a syn::Type) -> TokenStream {
         match ty {
             syn::Type::Array(arr) => {
-                let nested_shape = self.visit_ty(offset, &arr.elem);
+                let elem = &arr.elem;
                 let len = &arr.len;
-                quote! { _reflect::DataShape::FixedArray(#nested_shape, #len)}
+                quote! { _reflect::DataShape::FixedArray(core::any::TypeId::of::<#elem>(), #len) }
             }
             syn::Type::Paren(syn::TypeParen { elem, .. })
-            | syn::Type::Group(syn::TypeGroup { elem, .. }) => self.visit_ty(offset, elem),
+            | syn::Type::Group(syn::TypeGroup { elem, .. }) => self.visit_ty(elem),
             syn::Type::Infer(_) => panic!("are these even allowed where we're parsing for types?"),
             syn::Type::Path(path) => {
-                let p = path.to_token_stream();
-                quote! {
-                    _reflect::_builtin_search(#path)
-                                .unwrap_or(_reflect::DataShape::Leaf(core::any::TypeId::of::<#path>()))
-                }
+                self.seen_types.push(ty);
+                quote! { _reflect::__shape_of::<#path>() }
             }
             syn::Type::Reference(refer) => {
-                let nested = self.visit_ty(quote! { 0 }, &refer.elem);
-                quote! { _reflect::DataShape::Ref(alloc::borrow::Cow::Owned(#nested)) }
+                let nested = self.visit_ty(&refer.elem);
+                quote! { _reflect::DataShape::Ref(alloc::boxed::Box::new(#nested)) }
             }
             syn::Type::Slice(slice) => {
-                let nested = self.visit_ty(quote! { 0 }, &slice.elem);
-                quote! { _reflect::DataShape::Slice(alloc::borrow::Cow::Owned(#nested)) }
+                let nested = self.visit_ty(&slice.elem);
+                quote! { _reflect::DataShape::Slice(alloc::boxed::Box::new(#nested)) }
             }
             syn::Type::Tuple(tuple) => {
                 let tup_elts = tuple
@@ -195,12 +327,24 @@ impl<'a> DeriveReflect<'a> {
                     .enumerate()
                     .map(|(ix, tp)| {
                         let ix = syn::Index::from(ix);
-                        self.visit_ty(quote! { memoffset::offset_of_tuple!(#offset, #ix) }, tp)
+                        let shape = self.visit_ty(tp);
+                        let attrs = if cfg!(feature = "attrs") {
+                            quote! { attrs: alloc::borrow::Cow::Borrowed(&[]), }
+                        } else {
+                            quote! {}
+                        };
+                        quote! {
+                            _reflect::TupleField {
+                                offset: memoffset::offset_of_tuple!(#tuple, #ix),
+                                shape: alloc::boxed::Box::new(#shape),
+                                #attrs
+                            }
+                        }
                     })
                     .collect::<Vec<_>>();
                 let tup_elts_name = format_ident!("{}_TUP_ELTS", self.parent());
                 self.consts.push(
-                        quote! { const #tup_elts_name : &'static [_reflect::DataShape<'static>] = &[#(#tup_elts),*]; },
+                        quote! { const #tup_elts_name : &'static [_reflect::TupleField<'static>] = &[#(#tup_elts),*]; },
                     );
 
                 let tup_elts = self.list_reference(tup_elts_name, tup_elts);
@@ -221,35 +365,35 @@ impl<'a> DeriveReflect<'a> {
         }
     }
 
-    /*
-        computing offsets for enum fields is busted.
-        need to relayout the struct according to the repr/union system,
-        and then get the layouts from that.
-    */
     fn variant_data(&mut self, fields: &'a syn::Fields) -> TokenStream {
         let fields_ident = format_ident!("{}_FIELDS", self.parent());
 
         match fields {
             Fields::Named(n) => {
+                let rule = self.rename_all.clone();
                 let field_labels = n
                     .named
                     .iter()
-                    .map(|f| f.ident.as_ref().expect("should be named").to_string());
+                    .map(|f| {
+                        let name = f.ident.as_ref().expect("should be named").to_string();
+                        serde_label(&f.attrs, &name, rule.as_deref(), false)
+                    })
+                    .collect::<Vec<_>>();
 
                 let fields = n
                     .named
                     .iter()
                     .enumerate()
-                    .map(|(ix, f)| self.field(ix + 1, f))
+                    .map(|(ix, f)| self.field(ix, f))
                     .collect::<Vec<_>>();
                 let field_labels_ident = format_ident!("{}_LABELS", fields_ident);
 
                 self.consts.push(
-                    quote! { const #fields_ident : &'static [&'static str] = &[#(#fields),*]; },
+                    quote! { const #fields_ident : &'static [_reflect::Field<'static>] = &[#(#fields),*]; },
                 );
                 let fields = self.list_reference(fields_ident, fields);
                 quote! { _reflect::VariantData::Fields {
-                    labels_for_serde: { const #field_labels_ident : &'static [&'static str] = &[#(#field_labels),*]; #field_labels_ident },
+                    labels_for_serde: { #[allow(non_upper_case_globals)] const #field_labels_ident : &'static [&'static str] = &[#(#field_labels),*]; #field_labels_ident },
                     fields: #fields
                 } }
             }
@@ -260,7 +404,7 @@ impl<'a> DeriveReflect<'a> {
                     .enumerate()
                     .map(|(ix, f)| self.field(ix, f))
                     .collect::<Vec<_>>();
-                self.consts.push(quote!{ const #fields_ident : &'static [_reflect::Field<'static>] = &[#(#fields),*]; } );
+                self.consts.push(quote!{ const #fields_ident : &'static [_reflect::TupleField<'static>] = &[#(#fields),*]; } );
 
                 let fields = self.list_reference(fields_ident, fields);
                 quote! { _reflect::VariantData::Tuple(#fields) }
@@ -278,24 +422,23 @@ impl<'a> DeriveReflect<'a> {
         r
     }
 
-    /// For a variant `v` at index `ix` in its containing enum, generate the `EnumArm`
-    /// reflecting it. `disc_base` is the index and defining expression of the last variant
-    /// in this enum to have explicitly set a discriminant. It is returned, unless this variant
-    /// itself has a discriminant set, in which case _that_ is returned.
-    fn variant(&mut self, ix: usize, v: &'a syn::Variant) -> TokenStream {
-        let attrs = self.parented(format_ident!("V{}", ix), |me| me.attrs(&v.attrs));
+    /// For a variant `v` at index `ix` in its containing enum, which serde knows as `label`,
+    /// generate the `EnumArm` reflecting it. `disc_base` is the index and defining expression of
+    /// the last variant in this enum to have explicitly set a discriminant. It is returned, unless
+    /// this variant itself has a discriminant set, in which case _that_ is returned.
+    fn variant(&mut self, ix: usize, v: &'a syn::Variant, label: &str) -> TokenStream {
+        let attrs_name = format_ident!("{}_{}_ATTRS", self.parent(), v.ident);
+        let attrs = self.parented(format_ident!("V{}", ix), |me| {
+            me.attrs_field(attrs_name, &v.attrs)
+        });
 
+        self.variant_fields = Some(v.fields.iter().map(|f| &f.ty).collect());
+        self.rename_all = serde_str(&v.attrs, "rename_all");
         let vdata = self.parented(format_ident!("{}", v.ident), |me| {
             me.variant_data(&v.fields)
         });
-        let label = v.ident.to_string();
-        let attrs_name = format_ident!("{}_{}_ATTRS", self.parent(), label);
-
-        self.consts.push(
-            quote! { const #attrs_name : &'static [_reflect::Attr<'static>] = &[#(#attrs),*]; },
-        );
-
-        let attrs = self.list_reference(attrs_name, attrs);
+        self.variant_fields = None;
+        self.rename_all = None;
 
         let (new_disc_base, disc_val) = match &v.discriminant {
             Some((_eq_token, val)) => (Some((ix, val.to_token_stream())), val.to_token_stream()),
@@ -303,54 +446,66 @@ impl<'a> DeriveReflect<'a> {
                 self.most_recent_discriminant_expr.clone(),
                 match &self.most_recent_discriminant_expr {
                     Some((disc_setter_ix, prev_disc)) => {
-                        quote! { #prev_disc + #(#ix - #disc_setter_ix) }
+                        let delta = Literal::usize_unsuffixed(ix - disc_setter_ix);
+                        quote! { (#prev_disc) + #delta }
                     }
-                    None => quote! { #ix },
+                    None => Literal::usize_unsuffixed(ix).into_token_stream(),
                 },
             ),
         };
         self.most_recent_discriminant_expr = new_disc_base;
+        let variant_index = Literal::usize_unsuffixed(ix);
 
         quote! {
             _reflect::EnumArm {
                 label: #label,
-                variant_index: #ix,
+                variant_index: #variant_index,
                 discriminant: #disc_val,
-                attrs: #attrs,
+                #attrs
                 variant: #vdata,
             }
         }
     }
 
-    fn data_shape(&mut self, d: &'a syn::DeriveInput) -> TokenStream {
+    fn item_declaration(&mut self, d: &'a syn::DeriveInput) -> TokenStream {
         match &d.data {
             Data::Struct(DataStruct { fields, .. }) => {
+                self.rename_all = serde_str(&d.attrs, "rename_all");
                 let vdata = self.parented(d.ident.clone(), |me| me.variant_data(fields));
-                quote! { _reflect::DataShape::Struct(#vdata) }
+                quote! { _reflect::ItemDeclaration::Struct(#vdata) }
             }
             Data::Enum(DataEnum { variants, .. }) => {
-                //let variant = variants.iter().map(|v| v.ident.to_string());
+                if !is_repr_u16(&d.attrs) {
+                    return quote_spanned! { d.ident.span() =>
+                        compile_error!("reflected enums must be #[repr(u16)], so that their layout is known")
+                    };
+                }
+                let rule = serde_str(&d.attrs, "rename_all");
                 let (labels, variants): (Vec<_>, Vec<_>) = variants
                     .iter()
                     .enumerate()
                     .map(|(ix, v)| {
-                        let arm = self.parented(d.ident.clone(), |me| me.variant(ix, v));
-                        (v.ident.to_string(), arm)
+                        let label =
+                            serde_label(&v.attrs, &v.ident.to_string(), rule.as_deref(), true);
+                        let arm = self.parented(d.ident.clone(), |me| me.variant(ix, v, &label));
+                        (label, arm)
                     })
                     .unzip();
                 let variants_ident = format_ident!("{}_VARIANTS", d.ident);
                 let field_labels_ident = format_ident!("{}_LABELS", variants_ident);
                 self.consts.push(
-                    quote! { { const #field_labels_ident : &'static [&'static str] = &[#(#labels),*]; #field_labels_ident } },
-                );
-                self.consts.push(
-                    quote! { const #variants_ident : &'static [_reflect::EnumArm<'static>] = &[#(#variants),*]},
+                    quote! { const #variants_ident : &'static [_reflect::EnumArm<'static>] = &[#(#variants),*]; },
                 );
                 let variants = self.list_reference(variants_ident, variants);
 
-                quote! { _reflect::DataShape::Enum{
-                    variant_labels_for_serde: #field_labels_ident,
-                    variants: #variants,
+                // the arms are sorted by discriminant, as `ItemDeclaration::Enum` requires
+                quote! { _reflect::ItemDeclaration::Enum {
+                    variant_labels_for_serde: { #[allow(non_upper_case_globals)] const #field_labels_ident : &'static [&'static str] = &[#(#labels),*]; #field_labels_ident },
+                    variants: {
+                        let mut variants: alloc::borrow::Cow<'static, [_reflect::EnumArm<'static>]> = #variants;
+                        variants.to_mut().sort_by_key(|arm| arm.discriminant);
+                        variants
+                    },
                 } }
             }
             Data::Union(_) => panic!("unions can not be reflected into"),
@@ -358,7 +513,7 @@ impl<'a> DeriveReflect<'a> {
     }
 }
 
-#[proc_macro_derive(Reflect)]
+#[proc_macro_derive(Reflect, attributes(reflect))]
 pub fn derive_reflect(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let ast = syn::parse::<syn::DeriveInput>(input).unwrap();
     let mut derive = DeriveReflect {
@@ -369,58 +524,71 @@ pub fn derive_reflect(input: proc_macro::TokenStream) -> proc_macro::TokenStream
         nightly_const: false,
         generics: &ast.generics,
         seen_types: vec![],
+        variant_fields: None,
+        hook_checks: vec![],
+        rename_all: None,
     };
 
-    let attrs = derive.parented(ast.ident.clone(), |me| me.attrs(&ast.attrs));
-    let shape = derive.data_shape(&ast);
-
-    let leafs = vec![quote! {drop("oh well");}];
+    let attrs = derive.parented(ast.ident.clone(), |me| {
+        me.attrs_field(format_ident!("{}_ATTRS", ast.ident), &ast.attrs)
+    });
+    let typ = derive.item_declaration(&ast);
 
     // ideas for improving codegen:
     // - with a const offset_of, we can implement StaticReflect and not just SelfReflect
     // - how do we figure out when to register leafs?
 
-    let attr_name = format_ident!("{}_ATTRS", ast.ident);
-    derive
-        .consts
-        .push(quote! { const #attr_name : &'static [_reflect::Attr<'static>] = &[#(#attrs),*]; });
-
     // TODO: static_assert that every type either implements SelfReflect or implements Serialize/Deserialize
 
-    let generics = &ast.generics;
+    let (impl_generics, ty_generics, where_clause) = derive.generics.split_for_impl();
+    let where_clause = match where_clause {
+        Some(w) => quote! { #w, Self: 'static },
+        None => quote! { where Self: 'static },
+    };
 
     let me = &ast.ident;
 
+    let hook_checks = derive.hook_checks;
     let consts = if derive.nightly_const {
         derive.consts
     } else {
         Vec::new()
     };
 
+    // generic types have no single type to register
+    let register_global = if ast.generics.params.is_empty() {
+        quote! { _reflect::__register_derived!(#me); }
+    } else {
+        quote! {}
+    };
+
     let impl_block = quote! {
         const _ : () = {
             extern crate serde_reflect as _reflect;
             extern crate core;
-            extern crate memoffset;
             extern crate alloc;
+            use _reflect::memoffset;
 
             #(#consts)*
+            #(#hook_checks)*
 
-            impl #generics _reflect::Reflect for #me #generics where Self: 'static {
+            unsafe impl #impl_generics _reflect::Reflect for #me #ty_generics #where_clause {
+                type Key = Self;
                 fn rust_type() -> _reflect::StaticType {
                     alloc::borrow::Cow::Owned(_reflect::ReflectedType {
                         id: core::any::TypeId::of::<Self>(),
                         name: stringify!(#me),
                         layout: core::alloc::Layout::new::<Self>(),
-                        shape: #shape,
-                        attrs: alloc::borrow::Cow::Owned(vec![#(#attrs),*]),
+                        typ: #typ,
+                        #attrs
                     })
                 }
-                fn register(db: &mut _reflect::Db<'_,'_>) {
+                fn register(db: &mut _reflect::Db<'_>) {
                     db.register_type::<Self>();
-                    #(#leafs);*
                 }
             }
+
+            #register_global
         };
     };
 
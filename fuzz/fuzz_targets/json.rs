@@ -0,0 +1,6 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde_reflect_fuzz::{check, Json};
+
+fuzz_target!(|data: &[u8]| check::<Json>(data));
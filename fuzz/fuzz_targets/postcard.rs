@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde_reflect_fuzz::{check, Compact, Postcard};
+
+// the compact codec reads the same format without going through serde
+fuzz_target!(|data: &[u8]| {
+    check::<Postcard>(data);
+    check::<Compact>(data);
+});
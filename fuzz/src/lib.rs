@@ -0,0 +1,160 @@
+//! Representative reflected types, and the formats the fuzz targets read them from.
+//!
+//! Every input is read as each of the types. Whatever is accepted must serialize again, and
+//! read back as the same value.
+
+use postcard::ser_flavors::{AllocVec, Flavor};
+use serde_reflect::*;
+use std::any::Any;
+use std::fmt::Debug;
+use std::num::NonZeroU64;
+use std::sync::OnceLock;
+
+#[derive(Reflect, Debug, PartialEq)]
+pub struct Point {
+    pub x: i64,
+    pub y: i8,
+}
+
+#[derive(Reflect, Debug, PartialEq)]
+#[repr(u16)]
+pub enum Shape {
+    Empty,
+    Circle { radius: u32 },
+    Line(Point, Point),
+    Label(String),
+}
+
+#[derive(Reflect, Debug, PartialEq)]
+pub struct Id(pub NonZeroU64);
+
+#[derive(Reflect, Debug, PartialEq)]
+pub struct Message {
+    pub id: Id,
+    pub serial: u128,
+    pub name: String,
+    pub note: Option<String>,
+    pub flags: [bool; 3],
+    pub shapes: [Shape; 2],
+    pub pair: (char, u16),
+    pub payload: Vec<u8>,
+    pub at: Point,
+}
+
+fn db() -> &'static FrozenDb<'static> {
+    static DB: OnceLock<FrozenDb<'static>> = OnceLock::new();
+    DB.get_or_init(|| {
+        let mut db = Db::new();
+        Message::register(&mut db);
+        Shape::register(&mut db);
+        Point::register(&mut db);
+        Id::register(&mut db);
+        db.register_serde_leaf::<String>();
+        db.register_serde_leaf::<Option<String>>();
+        db.register_serde_leaf::<Vec<u8>>();
+        db.register_serde_leaf::<NonZeroU64>();
+        db.freeze()
+    })
+}
+
+/// A format to read values from with reflection, and write them back in.
+pub trait Format {
+    /// Read a `T` from `data`, if it holds one.
+    fn read<T: Any>(db: &Db<'_>, data: &[u8]) -> Option<T>;
+
+    /// Write `val`, which came from `read`.
+    fn write<T: Any>(db: &Db<'_>, val: &T) -> Vec<u8>;
+}
+
+pub struct Json;
+
+impl Format for Json {
+    fn read<T: Any>(db: &Db<'_>, data: &[u8]) -> Option<T> {
+        db.deserialize(&mut serde_json::Deserializer::from_slice(data))
+            .ok()
+    }
+
+    fn write<T: Any>(db: &Db<'_>, val: &T) -> Vec<u8> {
+        let mut out = Vec::new();
+        db.serialize(&mut serde_json::Serializer::new(&mut out), val)
+            .expect("accepted JSON doesn't serialize");
+        out
+    }
+}
+
+pub struct Postcard;
+
+impl Format for Postcard {
+    fn read<T: Any>(db: &Db<'_>, data: &[u8]) -> Option<T> {
+        db.deserialize(&mut postcard::Deserializer::from_bytes(data))
+            .ok()
+    }
+
+    fn write<T: Any>(db: &Db<'_>, val: &T) -> Vec<u8> {
+        let mut ser = postcard::Serializer {
+            output: AllocVec::new(),
+        };
+        db.serialize(&mut ser, val)
+            .expect("accepted postcard doesn't serialize");
+        ser.output.finalize().expect("postcard output")
+    }
+}
+
+/// Postcard, read and written by `Db::from_compact` and `Db::to_compact`.
+pub struct Compact;
+
+impl Format for Compact {
+    fn read<T: Any>(db: &Db<'_>, data: &[u8]) -> Option<T> {
+        db.from_compact(data).ok()
+    }
+
+    fn write<T: Any>(db: &Db<'_>, val: &T) -> Vec<u8> {
+        db.to_compact(val)
+            .expect("accepted compact input doesn't serialize")
+    }
+}
+
+/// CBOR with ciborium, which has no public `Deserializer`: input is read into a
+/// `ciborium::value::Value` first, and reflection reads from that.
+pub struct Cbor;
+
+/// Serializes a value with reflection, for ciborium to write.
+struct Reflected<'a, 'db, T>(&'a Db<'db>, &'a T);
+
+impl<T: Any> serde::Serialize for Reflected<'_, '_, T> {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(s, self.1)
+    }
+}
+
+impl Format for Cbor {
+    fn read<T: Any>(db: &Db<'_>, data: &[u8]) -> Option<T> {
+        let cbor: ciborium::value::Value = ciborium::de::from_reader(data).ok()?;
+        db.from_value(serde::Serialize::serialize(&cbor, ValueSerializer).ok()?)
+            .ok()
+    }
+
+    fn write<T: Any>(db: &Db<'_>, val: &T) -> Vec<u8> {
+        let mut out = Vec::new();
+        ciborium::ser::into_writer(&Reflected(db, val), &mut out)
+            .expect("accepted CBOR doesn't serialize");
+        out
+    }
+}
+
+fn round_trip<F: Format, T: Any + PartialEq + Debug>(db: &Db<'_>, data: &[u8]) {
+    if let Some(val) = F::read::<T>(db, data) {
+        let bytes = F::write(db, &val);
+        let again = F::read::<T>(db, &bytes).expect("written value doesn't read back");
+        assert_eq!(again, val);
+    }
+}
+
+/// Read `data` as each of the types in `F`, checking that whatever is accepted round trips.
+pub fn check<F: Format>(data: &[u8]) {
+    let db = db();
+    round_trip::<F, Message>(db, data);
+    round_trip::<F, Shape>(db, data);
+    round_trip::<F, Point>(db, data);
+    round_trip::<F, Id>(db, data);
+}
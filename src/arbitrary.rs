@@ -0,0 +1,190 @@
+use crate::convert::{ValueError, ValueSerializer};
+use crate::metadata::*;
+use crate::place::Place;
+use crate::value::{element_shape, field_label, ReflectValue};
+use crate::Db;
+
+use alloc::{boxed::Box, vec::Vec};
+use core::any::{Any, TypeId};
+use core::fmt::Debug;
+use proptest::prelude::*;
+use proptest::strategy::{SBoxedStrategy, Union};
+
+/// Generates `ReflectValue`s in the form they are written in place.
+pub(crate) type ValueStrategy = SBoxedStrategy<ReflectValue>;
+
+impl<'db> Db<'db> {
+    /// Generate leaves of type `T` with `strategy` in `Db::arbitrary`.
+    ///
+    /// Generated leaves are written with the deserializer registered for `T`, so it must be
+    /// registered with `register_serde_leaf` too.
+    pub fn register_leaf_strategy<T, S>(&mut self, strategy: S) -> &mut Db<'db>
+    where
+        T: serde::Serialize + Debug + 'static,
+        S: Strategy<Value = T> + Send + Sync + 'static,
+    {
+        let values = strategy.prop_map(|val| {
+            serde::Serialize::serialize(&val, ValueSerializer)
+                .expect("leaf doesn't serialize to a value")
+        });
+        self.leaf_strategies
+            .insert(TypeId::of::<T>(), values.sboxed());
+        self
+    }
+
+    /// A proptest strategy for values of `T`, built from its reflected type.
+    ///
+    /// Builtins are generated with their `Arbitrary` impls, enums pick one of their arms, and
+    /// leaves use the strategy registered with `register_leaf_strategy`. Values shrink as
+    /// their parts do.
+    pub fn arbitrary<T: Any + Debug>(&self) -> Result<SBoxedStrategy<T>, ValueError> {
+        let typ = self
+            .known_type(TypeId::of::<T>())
+            .ok_or_else(|| ValueError("type missing from db, cannot generate".into()))?;
+        let values = self.strategy(Place::Item(typ))?;
+        // the strategy outlives this borrow of the database, so it writes values with plans of
+        // its own
+        let plans = self.compile();
+        Ok(values
+            .prop_map(move |val| {
+                plans
+                    .deserialize::<T, _>(val)
+                    .expect("generated value doesn't fit its type")
+            })
+            .sboxed())
+    }
+
+    fn strategy(&self, place: Place<'_, 'db>) -> Result<ValueStrategy, ValueError> {
+        match place.resolve(self) {
+            Place::Item(typ) => {
+                let name = typ.name;
+                match &typ.typ {
+                    ItemDeclaration::Newtype(inner) => Ok(self
+                        .strategy(Place::Item(inner))?
+                        .prop_map(move |val| ReflectValue::Newtype(name, Box::new(val)))
+                        .sboxed()),
+                    ItemDeclaration::Struct(data) => self.data_strategy(name, data),
+                    ItemDeclaration::Enum { variants, .. } => {
+                        if variants.is_empty() {
+                            return Err(ValueError(alloc::format!(
+                                "{} has no variants to generate",
+                                name
+                            )));
+                        }
+                        let arms = variants
+                            .iter()
+                            .map(|arm| {
+                                let (label, variant_index) = (arm.label, arm.variant_index as u32);
+                                let data = self.data_strategy(label, &arm.variant)?;
+                                Ok(data
+                                    .prop_map(move |data| ReflectValue::Variant {
+                                        name,
+                                        label,
+                                        variant_index,
+                                        data: Box::new(match data {
+                                            ReflectValue::UnitStruct(_) => ReflectValue::Unit,
+                                            data => data,
+                                        }),
+                                    })
+                                    .sboxed())
+                            })
+                            .collect::<Result<Vec<_>, ValueError>>()?;
+                        Ok(Union::new(arms).sboxed())
+                    }
+                }
+            }
+            Place::Shape(shape) => match shape {
+                &DataShape::Builtin(builtin) => Ok(builtin_strategy(builtin)),
+                &DataShape::Leaf(id) => self
+                    .lookup(|db| &db.leaf_strategies, &id)
+                    .cloned()
+                    .ok_or_else(|| {
+                        ValueError(alloc::format!(
+                            "no strategy registered for leaf {}",
+                            self.name_of(id)
+                                .unwrap_or_else(|_| alloc::format!("{:?}", id))
+                        ))
+                    }),
+                &DataShape::FixedArray(id, len) => {
+                    let elt = self.strategy(Place::Shape(&element_shape(id)))?;
+                    Ok(proptest::collection::vec(elt, len)
+                        .prop_map(ReflectValue::Tuple)
+                        .sboxed())
+                }
+                DataShape::Tuple(fields) => Ok(self
+                    .strategies(fields.iter().map(|f| (attrs_of!(f), &*f.shape)))?
+                    .prop_map(ReflectValue::Tuple)
+                    .sboxed()),
+                DataShape::Slice(_) | DataShape::Ref(_) => {
+                    Err(ValueError("cannot generate borrowed data".into()))
+                }
+            },
+            Place::Hooked(hook, _) => Err(ValueError(alloc::format!(
+                "cannot generate a field of type {} with a hook",
+                hook.field_name
+            ))),
+        }
+    }
+
+    fn data_strategy(
+        &self,
+        name: &'static str,
+        data: &VariantData<'db>,
+    ) -> Result<ValueStrategy, ValueError> {
+        Ok(match data {
+            VariantData::Unit => Just(ReflectValue::UnitStruct(name)).sboxed(),
+            VariantData::Tuple(fields) if fields.len() == 1 => self
+                .strategy(self.field_place(attrs_of!(fields[0]), &fields[0].shape)?)?
+                .prop_map(move |val| ReflectValue::Newtype(name, Box::new(val)))
+                .sboxed(),
+            VariantData::Tuple(fields) => self
+                .strategies(fields.iter().map(|f| (attrs_of!(f), &*f.shape)))?
+                .prop_map(move |elts| ReflectValue::TupleStruct(name, elts))
+                .sboxed(),
+            VariantData::Fields {
+                labels_for_serde,
+                fields,
+            } => {
+                let labels: Vec<_> = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(ix, f)| field_label(labels_for_serde, ix, f))
+                    .collect();
+                self.strategies(fields.iter().map(|f| (attrs_of!(f), &f.shape)))?
+                    .prop_map(move |vals| {
+                        ReflectValue::Struct(name, labels.iter().copied().zip(vals).collect())
+                    })
+                    .sboxed()
+            }
+        })
+    }
+
+    fn strategies<'a>(
+        &self,
+        fields: impl Iterator<Item = (Option<&'a [Attr]>, &'a DataShape<'db>)>,
+    ) -> Result<Vec<ValueStrategy>, ValueError>
+    where
+        'db: 'a,
+    {
+        fields
+            .map(|(attrs, shape)| self.strategy(self.field_place(attrs, shape)?))
+            .collect()
+    }
+}
+
+fn builtin_strategy(builtin: RustBuiltin) -> ValueStrategy {
+    match builtin {
+        RustBuiltin::U8 => any::<u8>().prop_map(ReflectValue::U8).sboxed(),
+        RustBuiltin::I8 => any::<i8>().prop_map(ReflectValue::I8).sboxed(),
+        RustBuiltin::U16 => any::<u16>().prop_map(ReflectValue::U16).sboxed(),
+        RustBuiltin::I16 => any::<i16>().prop_map(ReflectValue::I16).sboxed(),
+        RustBuiltin::U32 => any::<u32>().prop_map(ReflectValue::U32).sboxed(),
+        RustBuiltin::I32 => any::<i32>().prop_map(ReflectValue::I32).sboxed(),
+        RustBuiltin::U64 => any::<u64>().prop_map(ReflectValue::U64).sboxed(),
+        RustBuiltin::I64 => any::<i64>().prop_map(ReflectValue::I64).sboxed(),
+        RustBuiltin::U128 => any::<u128>().prop_map(ReflectValue::U128).sboxed(),
+        RustBuiltin::I128 => any::<i128>().prop_map(ReflectValue::I128).sboxed(),
+        RustBuiltin::BOOLIN => any::<bool>().prop_map(ReflectValue::Bool).sboxed(),
+        RustBuiltin::CHAR => any::<char>().prop_map(ReflectValue::Char).sboxed(),
+    }
+}
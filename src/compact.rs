@@ -0,0 +1,870 @@
+use crate::convert::ValueError;
+use crate::metadata::*;
+use crate::place::Place;
+use crate::value::element_shape;
+use crate::Db;
+
+use alloc::{collections::BTreeMap, string::ToString, vec::Vec};
+use core::any::{Any, TypeId};
+use serde::de::{DeserializeSeed, IntoDeserializer, Visitor};
+
+// The compact format is postcard's: integers wider than a byte are LEB128 varints, zigzagged
+// if signed; strings and sequences are prefixed with their length; enums with their variant
+// index; and nothing else carries any framing. So values made only of single byte integers are
+// encoded as their bytes in memory, and are copied in one go.
+
+impl<'db> Db<'db> {
+    /// Encode a value in the compact binary format, via reflection.
+    ///
+    /// Reflected types are encoded straight from their metadata. Serde leaves go through their
+    /// `Serialize` impls. The encoding is the same as postcard's for the same data.
+    pub fn to_compact<T: Any>(&self, val: &T) -> Result<Vec<u8>, ValueError> {
+        let typ = self
+            .known_type(TypeId::of::<T>())
+            .ok_or_else(|| ValueError("type missing from db, cannot encode".into()))?;
+        let mut out = Vec::new();
+        // SAFETY: `val` is a `T`, as described by `typ`.
+        unsafe { Codec::new(self).encode_item(typ, val as *const T as *const u8, &mut out)? };
+        Ok(out)
+    }
+
+    /// Decode a value written by `to_compact` or by postcard, via reflection.
+    ///
+    /// It is an error for `bytes` to be longer than the encoded value. If decoding fails, the
+    /// parts of the value decoded so far are dropped again.
+    pub fn from_compact<T: Any>(&self, bytes: &[u8]) -> Result<T, ValueError> {
+        let typ = self
+            .known_type(TypeId::of::<T>())
+            .ok_or_else(|| ValueError("type missing from db, cannot decode".into()))?;
+        let mut uninit = core::mem::MaybeUninit::<T>::uninit();
+        let mut input = bytes;
+        // SAFETY: `uninit` has room for a `T`, as described by `typ`.
+        unsafe { Codec::new(self).decode_item(typ, uninit.as_mut_ptr().cast(), &mut input)? };
+        // SAFETY: decoding initialized all of it.
+        let val = unsafe { uninit.assume_init() };
+        if !input.is_empty() {
+            return Err(ValueError(alloc::format!(
+                "{} bytes left over after decoding",
+                input.len()
+            )));
+        }
+        Ok(val)
+    }
+}
+
+/// Encodes and decodes values of reflected types, working out which are copied in one go
+/// once for each type.
+struct Codec<'a, 'db> {
+    db: &'a Db<'db>,
+    /// The size of each type met so far which is made only of single byte integers.
+    pods: BTreeMap<TypeId, Option<usize>>,
+}
+
+impl<'a, 'db> Codec<'a, 'db> {
+    fn new(db: &'a Db<'db>) -> Self {
+        Codec {
+            db,
+            pods: BTreeMap::new(),
+        }
+    }
+
+    /// The size of `typ`, if its values are encoded as their bytes in memory.
+    fn pod_size(&mut self, typ: &ReflectedType<'db>) -> Option<usize> {
+        let db = self.db;
+        *self
+            .pods
+            .entry(typ.id)
+            .or_insert_with(|| db.pod_item(typ, true).map(|pod| pod.size))
+    }
+
+    /// The size of an element of shape `elt`, if arrays of them are encoded as their bytes in
+    /// memory.
+    fn pod_elements(&mut self, elt: &DataShape<'db>, stride: usize) -> Option<usize> {
+        match elt {
+            DataShape::Builtin(RustBuiltin::U8) | DataShape::Builtin(RustBuiltin::I8) => Some(1),
+            &DataShape::Leaf(id) => {
+                let typ = self.db.leaf_type(id)?;
+                self.pod_size(typ).filter(|&size| size == stride)
+            }
+            _ => None,
+        }
+    }
+
+    /// SAFETY: `ptr` must point to an initialized value described by `typ`.
+    unsafe fn encode_item(
+        &mut self,
+        typ: &ReflectedType<'db>,
+        ptr: *const u8,
+        out: &mut Vec<u8>,
+    ) -> Result<(), ValueError> {
+        if let Some(size) = self.pod_size(typ) {
+            out.extend_from_slice(core::slice::from_raw_parts(ptr, size));
+            return Ok(());
+        }
+        match &typ.typ {
+            ItemDeclaration::Newtype(inner) => self.encode_item(inner, ptr, out),
+            ItemDeclaration::Struct(data) => self.encode_data(data, ptr, out),
+            ItemDeclaration::Enum { variants, .. } => {
+                let arm = crate::active_arm(variants, ptr).map_err(err)?;
+                varint(out, arm.variant_index as u128);
+                self.encode_data(&arm.variant, ptr, out)
+            }
+        }
+    }
+
+    unsafe fn encode_data(
+        &mut self,
+        data: &VariantData<'db>,
+        ptr: *const u8,
+        out: &mut Vec<u8>,
+    ) -> Result<(), ValueError> {
+        match data {
+            VariantData::Unit => Ok(()),
+            VariantData::Tuple(fields) => fields.iter().try_for_each(|f| {
+                self.encode_field(attrs_of!(f), &f.shape, ptr.add(f.offset), out)
+            }),
+            VariantData::Fields { fields, .. } => fields.iter().try_for_each(|f| {
+                self.encode_field(attrs_of!(f), &f.shape, ptr.add(f.offset), out)
+            }),
+        }
+    }
+
+    unsafe fn encode_field(
+        &mut self,
+        attrs: Option<&[Attr]>,
+        shape: &DataShape<'db>,
+        ptr: *const u8,
+        out: &mut Vec<u8>,
+    ) -> Result<(), ValueError> {
+        match self.db.field_hook(attrs, shape)? {
+            Some(hook) => hook.serialize(&mut Encoder(out), ptr),
+            None => self.encode_shape(shape, ptr, out),
+        }
+    }
+
+    unsafe fn encode_shape(
+        &mut self,
+        shape: &DataShape<'db>,
+        ptr: *const u8,
+        out: &mut Vec<u8>,
+    ) -> Result<(), ValueError> {
+        match shape {
+            &DataShape::Builtin(builtin) => {
+                encode_builtin(builtin, ptr, out);
+                Ok(())
+            }
+            &DataShape::Leaf(id) => match self.db.leaf_type(id) {
+                Some(typ) => self.encode_item(typ, ptr, out),
+                // SAFETY: `ptr` points to a leaf of type id `id`.
+                None => unsafe { self.db.serialize_leaf_at(&mut Encoder(out), id, ptr) },
+            },
+            &DataShape::FixedArray(id, len) => {
+                let elt = element_shape(id);
+                let stride = self.db.stride(id).map_err(ValueError)?;
+                if let Some(size) = self.pod_elements(&elt, stride) {
+                    out.extend_from_slice(core::slice::from_raw_parts(ptr, size * len));
+                    return Ok(());
+                }
+                (0..len).try_for_each(|ix| self.encode_shape(&elt, ptr.add(ix * stride), out))
+            }
+            DataShape::Tuple(fields) => fields.iter().try_for_each(|f| {
+                self.encode_field(attrs_of!(f), &f.shape, ptr.add(f.offset), out)
+            }),
+            DataShape::Slice(_) | DataShape::Ref(_) => Err(err("cannot encode borrowed data")),
+        }
+    }
+
+    /// SAFETY: `ptr` must be valid for writes of the type described by `typ`.
+    unsafe fn decode_item(
+        &mut self,
+        typ: &ReflectedType<'db>,
+        ptr: *mut u8,
+        input: &mut &[u8],
+    ) -> Result<(), ValueError> {
+        if let Some(size) = self.pod_size(typ) {
+            ptr.copy_from_nonoverlapping(take(input, size)?.as_ptr(), size);
+            return Ok(());
+        }
+        match &typ.typ {
+            ItemDeclaration::Newtype(inner) => self.decode_item(inner, ptr, input),
+            ItemDeclaration::Struct(data) => self.decode_data(data, ptr, input),
+            ItemDeclaration::Enum { variants, .. } => {
+                let index = read_varint(input, 32)?;
+                let arm = variants
+                    .iter()
+                    .find(|arm| arm.variant_index as u128 == index)
+                    .ok_or_else(|| {
+                        ValueError(alloc::format!("no variant {} in {}", index, typ.name))
+                    })?;
+                ptr.cast::<u16>().write(arm.discriminant);
+                self.decode_data(&arm.variant, ptr, input)
+            }
+        }
+    }
+
+    unsafe fn decode_data(
+        &mut self,
+        data: &VariantData<'db>,
+        ptr: *mut u8,
+        input: &mut &[u8],
+    ) -> Result<(), ValueError> {
+        match data {
+            VariantData::Unit => Ok(()),
+            VariantData::Tuple(fields) => self.decode_fields(
+                fields.iter().map(|f| (attrs_of!(f), &*f.shape, f.offset)),
+                ptr,
+                input,
+            ),
+            VariantData::Fields { fields, .. } => self.decode_fields(
+                fields.iter().map(|f| (attrs_of!(f), &f.shape, f.offset)),
+                ptr,
+                input,
+            ),
+        }
+    }
+
+    /// Decode fields in order, dropping those already decoded if one of them fails.
+    unsafe fn decode_fields<'f>(
+        &mut self,
+        fields: impl Iterator<Item = (Option<&'f [Attr]>, &'f DataShape<'db>, usize)> + Clone,
+        ptr: *mut u8,
+        input: &mut &[u8],
+    ) -> Result<(), ValueError>
+    where
+        'db: 'f,
+    {
+        for (ix, (attrs, shape, offset)) in fields.clone().enumerate() {
+            if let Err(e) = self.decode_field(attrs, shape, ptr.add(offset), input) {
+                for (attrs, shape, offset) in fields.take(ix) {
+                    if let Ok(place) = self.db.field_place(attrs, shape) {
+                        self.db.drop_in_place(place, ptr.add(offset));
+                    }
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    unsafe fn decode_field(
+        &mut self,
+        attrs: Option<&[Attr]>,
+        shape: &DataShape<'db>,
+        ptr: *mut u8,
+        input: &mut &[u8],
+    ) -> Result<(), ValueError> {
+        match self.db.field_hook(attrs, shape)? {
+            Some(hook) => hook.deserialize(&mut Decoder(input), ptr),
+            None => self.decode_shape(shape, ptr, input),
+        }
+    }
+
+    unsafe fn decode_shape(
+        &mut self,
+        shape: &DataShape<'db>,
+        ptr: *mut u8,
+        input: &mut &[u8],
+    ) -> Result<(), ValueError> {
+        match shape {
+            &DataShape::Builtin(builtin) => decode_builtin(builtin, ptr, input),
+            &DataShape::Leaf(id) => match self.db.leaf_type(id) {
+                Some(typ) => self.decode_item(typ, ptr, input),
+                None => {
+                    let mut decoder = Decoder(input);
+                    self.db
+                        .deserialize_leaf_at(&mut decoder, id, ptr)
+                        .map_err(|e| ValueError(e.to_string()))
+                }
+            },
+            &DataShape::FixedArray(id, len) => {
+                let elt = element_shape(id);
+                let stride = self.db.stride(id).map_err(ValueError)?;
+                if let Some(size) = self.pod_elements(&elt, stride) {
+                    let size = size * len;
+                    ptr.copy_from_nonoverlapping(take(input, size)?.as_ptr(), size);
+                    return Ok(());
+                }
+                for ix in 0..len {
+                    if let Err(e) = self.decode_shape(&elt, ptr.add(ix * stride), input) {
+                        for done in 0..ix {
+                            self.db
+                                .drop_in_place(Place::Shape(&elt), ptr.add(done * stride));
+                        }
+                        return Err(e);
+                    }
+                }
+                Ok(())
+            }
+            DataShape::Tuple(fields) => self.decode_fields(
+                fields.iter().map(|f| (attrs_of!(f), &*f.shape, f.offset)),
+                ptr,
+                input,
+            ),
+            DataShape::Slice(_) | DataShape::Ref(_) => Err(err("cannot decode borrowed data")),
+        }
+    }
+}
+
+fn err(msg: &str) -> ValueError {
+    ValueError(msg.to_string())
+}
+
+fn varint(out: &mut Vec<u8>, mut v: u128) {
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn zigzag(v: i128) -> u128 {
+    ((v << 1) ^ (v >> 127)) as u128
+}
+
+fn unzigzag(v: u128) -> i128 {
+    (v >> 1) as i128 ^ -((v & 1) as i128)
+}
+
+fn take<'de>(input: &mut &'de [u8], len: usize) -> Result<&'de [u8], ValueError> {
+    if input.len() < len {
+        return Err(err("unexpected end of input"));
+    }
+    let (taken, rest) = input.split_at(len);
+    *input = rest;
+    Ok(taken)
+}
+
+/// Read a varint holding an unsigned integer of `bits` bits.
+fn read_varint(input: &mut &[u8], bits: u32) -> Result<u128, ValueError> {
+    let mut v = 0u128;
+    for ix in 0..bits.div_ceil(7) {
+        let byte = take(input, 1)?[0];
+        v |= ((byte & 0x7f) as u128) << (7 * ix);
+        if byte & 0x80 == 0 {
+            return if bits == 128 || v >> bits == 0 {
+                Ok(v)
+            } else {
+                Err(err("varint out of range"))
+            };
+        }
+    }
+    Err(err("varint too long"))
+}
+
+fn read_signed(input: &mut &[u8], bits: u32) -> Result<i128, ValueError> {
+    read_varint(input, bits).map(unzigzag)
+}
+
+fn encode_str(out: &mut Vec<u8>, s: &str) {
+    varint(out, s.len() as u128);
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn decode_str<'de>(input: &mut &'de [u8]) -> Result<&'de str, ValueError> {
+    let len = read_varint(input, 64)? as usize;
+    core::str::from_utf8(take(input, len)?).map_err(|_| err("invalid utf-8"))
+}
+
+/// SAFETY: `ptr` must point to an initialized value of the builtin's type.
+unsafe fn encode_builtin(builtin: RustBuiltin, ptr: *const u8, out: &mut Vec<u8>) {
+    match builtin {
+        RustBuiltin::U8 | RustBuiltin::I8 => out.push(ptr.read()),
+        RustBuiltin::U16 => varint(out, ptr.cast::<u16>().read() as u128),
+        RustBuiltin::U32 => varint(out, ptr.cast::<u32>().read() as u128),
+        RustBuiltin::U64 => varint(out, ptr.cast::<u64>().read() as u128),
+        RustBuiltin::U128 => varint(out, ptr.cast::<u128>().read()),
+        RustBuiltin::I16 => varint(out, zigzag(ptr.cast::<i16>().read() as i128)),
+        RustBuiltin::I32 => varint(out, zigzag(ptr.cast::<i32>().read() as i128)),
+        RustBuiltin::I64 => varint(out, zigzag(ptr.cast::<i64>().read() as i128)),
+        RustBuiltin::I128 => varint(out, zigzag(ptr.cast::<i128>().read())),
+        RustBuiltin::BOOLIN => out.push(ptr.cast::<bool>().read() as u8),
+        RustBuiltin::CHAR => {
+            let mut buf = [0; 4];
+            encode_str(out, ptr.cast::<char>().read().encode_utf8(&mut buf))
+        }
+    }
+}
+
+/// SAFETY: `ptr` must be valid for writes of the builtin's type.
+unsafe fn decode_builtin(
+    builtin: RustBuiltin,
+    ptr: *mut u8,
+    input: &mut &[u8],
+) -> Result<(), ValueError> {
+    match builtin {
+        RustBuiltin::U8 | RustBuiltin::I8 => ptr.write(take(input, 1)?[0]),
+        RustBuiltin::U16 => ptr.cast::<u16>().write(read_varint(input, 16)? as u16),
+        RustBuiltin::U32 => ptr.cast::<u32>().write(read_varint(input, 32)? as u32),
+        RustBuiltin::U64 => ptr.cast::<u64>().write(read_varint(input, 64)? as u64),
+        RustBuiltin::U128 => ptr.cast::<u128>().write(read_varint(input, 128)?),
+        RustBuiltin::I16 => ptr.cast::<i16>().write(read_signed(input, 16)? as i16),
+        RustBuiltin::I32 => ptr.cast::<i32>().write(read_signed(input, 32)? as i32),
+        RustBuiltin::I64 => ptr.cast::<i64>().write(read_signed(input, 64)? as i64),
+        RustBuiltin::I128 => ptr.cast::<i128>().write(read_signed(input, 128)?),
+        RustBuiltin::BOOLIN => ptr.cast::<bool>().write(decode_bool(input)?),
+        RustBuiltin::CHAR => ptr.cast::<char>().write(decode_char(input)?),
+    }
+    Ok(())
+}
+
+fn decode_bool(input: &mut &[u8]) -> Result<bool, ValueError> {
+    match take(input, 1)?[0] {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(err("invalid bool")),
+    }
+}
+
+fn decode_char(input: &mut &[u8]) -> Result<char, ValueError> {
+    let mut chars = decode_str(input)?.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(err("expected a single character")),
+    }
+}
+
+/// Encodes serde leaves in the compact format.
+struct Encoder<'a>(&'a mut Vec<u8>);
+
+impl<'a> serde::Serializer for &mut Encoder<'a> {
+    type Ok = ();
+    type Error = ValueError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<(), ValueError> {
+        self.0.push(v as u8);
+        Ok(())
+    }
+    fn serialize_i8(self, v: i8) -> Result<(), ValueError> {
+        self.0.push(v as u8);
+        Ok(())
+    }
+    fn serialize_i16(self, v: i16) -> Result<(), ValueError> {
+        self.serialize_i128(v as i128)
+    }
+    fn serialize_i32(self, v: i32) -> Result<(), ValueError> {
+        self.serialize_i128(v as i128)
+    }
+    fn serialize_i64(self, v: i64) -> Result<(), ValueError> {
+        self.serialize_i128(v as i128)
+    }
+    fn serialize_i128(self, v: i128) -> Result<(), ValueError> {
+        varint(self.0, zigzag(v));
+        Ok(())
+    }
+    fn serialize_u8(self, v: u8) -> Result<(), ValueError> {
+        self.0.push(v);
+        Ok(())
+    }
+    fn serialize_u16(self, v: u16) -> Result<(), ValueError> {
+        self.serialize_u128(v as u128)
+    }
+    fn serialize_u32(self, v: u32) -> Result<(), ValueError> {
+        self.serialize_u128(v as u128)
+    }
+    fn serialize_u64(self, v: u64) -> Result<(), ValueError> {
+        self.serialize_u128(v as u128)
+    }
+    fn serialize_u128(self, v: u128) -> Result<(), ValueError> {
+        varint(self.0, v);
+        Ok(())
+    }
+    fn serialize_f32(self, v: f32) -> Result<(), ValueError> {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+    fn serialize_f64(self, v: f64) -> Result<(), ValueError> {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+    fn serialize_char(self, v: char) -> Result<(), ValueError> {
+        let mut buf = [0; 4];
+        self.serialize_str(v.encode_utf8(&mut buf))
+    }
+    fn serialize_str(self, v: &str) -> Result<(), ValueError> {
+        encode_str(self.0, v);
+        Ok(())
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), ValueError> {
+        varint(self.0, v.len() as u128);
+        self.0.extend_from_slice(v);
+        Ok(())
+    }
+    fn serialize_none(self) -> Result<(), ValueError> {
+        self.0.push(0);
+        Ok(())
+    }
+    fn serialize_some<T: ?Sized + serde::Serialize>(self, v: &T) -> Result<(), ValueError> {
+        self.0.push(1);
+        v.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<(), ValueError> {
+        Ok(())
+    }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), ValueError> {
+        Ok(())
+    }
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+    ) -> Result<(), ValueError> {
+        self.serialize_u32(variant_index)
+    }
+    fn serialize_newtype_struct<T: ?Sized + serde::Serialize>(
+        self,
+        _: &'static str,
+        v: &T,
+    ) -> Result<(), ValueError> {
+        v.serialize(self)
+    }
+    fn serialize_newtype_variant<T: ?Sized + serde::Serialize>(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        v: &T,
+    ) -> Result<(), ValueError> {
+        varint(self.0, variant_index as u128);
+        v.serialize(self)
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self, ValueError> {
+        let len = len.ok_or_else(|| err("sequences must have a known length"))?;
+        varint(self.0, len as u128);
+        Ok(self)
+    }
+    fn serialize_tuple(self, _: usize) -> Result<Self, ValueError> {
+        Ok(self)
+    }
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self, ValueError> {
+        Ok(self)
+    }
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self, ValueError> {
+        varint(self.0, variant_index as u128);
+        Ok(self)
+    }
+    fn serialize_map(self, len: Option<usize>) -> Result<Self, ValueError> {
+        let len = len.ok_or_else(|| err("maps must have a known length"))?;
+        varint(self.0, len as u128);
+        Ok(self)
+    }
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self, ValueError> {
+        Ok(self)
+    }
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self, ValueError> {
+        varint(self.0, variant_index as u128);
+        Ok(self)
+    }
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+macro_rules! encode_elements {
+    ($($trait:ident :: $method:ident),*) => {$(
+        impl serde::ser::$trait for &mut Encoder<'_> {
+            type Ok = ();
+            type Error = ValueError;
+            fn $method<T: ?Sized + serde::Serialize>(&mut self, v: &T) -> Result<(), ValueError> {
+                v.serialize(&mut **self)
+            }
+            fn end(self) -> Result<(), ValueError> {
+                Ok(())
+            }
+        }
+    )*};
+}
+
+encode_elements!(
+    SerializeSeq::serialize_element,
+    SerializeTuple::serialize_element,
+    SerializeTupleStruct::serialize_field,
+    SerializeTupleVariant::serialize_field
+);
+
+impl serde::ser::SerializeMap for &mut Encoder<'_> {
+    type Ok = ();
+    type Error = ValueError;
+    fn serialize_key<T: ?Sized + serde::Serialize>(&mut self, k: &T) -> Result<(), ValueError> {
+        k.serialize(&mut **self)
+    }
+    fn serialize_value<T: ?Sized + serde::Serialize>(&mut self, v: &T) -> Result<(), ValueError> {
+        v.serialize(&mut **self)
+    }
+    fn end(self) -> Result<(), ValueError> {
+        Ok(())
+    }
+}
+
+macro_rules! encode_fields {
+    ($($trait:ident),*) => {$(
+        impl serde::ser::$trait for &mut Encoder<'_> {
+            type Ok = ();
+            type Error = ValueError;
+            fn serialize_field<T: ?Sized + serde::Serialize>(
+                &mut self,
+                _: &'static str,
+                v: &T,
+            ) -> Result<(), ValueError> {
+                v.serialize(&mut **self)
+            }
+            fn end(self) -> Result<(), ValueError> {
+                Ok(())
+            }
+        }
+    )*};
+}
+
+encode_fields!(SerializeStruct, SerializeStructVariant);
+
+/// Decodes serde leaves from the compact format, consuming what they read.
+struct Decoder<'a, 'de>(&'a mut &'de [u8]);
+
+impl<'de> Decoder<'_, 'de> {
+    fn len(&mut self) -> Result<usize, ValueError> {
+        read_varint(self.0, 64).map(|len| len as usize)
+    }
+
+    fn bytes(&mut self) -> Result<&'de [u8], ValueError> {
+        let len = self.len()?;
+        take(self.0, len)
+    }
+}
+
+impl<'de> serde::Deserializer<'de> for &mut Decoder<'_, 'de> {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, ValueError> {
+        Err(err("the compact format is not self-describing"))
+    }
+    fn deserialize_bool<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_bool(decode_bool(self.0)?)
+    }
+    fn deserialize_i8<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_i8(take(self.0, 1)?[0] as i8)
+    }
+    fn deserialize_i16<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_i16(read_signed(self.0, 16)? as i16)
+    }
+    fn deserialize_i32<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_i32(read_signed(self.0, 32)? as i32)
+    }
+    fn deserialize_i64<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_i64(read_signed(self.0, 64)? as i64)
+    }
+    fn deserialize_i128<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_i128(read_signed(self.0, 128)?)
+    }
+    fn deserialize_u8<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_u8(take(self.0, 1)?[0])
+    }
+    fn deserialize_u16<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_u16(read_varint(self.0, 16)? as u16)
+    }
+    fn deserialize_u32<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_u32(read_varint(self.0, 32)? as u32)
+    }
+    fn deserialize_u64<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_u64(read_varint(self.0, 64)? as u64)
+    }
+    fn deserialize_u128<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_u128(read_varint(self.0, 128)?)
+    }
+    fn deserialize_f32<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(take(self.0, 4)?);
+        v.visit_f32(f32::from_le_bytes(buf))
+    }
+    fn deserialize_f64<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(take(self.0, 8)?);
+        v.visit_f64(f64::from_le_bytes(buf))
+    }
+    fn deserialize_char<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_char(decode_char(self.0)?)
+    }
+    fn deserialize_str<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_borrowed_str(decode_str(self.0)?)
+    }
+    fn deserialize_string<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        self.deserialize_str(v)
+    }
+    fn deserialize_bytes<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_borrowed_bytes(self.bytes()?)
+    }
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        self.deserialize_bytes(v)
+    }
+    fn deserialize_option<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        match take(self.0, 1)?[0] {
+            0 => v.visit_none(),
+            1 => v.visit_some(self),
+            _ => Err(err("invalid option tag")),
+        }
+    }
+    fn deserialize_unit<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        v.visit_unit()
+    }
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        v: V,
+    ) -> Result<V::Value, ValueError> {
+        v.visit_unit()
+    }
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        v: V,
+    ) -> Result<V::Value, ValueError> {
+        v.visit_newtype_struct(self)
+    }
+    fn deserialize_seq<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        let len = self.len()?;
+        v.visit_seq(Elements(self, len))
+    }
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, v: V) -> Result<V::Value, ValueError> {
+        v.visit_seq(Elements(self, len))
+    }
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        len: usize,
+        v: V,
+    ) -> Result<V::Value, ValueError> {
+        v.visit_seq(Elements(self, len))
+    }
+    fn deserialize_map<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        let len = self.len()?;
+        v.visit_map(Elements(self, len))
+    }
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        v: V,
+    ) -> Result<V::Value, ValueError> {
+        v.visit_seq(Elements(self, fields.len()))
+    }
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        v: V,
+    ) -> Result<V::Value, ValueError> {
+        v.visit_enum(self)
+    }
+    fn deserialize_identifier<V: Visitor<'de>>(self, v: V) -> Result<V::Value, ValueError> {
+        self.deserialize_u32(v)
+    }
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, ValueError> {
+        Err(err("the compact format cannot skip values"))
+    }
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// The remaining elements of a sequence, or entries of a map.
+struct Elements<'a, 'b, 'de>(&'a mut Decoder<'b, 'de>, usize);
+
+impl<'de> serde::de::SeqAccess<'de> for Elements<'_, '_, 'de> {
+    type Error = ValueError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, ValueError> {
+        if self.1 == 0 {
+            return Ok(None);
+        }
+        self.1 -= 1;
+        seed.deserialize(&mut *self.0).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.1)
+    }
+}
+
+impl<'de> serde::de::MapAccess<'de> for Elements<'_, '_, 'de> {
+    type Error = ValueError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, ValueError> {
+        serde::de::SeqAccess::next_element_seed(self, seed)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, ValueError> {
+        seed.deserialize(&mut *self.0)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.1)
+    }
+}
+
+impl<'de> serde::de::EnumAccess<'de> for &mut Decoder<'_, 'de> {
+    type Error = ValueError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self), ValueError> {
+        let index = read_varint(self.0, 32)? as u32;
+        let val = seed.deserialize(index.into_deserializer())?;
+        Ok((val, self))
+    }
+}
+
+impl<'de> serde::de::VariantAccess<'de> for &mut Decoder<'_, 'de> {
+    type Error = ValueError;
+
+    fn unit_variant(self) -> Result<(), ValueError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, ValueError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, v: V) -> Result<V::Value, ValueError> {
+        v.visit_seq(Elements(self, len))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        v: V,
+    ) -> Result<V::Value, ValueError> {
+        v.visit_seq(Elements(self, fields.len()))
+    }
+}
@@ -0,0 +1,396 @@
+use crate::describe::*;
+use crate::metadata::RustBuiltin;
+
+use alloc::{
+    collections::BTreeSet,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+/// Which way data stops being readable.
+///
+/// A change breaks backward compatibility if the new build can't read data written by the old
+/// one, and forward compatibility if the old build can't read data written by the new one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Backward,
+    Forward,
+    Both,
+}
+
+impl Direction {
+    fn new(backward: bool, forward: bool) -> Option<Direction> {
+        match (backward, forward) {
+            (true, true) => Some(Direction::Both),
+            (true, false) => Some(Direction::Backward),
+            (false, true) => Some(Direction::Forward),
+            (false, false) => None,
+        }
+    }
+
+    pub fn breaks_backward(self) -> bool {
+        self != Direction::Forward
+    }
+
+    pub fn breaks_forward(self) -> bool {
+        self != Direction::Backward
+    }
+}
+
+/// A change between two versions of a type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BreakKind {
+    /// A named field is gone. Old readers will miss it.
+    FieldRemoved(String),
+    /// A named field is new. New readers will miss it in old data, unless it is optional.
+    FieldAdded(String),
+    /// A variant is gone. Old data may hold it.
+    VariantRemoved(String),
+    /// A variant is new. Old readers won't know it.
+    VariantAdded(String),
+    /// An integer changed width or signedness, so some values no longer fit one way.
+    IntegerWidth { before: String, after: String },
+    /// Tuple fields have the same types, in a different order.
+    TupleReordered,
+    /// A tuple or tuple struct has a different number of fields.
+    TupleLength { before: usize, after: usize },
+    /// A fixed-size array has a different length.
+    ArrayLength { before: usize, after: usize },
+    /// Something else was put in place of the old type.
+    TypeChanged { before: String, after: String },
+}
+
+/// A compatibility break found by `check_compatibility`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Break {
+    /// Where in the type the break is, like `shapes[]::Circle.radius`.
+    pub path: String,
+    pub direction: Direction,
+    pub kind: BreakKind,
+}
+
+impl fmt::Display for Break {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "."
+        } else {
+            &self.path
+        };
+        write!(f, "{}: ", path)?;
+        match &self.kind {
+            BreakKind::FieldRemoved(name) => write!(f, "field `{}` removed", name),
+            BreakKind::FieldAdded(name) => write!(f, "field `{}` added", name),
+            BreakKind::VariantRemoved(label) => write!(f, "variant `{}` removed", label),
+            BreakKind::VariantAdded(label) => write!(f, "variant `{}` added", label),
+            BreakKind::IntegerWidth { before, after } => {
+                write!(f, "integer changed from {} to {}", before, after)
+            }
+            BreakKind::TupleReordered => f.write_str("tuple fields reordered"),
+            BreakKind::TupleLength { before, after } => {
+                write!(f, "tuple length changed from {} to {}", before, after)
+            }
+            BreakKind::ArrayLength { before, after } => {
+                write!(f, "array length changed from {} to {}", before, after)
+            }
+            BreakKind::TypeChanged { before, after } => {
+                write!(f, "type changed from {} to {}", before, after)
+            }
+        }?;
+        match self.direction {
+            Direction::Backward => f.write_str(" (breaks backward compatibility)"),
+            Direction::Forward => f.write_str(" (breaks forward compatibility)"),
+            Direction::Both => f.write_str(" (breaks backward and forward compatibility)"),
+        }
+    }
+}
+
+/// Find the changes between two versions of a type that break reading data written by one
+/// version with the other.
+///
+/// `before` and `after` are as produced by `Db::describe`, with the type being checked first.
+/// Fields and variants are matched by their serde labels, so this is about formats which
+/// write those, like JSON. Formats which go by position, like bincode, break on many more
+/// changes, such as any added field.
+pub fn check_compatibility(before: &[TypeDescription], after: &[TypeDescription]) -> Vec<Break> {
+    let mut checker = Checker {
+        before,
+        after,
+        path: String::new(),
+        seen: BTreeSet::new(),
+        breaks: Vec::new(),
+    };
+    if let (Some(old), Some(new)) = (before.first(), after.first()) {
+        checker.item(old, new);
+    }
+    checker.breaks
+}
+
+struct Checker<'a> {
+    before: &'a [TypeDescription],
+    after: &'a [TypeDescription],
+    path: String,
+    /// Pairs of types already compared or being compared.
+    seen: BTreeSet<(&'a str, &'a str)>,
+    breaks: Vec<Break>,
+}
+
+/// What a type name refers to within a schema.
+enum Named<'a> {
+    Builtin(RustBuiltin),
+    Described(&'a TypeDescription),
+    Leaf(&'a str),
+}
+
+fn lookup<'a>(schema: &'a [TypeDescription], name: &'a str) -> Named<'a> {
+    match RustBuiltin::from_name(name) {
+        Some(builtin) => Named::Builtin(builtin),
+        None => match schema.iter().find(|desc| desc.name == name) {
+            Some(desc) => Named::Described(desc),
+            None => Named::Leaf(name),
+        },
+    }
+}
+
+/// Signedness and width of an integer builtin.
+fn int_range(builtin: RustBuiltin) -> Option<(bool, usize)> {
+    match builtin {
+        RustBuiltin::BOOLIN | RustBuiltin::CHAR => None,
+        RustBuiltin::I8
+        | RustBuiltin::I16
+        | RustBuiltin::I32
+        | RustBuiltin::I64
+        | RustBuiltin::I128 => Some((true, builtin.size())),
+        _ => Some((false, builtin.size())),
+    }
+}
+
+/// Whether every value of the integer type `a` is a value of `b`.
+fn fits((a_signed, a_size): (bool, usize), (b_signed, b_size): (bool, usize)) -> bool {
+    match (a_signed, b_signed) {
+        (false, true) => a_size < b_size,
+        (true, false) => false,
+        _ => a_size <= b_size,
+    }
+}
+
+fn labels<'a>(labels: &'a [String], fields: &'a [FieldDescription]) -> Vec<&'a str> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(ix, f)| labels.get(ix).unwrap_or(&f.name).as_str())
+        .collect()
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, direction: Direction, kind: BreakKind) {
+        self.breaks.push(Break {
+            path: self.path.clone(),
+            direction,
+            kind,
+        });
+    }
+
+    fn nested(&mut self, seg: &str, f: impl FnOnce(&mut Self)) {
+        let len = self.path.len();
+        let seg = if len == 0 {
+            seg.trim_start_matches('.')
+        } else {
+            seg
+        };
+        self.path.push_str(seg);
+        f(self);
+        self.path.truncate(len);
+    }
+
+    fn item(&mut self, old: &'a TypeDescription, new: &'a TypeDescription) {
+        if !self.seen.insert((&old.name, &new.name)) {
+            return;
+        }
+        match (&old.item, &new.item) {
+            (ItemDescription::Newtype(old), ItemDescription::Newtype(new)) => self.named(old, new),
+            (ItemDescription::Struct(old), ItemDescription::Struct(new)) => self.data(old, new),
+            (
+                ItemDescription::Enum { variants: old, .. },
+                ItemDescription::Enum { variants: new, .. },
+            ) => {
+                for arm in old {
+                    match new.iter().find(|n| n.label == arm.label) {
+                        Some(n) => self.nested(&alloc::format!("::{}", arm.label), |me| {
+                            me.data(&arm.variant, &n.variant)
+                        }),
+                        None => self.report(
+                            Direction::Backward,
+                            BreakKind::VariantRemoved(arm.label.clone()),
+                        ),
+                    }
+                }
+                for arm in new {
+                    if !old.iter().any(|o| o.label == arm.label) {
+                        self.report(
+                            Direction::Forward,
+                            BreakKind::VariantAdded(arm.label.clone()),
+                        );
+                    }
+                }
+            }
+            _ => self.report(
+                Direction::Both,
+                BreakKind::TypeChanged {
+                    before: old.name.clone(),
+                    after: new.name.clone(),
+                },
+            ),
+        }
+    }
+
+    fn data(&mut self, old: &'a VariantDescription, new: &'a VariantDescription) {
+        match (old, new) {
+            (VariantDescription::Unit, VariantDescription::Unit) => {}
+            (
+                VariantDescription::Fields {
+                    labels: old_labels,
+                    fields: old,
+                },
+                VariantDescription::Fields {
+                    labels: new_labels,
+                    fields: new,
+                },
+            ) => {
+                let old_labels = labels(old_labels, old);
+                let new_labels = labels(new_labels, new);
+                for (label, f) in old_labels.iter().zip(old) {
+                    match new_labels.iter().position(|n| n == label) {
+                        Some(ix) => self.nested(&alloc::format!(".{}", label), |me| {
+                            me.shape(&f.shape, &new[ix].shape)
+                        }),
+                        None => self.report(
+                            Direction::Forward,
+                            BreakKind::FieldRemoved(label.to_string()),
+                        ),
+                    }
+                }
+                for label in &new_labels {
+                    if !old_labels.contains(label) {
+                        self.report(
+                            Direction::Backward,
+                            BreakKind::FieldAdded(label.to_string()),
+                        );
+                    }
+                }
+            }
+            (VariantDescription::Tuple(old), VariantDescription::Tuple(new)) => {
+                self.tuple(old, new)
+            }
+            _ => self.report(
+                Direction::Both,
+                BreakKind::TypeChanged {
+                    before: data_kind(old).into(),
+                    after: data_kind(new).into(),
+                },
+            ),
+        }
+    }
+
+    fn tuple(&mut self, old: &'a [TupleFieldDescription], new: &'a [TupleFieldDescription]) {
+        if old.len() != new.len() {
+            return self.report(
+                Direction::Both,
+                BreakKind::TupleLength {
+                    before: old.len(),
+                    after: new.len(),
+                },
+            );
+        }
+        let differs = old.iter().zip(new).any(|(o, n)| o.shape != n.shape);
+        if differs {
+            let mut unmatched: Vec<_> = new.iter().map(|f| &f.shape).collect();
+            for f in old {
+                if let Some(ix) = unmatched.iter().position(|s| **s == f.shape) {
+                    unmatched.swap_remove(ix);
+                }
+            }
+            if unmatched.is_empty() {
+                return self.report(Direction::Both, BreakKind::TupleReordered);
+            }
+        }
+        for (ix, (o, n)) in old.iter().zip(new).enumerate() {
+            self.nested(&alloc::format!(".{}", ix), |me| {
+                me.shape(&o.shape, &n.shape)
+            });
+        }
+    }
+
+    fn shape(&mut self, old: &'a ShapeDescription, new: &'a ShapeDescription) {
+        match (old, new) {
+            (ShapeDescription::Type(old), ShapeDescription::Type(new)) => self.named(old, new),
+            (ShapeDescription::Array(old, old_len), ShapeDescription::Array(new, new_len)) => {
+                if old_len != new_len {
+                    self.report(
+                        Direction::Both,
+                        BreakKind::ArrayLength {
+                            before: *old_len,
+                            after: *new_len,
+                        },
+                    );
+                }
+                self.nested("[]", |me| me.named(old, new));
+            }
+            (ShapeDescription::Slice(old), ShapeDescription::Slice(new)) => {
+                self.nested("[]", |me| me.shape(old, new))
+            }
+            (ShapeDescription::Ref(old), ShapeDescription::Ref(new)) => self.shape(old, new),
+            (ShapeDescription::Tuple(old), ShapeDescription::Tuple(new)) => self.tuple(old, new),
+            _ => self.report(
+                Direction::Both,
+                BreakKind::TypeChanged {
+                    before: old.to_string(),
+                    after: new.to_string(),
+                },
+            ),
+        }
+    }
+
+    fn named(&mut self, old: &'a str, new: &'a str) {
+        match (lookup(self.before, old), lookup(self.after, new)) {
+            (Named::Builtin(a), Named::Builtin(b)) if a == b => {}
+            (Named::Builtin(a), Named::Builtin(b)) => match (int_range(a), int_range(b)) {
+                (Some(a), Some(b)) => {
+                    if let Some(direction) = Direction::new(!fits(a, b), !fits(b, a)) {
+                        self.report(
+                            direction,
+                            BreakKind::IntegerWidth {
+                                before: old.into(),
+                                after: new.into(),
+                            },
+                        );
+                    }
+                }
+                _ => self.report(
+                    Direction::Both,
+                    BreakKind::TypeChanged {
+                        before: old.into(),
+                        after: new.into(),
+                    },
+                ),
+            },
+            (Named::Described(a), Named::Described(b)) => self.item(a, b),
+            (Named::Leaf(a), Named::Leaf(b)) if a == b => {}
+            _ => self.report(
+                Direction::Both,
+                BreakKind::TypeChanged {
+                    before: old.into(),
+                    after: new.into(),
+                },
+            ),
+        }
+    }
+}
+
+fn data_kind(data: &VariantDescription) -> &'static str {
+    match data {
+        VariantDescription::Unit => "unit",
+        VariantDescription::Fields { .. } => "named fields",
+        VariantDescription::Tuple(_) => "tuple fields",
+    }
+}
@@ -0,0 +1,714 @@
+use crate::value::ReflectValue;
+use crate::{Db, Deserialize, Serialize, TypedLocation, TypedOutputLocation};
+
+use alloc::{borrow::ToOwned, boxed::Box, string::String, string::ToString, vec::Vec};
+use core::any::Any;
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{DeserializeSeed, Error as deError, IntoDeserializer, Visitor};
+use serde::Deserializer as _;
+
+/// Error converting to or from a `ReflectValue`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueError(pub String);
+
+impl core::fmt::Display for ValueError {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fmt.write_str(&self.0)
+    }
+}
+
+impl serde::ser::StdError for ValueError {}
+
+impl serde::ser::Error for ValueError {
+    fn custom<T: core::fmt::Display>(msg: T) -> Self {
+        ValueError(msg.to_string())
+    }
+}
+
+impl serde::de::Error for ValueError {
+    fn custom<T: core::fmt::Display>(msg: T) -> Self {
+        ValueError(msg.to_string())
+    }
+}
+
+impl<'db> Db<'db> {
+    /// Convert a value into a `ReflectValue` tree, via reflection.
+    pub fn to_value<T: Any>(&self, val: &T) -> Result<ReflectValue, ValueError> {
+        self.serialize(ValueSerializer, val)
+    }
+
+    /// Convert a `ReflectValue` tree into a value, via reflection.
+    ///
+    /// The conversion is directed by the reflected type of `T`, so the tree need not match
+    /// it exactly: integers are converted between widths when they fit, strings are parsed
+    /// as numbers or looked up as unit variants, and a bare value can stand in for `Some`.
+    pub fn from_value<T: Any>(&self, val: ReflectValue) -> Result<T, ValueError> {
+        self.deserialize(val)
+    }
+
+    /// Read the value at some location into a `ReflectValue` tree.
+    pub fn read_value(&'db self, src: TypedLocation<'db, '_>) -> Result<ReflectValue, ValueError> {
+        serde::Serialize::serialize(&Serialize(self, src), ValueSerializer)
+    }
+
+    /// Write a `ReflectValue` tree into some location.
+    ///
+    /// # Safety
+    ///
+    /// As for deserializing into a `TypedOutputLocation`: the previous contents of `dst` are
+    /// overwritten without being dropped, and on error they may be partially initialized.
+    pub unsafe fn write_value(
+        &'db self,
+        dst: TypedOutputLocation<'db, '_>,
+        val: ReflectValue,
+    ) -> Result<(), ValueError> {
+        Deserialize(self, dst).deserialize(val)
+    }
+}
+
+/// A `serde::Serializer` which builds a `ReflectValue` tree.
+pub struct ValueSerializer;
+
+impl serde::Serializer for ValueSerializer {
+    type Ok = ReflectValue;
+    type Error = ValueError;
+    type SerializeSeq = SerializeElements;
+    type SerializeTuple = SerializeElements;
+    type SerializeTupleStruct = SerializeElements;
+    type SerializeTupleVariant = SerializeElements;
+    type SerializeMap = SerializeEntries;
+    type SerializeStruct = SerializeFields;
+    type SerializeStructVariant = SerializeFields;
+
+    fn serialize_bool(self, v: bool) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::Bool(v))
+    }
+    fn serialize_i8(self, v: i8) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::I8(v))
+    }
+    fn serialize_i16(self, v: i16) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::I16(v))
+    }
+    fn serialize_i32(self, v: i32) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::I32(v))
+    }
+    fn serialize_i64(self, v: i64) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::I64(v))
+    }
+    fn serialize_i128(self, v: i128) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::I128(v))
+    }
+    fn serialize_u8(self, v: u8) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::U8(v))
+    }
+    fn serialize_u16(self, v: u16) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::U16(v))
+    }
+    fn serialize_u32(self, v: u32) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::U32(v))
+    }
+    fn serialize_u64(self, v: u64) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::U64(v))
+    }
+    fn serialize_u128(self, v: u128) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::U128(v))
+    }
+    fn serialize_f32(self, v: f32) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::F32(v))
+    }
+    fn serialize_f64(self, v: f64) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::F64(v))
+    }
+    fn serialize_char(self, v: char) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::Char(v))
+    }
+    fn serialize_str(self, v: &str) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::Str(v.to_owned()))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::Bytes(v.to_owned()))
+    }
+    fn serialize_none(self) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::Option(None))
+    }
+    fn serialize_some<T: ?Sized + serde::Serialize>(
+        self,
+        value: &T,
+    ) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::Option(Some(Box::new(
+            value.serialize(ValueSerializer)?,
+        ))))
+    }
+    fn serialize_unit(self) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::Unit)
+    }
+    fn serialize_unit_struct(self, name: &'static str) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::UnitStruct(name))
+    }
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        label: &'static str,
+    ) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::Variant {
+            name,
+            label,
+            variant_index,
+            data: Box::new(ReflectValue::Unit),
+        })
+    }
+    fn serialize_newtype_struct<T: ?Sized + serde::Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::Newtype(
+            name,
+            Box::new(value.serialize(ValueSerializer)?),
+        ))
+    }
+    fn serialize_newtype_variant<T: ?Sized + serde::Serialize>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        label: &'static str,
+        value: &T,
+    ) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::Variant {
+            name,
+            label,
+            variant_index,
+            data: Box::new(ReflectValue::Newtype(
+                label,
+                Box::new(value.serialize(ValueSerializer)?),
+            )),
+        })
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeElements, ValueError> {
+        Ok(SerializeElements::new(ElementsOf::Seq, len.unwrap_or(0)))
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SerializeElements, ValueError> {
+        Ok(SerializeElements::new(ElementsOf::Tuple, len))
+    }
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<SerializeElements, ValueError> {
+        Ok(SerializeElements::new(ElementsOf::TupleStruct(name), len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        label: &'static str,
+        len: usize,
+    ) -> Result<SerializeElements, ValueError> {
+        Ok(SerializeElements::new(
+            ElementsOf::TupleVariant(name, variant_index, label),
+            len,
+        ))
+    }
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeEntries, ValueError> {
+        Ok(SerializeEntries {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<SerializeFields, ValueError> {
+        Ok(SerializeFields {
+            name,
+            variant: None,
+            fields: Vec::with_capacity(len),
+        })
+    }
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        label: &'static str,
+        len: usize,
+    ) -> Result<SerializeFields, ValueError> {
+        Ok(SerializeFields {
+            name,
+            variant: Some((variant_index, label)),
+            fields: Vec::with_capacity(len),
+        })
+    }
+}
+
+enum ElementsOf {
+    Seq,
+    Tuple,
+    TupleStruct(&'static str),
+    TupleVariant(&'static str, u32, &'static str),
+}
+
+#[doc(hidden)]
+pub struct SerializeElements {
+    of: ElementsOf,
+    elts: Vec<ReflectValue>,
+}
+
+impl SerializeElements {
+    fn new(of: ElementsOf, len: usize) -> Self {
+        SerializeElements {
+            of,
+            elts: Vec::with_capacity(len),
+        }
+    }
+
+    fn push<T: ?Sized + serde::Serialize>(&mut self, value: &T) -> Result<(), ValueError> {
+        self.elts.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<ReflectValue, ValueError> {
+        Ok(match self.of {
+            ElementsOf::Seq => ReflectValue::Seq(self.elts),
+            ElementsOf::Tuple => ReflectValue::Tuple(self.elts),
+            ElementsOf::TupleStruct(name) => ReflectValue::TupleStruct(name, self.elts),
+            ElementsOf::TupleVariant(name, variant_index, label) => ReflectValue::Variant {
+                name,
+                label,
+                variant_index,
+                data: Box::new(ReflectValue::TupleStruct(label, self.elts)),
+            },
+        })
+    }
+}
+
+impl serde::ser::SerializeSeq for SerializeElements {
+    type Ok = ReflectValue;
+    type Error = ValueError;
+    fn serialize_element<T: ?Sized + serde::Serialize>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), ValueError> {
+        self.push(value)
+    }
+    fn end(self) -> Result<ReflectValue, ValueError> {
+        self.finish()
+    }
+}
+
+impl serde::ser::SerializeTuple for SerializeElements {
+    type Ok = ReflectValue;
+    type Error = ValueError;
+    fn serialize_element<T: ?Sized + serde::Serialize>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), ValueError> {
+        self.push(value)
+    }
+    fn end(self) -> Result<ReflectValue, ValueError> {
+        self.finish()
+    }
+}
+
+impl serde::ser::SerializeTupleStruct for SerializeElements {
+    type Ok = ReflectValue;
+    type Error = ValueError;
+    fn serialize_field<T: ?Sized + serde::Serialize>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), ValueError> {
+        self.push(value)
+    }
+    fn end(self) -> Result<ReflectValue, ValueError> {
+        self.finish()
+    }
+}
+
+impl serde::ser::SerializeTupleVariant for SerializeElements {
+    type Ok = ReflectValue;
+    type Error = ValueError;
+    fn serialize_field<T: ?Sized + serde::Serialize>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), ValueError> {
+        self.push(value)
+    }
+    fn end(self) -> Result<ReflectValue, ValueError> {
+        self.finish()
+    }
+}
+
+#[doc(hidden)]
+pub struct SerializeEntries {
+    entries: Vec<(ReflectValue, ReflectValue)>,
+    key: Option<ReflectValue>,
+}
+
+impl serde::ser::SerializeMap for SerializeEntries {
+    type Ok = ReflectValue;
+    type Error = ValueError;
+    fn serialize_key<T: ?Sized + serde::Serialize>(&mut self, key: &T) -> Result<(), ValueError> {
+        self.key = Some(key.serialize(ValueSerializer)?);
+        Ok(())
+    }
+    fn serialize_value<T: ?Sized + serde::Serialize>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), ValueError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| ValueError("map value serialized before its key".into()))?;
+        self.entries.push((key, value.serialize(ValueSerializer)?));
+        Ok(())
+    }
+    fn end(self) -> Result<ReflectValue, ValueError> {
+        Ok(ReflectValue::Map(self.entries))
+    }
+}
+
+#[doc(hidden)]
+pub struct SerializeFields {
+    name: &'static str,
+    variant: Option<(u32, &'static str)>,
+    fields: Vec<(&'static str, ReflectValue)>,
+}
+
+impl SerializeFields {
+    fn push<T: ?Sized + serde::Serialize>(
+        &mut self,
+        label: &'static str,
+        value: &T,
+    ) -> Result<(), ValueError> {
+        self.fields.push((label, value.serialize(ValueSerializer)?));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<ReflectValue, ValueError> {
+        Ok(match self.variant {
+            None => ReflectValue::Struct(self.name, self.fields),
+            Some((variant_index, label)) => ReflectValue::Variant {
+                name: self.name,
+                label,
+                variant_index,
+                data: Box::new(ReflectValue::Struct(label, self.fields)),
+            },
+        })
+    }
+}
+
+impl serde::ser::SerializeStruct for SerializeFields {
+    type Ok = ReflectValue;
+    type Error = ValueError;
+    fn serialize_field<T: ?Sized + serde::Serialize>(
+        &mut self,
+        label: &'static str,
+        value: &T,
+    ) -> Result<(), ValueError> {
+        self.push(label, value)
+    }
+    fn end(self) -> Result<ReflectValue, ValueError> {
+        self.finish()
+    }
+}
+
+impl serde::ser::SerializeStructVariant for SerializeFields {
+    type Ok = ReflectValue;
+    type Error = ValueError;
+    fn serialize_field<T: ?Sized + serde::Serialize>(
+        &mut self,
+        label: &'static str,
+        value: &T,
+    ) -> Result<(), ValueError> {
+        self.push(label, value)
+    }
+    fn end(self) -> Result<ReflectValue, ValueError> {
+        self.finish()
+    }
+}
+
+impl<'de> IntoDeserializer<'de, ValueError> for ReflectValue {
+    type Deserializer = ReflectValue;
+    fn into_deserializer(self) -> ReflectValue {
+        self
+    }
+}
+
+impl ReflectValue {
+    /// The value as an integer, parsing strings if need be.
+    fn as_int(&self) -> Option<i128> {
+        use core::convert::TryFrom;
+        Some(match *self {
+            ReflectValue::U8(v) => v as i128,
+            ReflectValue::I8(v) => v as i128,
+            ReflectValue::U16(v) => v as i128,
+            ReflectValue::I16(v) => v as i128,
+            ReflectValue::U32(v) => v as i128,
+            ReflectValue::I32(v) => v as i128,
+            ReflectValue::U64(v) => v as i128,
+            ReflectValue::I64(v) => v as i128,
+            ReflectValue::U128(v) => i128::try_from(v).ok()?,
+            ReflectValue::I128(v) => v,
+            ReflectValue::Str(ref s) => s.trim().parse().ok()?,
+            _ => return None,
+        })
+    }
+
+    fn deserialize_integer<'de, V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        match self {
+            ReflectValue::U128(v) => visitor.visit_u128(v),
+            ReflectValue::Str(s) => match s.trim().parse::<u128>() {
+                Ok(v) if v > i128::MAX as u128 => visitor.visit_u128(v),
+                _ => match ReflectValue::Str(s).as_int() {
+                    Some(v) => visit_int(visitor, v),
+                    None => Err(ValueError("expected an integer, found a string".into())),
+                },
+            },
+            other => match other.as_int() {
+                Some(v) => visit_int(visitor, v),
+                None => other.deserialize_any(visitor),
+            },
+        }
+    }
+}
+
+/// Visit an integer using the narrowest visit method that holds it.
+fn visit_int<'de, V: Visitor<'de>>(visitor: V, v: i128) -> Result<V::Value, ValueError> {
+    use core::convert::TryFrom;
+    if let Ok(v) = u64::try_from(v) {
+        visitor.visit_u64(v)
+    } else if let Ok(v) = i64::try_from(v) {
+        visitor.visit_i64(v)
+    } else {
+        visitor.visit_i128(v)
+    }
+}
+
+impl<'de> serde::Deserializer<'de> for ReflectValue {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        match self {
+            ReflectValue::Unit | ReflectValue::UnitStruct(_) => visitor.visit_unit(),
+            ReflectValue::Bool(v) => visitor.visit_bool(v),
+            ReflectValue::Char(v) => visitor.visit_char(v),
+            ReflectValue::U8(v) => visitor.visit_u8(v),
+            ReflectValue::I8(v) => visitor.visit_i8(v),
+            ReflectValue::U16(v) => visitor.visit_u16(v),
+            ReflectValue::I16(v) => visitor.visit_i16(v),
+            ReflectValue::U32(v) => visitor.visit_u32(v),
+            ReflectValue::I32(v) => visitor.visit_i32(v),
+            ReflectValue::U64(v) => visitor.visit_u64(v),
+            ReflectValue::I64(v) => visitor.visit_i64(v),
+            ReflectValue::U128(v) => visitor.visit_u128(v),
+            ReflectValue::I128(v) => visitor.visit_i128(v),
+            ReflectValue::F32(v) => visitor.visit_f32(v),
+            ReflectValue::F64(v) => visitor.visit_f64(v),
+            ReflectValue::Str(v) => visitor.visit_string(v),
+            ReflectValue::Bytes(v) => visitor.visit_byte_buf(v),
+            ReflectValue::Option(None) => visitor.visit_none(),
+            ReflectValue::Option(Some(v)) => visitor.visit_some(*v),
+            ReflectValue::Newtype(_, v) => visitor.visit_newtype_struct(*v),
+            ReflectValue::Seq(elts)
+            | ReflectValue::Tuple(elts)
+            | ReflectValue::TupleStruct(_, elts) => {
+                let mut seq = SeqDeserializer::new(elts.into_iter());
+                let val = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(val)
+            }
+            ReflectValue::Map(entries) => {
+                let mut map = MapDeserializer::new(entries.into_iter());
+                let val = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(val)
+            }
+            ReflectValue::Struct(_, fields) => {
+                let mut map = MapDeserializer::new(fields.into_iter());
+                let val = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(val)
+            }
+            variant @ ReflectValue::Variant { .. } => visitor.visit_enum(EnumValue::from(variant)),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_integer(visitor)
+    }
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        self.deserialize_integer(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        match self {
+            ReflectValue::Option(None) | ReflectValue::Unit => visitor.visit_none(),
+            ReflectValue::Option(Some(v)) => visitor.visit_some(*v),
+            other => visitor.visit_some(other),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        match self {
+            ReflectValue::Newtype(_, v) => visitor.visit_newtype_struct(*v),
+            other => visitor.visit_newtype_struct(other),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        match self {
+            variant @ ReflectValue::Variant { .. } => visitor.visit_enum(EnumValue::from(variant)),
+            label @ ReflectValue::Str(_) => visitor.visit_enum(EnumValue { label, data: None }),
+            index @ ReflectValue::U32(_) | index @ ReflectValue::U64(_) => {
+                visitor.visit_enum(EnumValue {
+                    label: index,
+                    data: None,
+                })
+            }
+            ReflectValue::Map(mut entries) if entries.len() == 1 => {
+                let (label, data) = entries.pop().unwrap();
+                visitor.visit_enum(EnumValue {
+                    label,
+                    data: Some(data),
+                })
+            }
+            _ => Err(ValueError(
+                "expected an enum variant, a variant label or a single-entry map".into(),
+            )),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool f32 f64 char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// An enum variant being deserialized from a `ReflectValue`.
+struct EnumValue {
+    label: ReflectValue,
+    data: Option<ReflectValue>,
+}
+
+impl From<ReflectValue> for EnumValue {
+    fn from(variant: ReflectValue) -> Self {
+        match variant {
+            ReflectValue::Variant { label, data, .. } => EnumValue {
+                label: ReflectValue::Str(label.into()),
+                data: match *data {
+                    ReflectValue::Unit => None,
+                    ReflectValue::Newtype(_, inner) => Some(*inner),
+                    ReflectValue::TupleStruct(_, elts) => Some(ReflectValue::Tuple(elts)),
+                    ReflectValue::Struct(_, fields) => Some(ReflectValue::Struct(label, fields)),
+                    other => Some(other),
+                },
+            },
+            other => EnumValue {
+                label: other,
+                data: None,
+            },
+        }
+    }
+}
+
+impl<'de> serde::de::EnumAccess<'de> for EnumValue {
+    type Error = ValueError;
+    type Variant = VariantValue;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, VariantValue), ValueError>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let ix = seed.deserialize(self.label)?;
+        Ok((ix, VariantValue(self.data)))
+    }
+}
+
+#[doc(hidden)]
+pub struct VariantValue(Option<ReflectValue>);
+
+impl<'de> serde::de::VariantAccess<'de> for VariantValue {
+    type Error = ValueError;
+
+    fn unit_variant(self) -> Result<(), ValueError> {
+        match self.0 {
+            None | Some(ReflectValue::Unit) => Ok(()),
+            Some(_) => Err(ValueError("expected a unit variant".into())),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, ValueError>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.0 {
+            Some(v) => seed.deserialize(v),
+            None => Err(ValueError::invalid_type(
+                serde::de::Unexpected::UnitVariant,
+                &"a newtype variant",
+            )),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        match self.0 {
+            Some(v) => serde::Deserializer::deserialize_any(v, visitor),
+            None => Err(ValueError::invalid_type(
+                serde::de::Unexpected::UnitVariant,
+                &"a tuple variant",
+            )),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        match self.0 {
+            Some(v) => serde::Deserializer::deserialize_any(v, visitor),
+            None => Err(ValueError::invalid_type(
+                serde::de::Unexpected::UnitVariant,
+                &"a struct variant",
+            )),
+        }
+    }
+}
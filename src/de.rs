@@ -1,16 +1,24 @@
 use crate::metadata::*;
+use crate::place::Place;
+use crate::value::{element_shape, field_label, ArmIx};
 use crate::{Db, TypedOutputLocation};
 
-use alloc::{borrow::Cow, string::ToString};
+use alloc::{string::ToString, vec::Vec};
 
 use core::any::{Any, TypeId};
+use core::marker::PhantomData;
 use erased_serde::Error;
-use serde::de::Error as deError;
+use serde::de::{DeserializeSeed, Error as deError, Visitor};
 
 impl<'db> Db<'db> {
     /// Deserialize some value.
     ///
-    /// The reflection database is consulted for the reflection type.
+    /// The reflection database is consulted for the reflection type. If this fails, the parts
+    /// of the value read so far are dropped again.
+    ///
+    /// This walks the reflection metadata as it goes, finding each field by comparing its label
+    /// with the labels of the type in turn. Plans are opt-in: for hot paths, `Db::compile` once
+    /// and use `Plans::deserialize`, which finds labels with perfect hash tables instead.
     pub fn deserialize<'de, T: Any, D>(
         &self,
         src: D,
@@ -18,404 +26,522 @@ impl<'db> Db<'db> {
     where
         D: serde::Deserializer<'de>,
     {
-        use serde::de::DeserializeSeed;
-
         let rust_type = self
-            .known_types
-            .get(&TypeId::of::<T>())
+            .known_type(TypeId::of::<T>())
             .ok_or_else(|| deError::custom("type missing from db, cannot deserialize"))?;
-        let mut uninit = core::mem::MaybeUninit::uninit();
-        Deserialize(
-            self,
-            TypedOutputLocation {
-                ptr: uninit.as_mut_ptr() as *mut u8,
-                typ: rust_type,
-                _data: Default::default(),
-            },
-        )
+        let mut uninit = core::mem::MaybeUninit::<T>::uninit();
+        PlaceSeed {
+            db: self,
+            place: Place::Item(rust_type),
+            ptr: uninit.as_mut_ptr().cast(),
+        }
         .deserialize(src)?;
+        // SAFETY: deserializing into a place initializes all of it, or fails.
         Ok(unsafe { uninit.assume_init() })
     }
 
-    fn deserialize_leaf<'de, D>(
+    /// Deserialize the leaf with type id `id` into `ptr`.
+    ///
+    /// SAFETY: `ptr` must be valid for writes of the leaf type. It is not dropped first.
+    pub(crate) unsafe fn deserialize_leaf_at<'de, D>(
         &self,
         d: D,
-        dst: &TypedOutputLocation<'db, '_>,
+        id: TypeId,
+        ptr: *mut u8,
     ) -> Result<(), Error>
     where
         D: serde::Deserializer<'de>,
     {
-        self.deserialize_trampolines
-            .get(&dst.typ.id)
+        self.lookup(|db| &db.deserialize_trampolines, &id)
             .ok_or_else(|| <Error as deError>::custom("leaf type missing from reflection db"))?(
-            &mut erased_serde::Deserializer::erase(d),
-            dst,
+            &mut <dyn erased_serde::Deserializer>::erase(d),
+            ptr,
         )
         .map_err(|e| <Error as deError>::custom(e.to_string()))
     }
 }
+
 /// Wrapper for deserializing a value via reflection. You're better off using `Db::deserialize`.
-pub struct Deserialize<'db, 'data>(&'db Db<'db>, TypedOutputLocation<'db, 'data>);
+///
+/// If deserialization fails, whatever it had written to the location is dropped again.
+pub struct Deserialize<'db, 'data>(
+    pub(crate) &'db Db<'db>,
+    pub(crate) TypedOutputLocation<'db, 'data>,
+);
 
-impl<'db, 'data, 'de> serde::de::DeserializeSeed<'de> for Deserialize<'db, 'data> {
+impl<'db, 'data, 'de> DeserializeSeed<'de> for Deserialize<'db, 'data> {
     type Value = ();
     fn deserialize<D>(self, src: D) -> Result<(), D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        let db = self.0;
-        let dst = self.1;
-        match &dst.typ.shape {
-            DataShape::Leaf(id) => db
-                .deserialize_leaf(src, &dst)
-                .map_err(|e| serde::de::Error::custom(e.to_string())),
-            DataShape::Tuple(fields) => src.deserialize_tuple(fields.len(), TupleVisitor(db, &dst)),
-            DataShape::Newtype(_) => {
-                src.deserialize_newtype_struct(dst.typ.name, NewtypeVisitor(db, &dst))
-            }
-            DataShape::Struct(VariantData::Unit) => {
-                src.deserialize_unit_struct(dst.typ.name, UnitVisitor(db, &dst))
-            }
-            DataShape::Struct(VariantData::Tuple(fields)) => {
-                src.deserialize_tuple_struct(dst.typ.name, fields.len(), TupleVisitor(db, &dst))
-            }
-            DataShape::Struct(VariantData::Fields {
-                labels_for_serde, ..
-            }) => src.deserialize_struct(dst.typ.name, labels_for_serde, FieldsVisitor(db, &dst)),
-            DataShape::Enum {
-                variant_labels_for_serde,
-                ..
-            } => src.deserialize_enum(
-                dst.typ.name,
-                variant_labels_for_serde,
-                EnumVisitor(db, &dst),
-            ),
-            DataShape::FixedArray(_, len) => src.deserialize_tuple(*len, TupleVisitor(db, &dst)),
+        let Deserialize(db, dst) = self;
+        PlaceSeed {
+            db,
+            place: Place::Item(dst.typ),
+            ptr: dst.ptr,
         }
+        .deserialize(src)
     }
 }
 
-struct TupleVisitor<'db, 'data, 'visitor>(&'db Db<'db>, &'visitor TypedOutputLocation<'db, 'data>);
-impl<'r, 'db, 'de, 'a, 'b> serde::de::Visitor<'de> for TupleVisitor<'_, '_, '_> {
+/// Deserializes into `ptr` a value which `place` describes.
+///
+/// `ptr` is valid for writes of that value. Its contents are not dropped first, and are left
+/// uninitialized again if deserialization fails.
+#[derive(Clone, Copy)]
+struct PlaceSeed<'a, 'db> {
+    db: &'a Db<'db>,
+    place: Place<'a, 'db>,
+    ptr: *mut u8,
+}
+
+/// The places of some fields, with their offsets.
+type Slots<'a, 'db> = Vec<(Place<'a, 'db>, usize)>;
+
+impl<'a, 'db> PlaceSeed<'a, 'db> {
+    fn slot(&self, &(place, offset): &(Place<'a, 'db>, usize)) -> PlaceSeed<'a, 'db> {
+        PlaceSeed {
+            db: self.db,
+            place,
+            // SAFETY: the slot is a field of the value at `ptr`.
+            ptr: unsafe { self.ptr.add(offset) },
+        }
+    }
+
+    fn tuple_slots<E: deError>(&self, fields: &'a [TupleField<'db>]) -> Result<Slots<'a, 'db>, E> {
+        fields
+            .iter()
+            .map(|f| Ok((self.field_place(attrs_of!(f), &f.shape)?, f.offset)))
+            .collect()
+    }
+
+    fn named_slots<E: deError>(&self, fields: &'a [Field<'db>]) -> Result<Slots<'a, 'db>, E> {
+        fields
+            .iter()
+            .map(|f| Ok((self.field_place(attrs_of!(f), &f.shape)?, f.offset)))
+            .collect()
+    }
+
+    fn field_place<E: deError>(
+        &self,
+        attrs: Option<&[Attr]>,
+        shape: &'a DataShape<'db>,
+    ) -> Result<Place<'a, 'db>, E> {
+        self.db.field_place(attrs, shape).map_err(E::custom)
+    }
+
+    /// Drop the fields in `slots`, after another field failed to deserialize.
+    ///
+    /// SAFETY: each of `slots` must have been deserialized into.
+    unsafe fn drop_written<'s>(&self, slots: impl Iterator<Item = &'s (Place<'a, 'db>, usize)>)
+    where
+        'a: 's,
+        'db: 's,
+    {
+        for &(place, offset) in slots {
+            self.db.drop_in_place(place, self.ptr.add(offset));
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for PlaceSeed<'_, '_> {
     type Value = ();
 
-    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    fn deserialize<D>(self, src: D) -> Result<(), D::Error>
     where
-        A: serde::de::SeqAccess<'de>,
+        D: serde::Deserializer<'de>,
     {
-        let TupleVisitor(db, dst) = self;
-        match &dst.typ.shape {
-            DataShape::Leaf(id) => todo!(),
-            &DataShape::FixedArray(type_id, len) => {
-                let typ = db.type_layout(type_id).map_err(deError::custom)?;
-                let stride = typ.layout.pad_to_align().size();
-                for ix in 0..len {
-                    seq.next_element_seed(Deserialize(self.0, unsafe {
-                        // SAFETY: correctness of reflection data
-                        TypedOutputLocation::new(&typ, dst.ptr.add(ix * stride).cast())
-                    }))?;
+        let ptr = self.ptr;
+        // SAFETY: `place` describes the value at `ptr`, which is valid for writes.
+        match self.place.resolve(self.db) {
+            Place::Item(typ) => match &typ.typ {
+                ItemDeclaration::Newtype(inner) => src.deserialize_newtype_struct(
+                    typ.name,
+                    NewtypeVisitor(PlaceSeed {
+                        place: Place::Item(inner),
+                        ..self
+                    }),
+                ),
+                ItemDeclaration::Struct(VariantData::Unit) => {
+                    src.deserialize_unit_struct(typ.name, UnitVisitor)
                 }
-            }
-            DataShape::Tuple(fields) | DataShape::Struct(VariantData::Tuple(fields)) => {
-                for &TupleField {
-                    type_id, offset, ..
-                } in fields.iter()
-                {
-                    seq.next_element_seed(Deserialize(self.0, unsafe {
-                        // SAFETY: correctness of reflection data
-                        TypedOutputLocation::new(
-                            &db.type_layout(type_id).map_err(deError::custom)?,
-                            dst.ptr.add(offset).cast(),
-                        )
-                    }))?;
+                // serde treats single-field tuple structs as newtypes
+                ItemDeclaration::Struct(VariantData::Tuple(fields)) if fields.len() == 1 => {
+                    let slots = self.tuple_slots(fields)?;
+                    src.deserialize_newtype_struct(typ.name, NewtypeVisitor(self.slot(&slots[0])))
                 }
-            }
-            DataShape::Struct(VariantData::Fields { fields, .. }) => {
-                for &Field {
-                    type_id, offset, ..
-                } in fields.iter()
-                {
-                    seq.next_element_seed(Deserialize(self.0, unsafe {
-                        // SAFETY: correctness of reflection data
-                        TypedOutputLocation::new(
-                            &db.type_layout(type_id).map_err(deError::custom)?,
-                            dst.ptr.add(offset).cast(),
-                        )
-                    }))?;
+                ItemDeclaration::Struct(VariantData::Tuple(fields)) => src
+                    .deserialize_tuple_struct(
+                        typ.name,
+                        fields.len(),
+                        TupleVisitor(self, self.tuple_slots(fields)?),
+                    ),
+                ItemDeclaration::Struct(VariantData::Fields {
+                    labels_for_serde,
+                    fields,
+                }) => src.deserialize_struct(
+                    typ.name,
+                    labels_for_serde,
+                    FieldsVisitor {
+                        seed: self,
+                        labels: FieldIx(labels_for_serde, fields),
+                        slots: self.named_slots(fields)?,
+                    },
+                ),
+                ItemDeclaration::Enum {
+                    variant_labels_for_serde,
+                    variants,
+                } => src.deserialize_enum(
+                    typ.name,
+                    variant_labels_for_serde,
+                    EnumVisitor(self, variant_labels_for_serde, variants),
+                ),
+            },
+            Place::Shape(shape) => match shape {
+                &DataShape::Builtin(builtin) => unsafe { deserialize_builtin(src, builtin, ptr) },
+                &DataShape::Leaf(id) => unsafe { self.db.deserialize_leaf_at(src, id, ptr) }
+                    .map_err(|e| D::Error::custom(e.to_string())),
+                &DataShape::FixedArray(id, len) => {
+                    let stride = self.db.stride(id).map_err(D::Error::custom)?;
+                    src.deserialize_tuple(len, ArrayVisitor(self, id, len, stride))
                 }
-            }
-            _ => return Err(deError::custom("unexpected shape when visiting sequence")),
+                DataShape::Tuple(fields) => src
+                    .deserialize_tuple(fields.len(), TupleVisitor(self, self.tuple_slots(fields)?)),
+                DataShape::Slice(_) | DataShape::Ref(_) => {
+                    Err(D::Error::custom("cannot deserialize borrowed data"))
+                }
+            },
+            Place::Hooked(hook, _) => unsafe { hook.deserialize(src, ptr) },
         }
-        Ok(())
     }
+}
 
-    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
-        write!(fmt, "a sequence to fill the fields of {:?}", self.1.typ)
+/// SAFETY: `ptr` must be valid for writes of the builtin's type.
+pub(crate) unsafe fn deserialize_builtin<'de, D: serde::Deserializer<'de>>(
+    d: D,
+    builtin: RustBuiltin,
+    ptr: *mut u8,
+) -> Result<(), D::Error> {
+    use serde::Deserialize;
+    match builtin {
+        RustBuiltin::U8 => ptr.write(u8::deserialize(d)?),
+        RustBuiltin::I8 => ptr.cast::<i8>().write(i8::deserialize(d)?),
+        RustBuiltin::U16 => ptr.cast::<u16>().write(u16::deserialize(d)?),
+        RustBuiltin::I16 => ptr.cast::<i16>().write(i16::deserialize(d)?),
+        RustBuiltin::U32 => ptr.cast::<u32>().write(u32::deserialize(d)?),
+        RustBuiltin::I32 => ptr.cast::<i32>().write(i32::deserialize(d)?),
+        RustBuiltin::U64 => ptr.cast::<u64>().write(u64::deserialize(d)?),
+        RustBuiltin::I64 => ptr.cast::<i64>().write(i64::deserialize(d)?),
+        RustBuiltin::U128 => ptr.cast::<u128>().write(u128::deserialize(d)?),
+        RustBuiltin::I128 => ptr.cast::<i128>().write(i128::deserialize(d)?),
+        RustBuiltin::BOOLIN => ptr.cast::<bool>().write(bool::deserialize(d)?),
+        RustBuiltin::CHAR => ptr.cast::<char>().write(char::deserialize(d)?),
     }
+    Ok(())
 }
 
-struct UnitVisitor<'db, 'data, 'visitor>(&'db Db<'db>, &'visitor TypedOutputLocation<'db, 'data>);
-impl<'de> serde::de::Visitor<'de> for UnitVisitor<'_, '_, '_> {
+struct UnitVisitor;
+impl<'de> serde::de::Visitor<'de> for UnitVisitor {
     type Value = ();
 
-    fn visit_unit<E>(self) -> Result<Self::Value, E>
-    where
-        E: deError,
-    {
-        unsafe {
-            self.1.ptr.cast::<()>().write(());
-        }
+    fn visit_unit<E: deError>(self) -> Result<(), E> {
         Ok(())
     }
 
     fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
-        write!(fmt, "a unit to fill {:?}", self.1.typ)
+        fmt.write_str("a unit struct")
     }
 }
 
-struct FieldIx<'db>(&'static [&'static str], &'db Cow<'db, [Field<'db>]>);
-impl<'de> serde::de::Visitor<'de> for FieldIx<'_> {
-    type Value = usize;
-    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
-    where
-        E: deError,
-    {
-        if (v as usize) < self.1.len() {
-            Ok(v as usize)
-        } else {
-            Err(E::unknown_field(&v.to_string(), self.0))
-        }
-    }
-    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+struct NewtypeVisitor<'a, 'db>(PlaceSeed<'a, 'db>);
+impl<'de> serde::de::Visitor<'de> for NewtypeVisitor<'_, '_> {
+    type Value = ();
+
+    fn visit_newtype_struct<D>(self, d: D) -> Result<(), D::Error>
     where
-        E: deError,
+        D: serde::Deserializer<'de>,
     {
-        // TODO: faster scan
-        for (ix, f) in self.1.iter().enumerate() {
-            if f.name == v {
-                return Ok(ix);
-            }
-        }
-        Err(E::invalid_value(
-            serde::de::Unexpected::Str(v),
-            &"a string that is one of the field labels",
-        ))
+        self.0.deserialize(d)
     }
-    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
     where
-        E: deError,
+        A: serde::de::SeqAccess<'de>,
     {
-        for (ix, f) in self.1.iter().enumerate() {
-            if f.name.as_bytes() == v {
-                return Ok(ix);
-            }
-        }
-        Err(E::unknown_field(
-            core::str::from_utf8(v).unwrap_or("<non-utf8 fieldname>"),
-            self.0,
-        ))
+        seq.next_element_seed(self.0)?
+            .ok_or_else(|| A::Error::invalid_length(0, &self))
     }
+
     fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
-        fmt.write_str("a struct field")
+        fmt.write_str("a newtype struct")
     }
 }
-impl<'de> serde::de::DeserializeSeed<'de> for FieldIx<'_> {
-    type Value = usize;
-    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        deserializer.deserialize_identifier(self)
-    }
-}
-struct VariantIx<'f, 'e, 'a>(&'static [&'static str], &'f Cow<'e, [EnumArm<'a>]>);
-impl<'de> serde::de::Visitor<'de> for VariantIx<'_, '_, '_> {
-    type Value = usize;
-    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+
+struct TupleVisitor<'a, 'db>(PlaceSeed<'a, 'db>, Slots<'a, 'db>);
+impl<'de> serde::de::Visitor<'de> for TupleVisitor<'_, '_> {
+    type Value = ();
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
     where
-        E: deError,
+        A: serde::de::SeqAccess<'de>,
     {
-        if (v as usize) < self.1.len() {
-            Ok(v as usize)
-        } else {
-            Err(E::unknown_variant(&v.to_string(), self.0))
+        let TupleVisitor(seed, slots) = &self;
+        for (ix, slot) in slots.iter().enumerate() {
+            let err = match seq.next_element_seed(seed.slot(slot)) {
+                Ok(Some(())) => continue,
+                Ok(None) => A::Error::invalid_length(ix, &self),
+                Err(e) => e,
+            };
+            // SAFETY: the fields before `ix` were deserialized.
+            unsafe { seed.drop_written(slots[..ix].iter()) };
+            return Err(err);
         }
+        Ok(())
     }
-    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
-    where
-        E: deError,
-    {
-        // TODO: faster scan
-        for (ix, f) in self.1.iter().enumerate() {
-            if f.label == v {
-                return Ok(ix);
-            }
-        }
-        Err(E::unknown_variant(v, self.0))
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(fmt, "a sequence of {} elements", self.1.len())
     }
-    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+}
+
+struct ArrayVisitor<'a, 'db>(PlaceSeed<'a, 'db>, TypeId, usize, usize);
+impl<'de> serde::de::Visitor<'de> for ArrayVisitor<'_, '_> {
+    type Value = ();
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
     where
-        E: deError,
+        A: serde::de::SeqAccess<'de>,
     {
-        for (ix, f) in self.1.iter().enumerate() {
-            if f.label.as_bytes() == v {
-                return Ok(ix);
+        let ArrayVisitor(seed, id, len, stride) = self;
+        let elt = element_shape(id);
+        let at = |ix: usize| PlaceSeed {
+            db: seed.db,
+            place: Place::Shape(&elt),
+            // SAFETY: the array has `len` elements, `stride` apart.
+            ptr: unsafe { seed.ptr.add(ix * stride) },
+        };
+        for ix in 0..len {
+            let err = match seq.next_element_seed(at(ix)) {
+                Ok(Some(())) => continue,
+                Ok(None) => A::Error::invalid_length(ix, &self),
+                Err(e) => e,
+            };
+            for done in 0..ix {
+                // SAFETY: the elements before `ix` were deserialized.
+                unsafe { seed.db.drop_in_place(Place::Shape(&elt), at(done).ptr) };
             }
+            return Err(err);
         }
-        Err(E::unknown_field(
-            core::str::from_utf8(v).unwrap_or("<non-utf8 fieldname>"),
-            self.0,
-        ))
+        Ok(())
     }
+
     fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
-        fmt.write_str("a struct field")
+        write!(fmt, "an array of length {}", self.2)
     }
 }
-impl<'de> serde::de::DeserializeSeed<'de> for VariantIx<'_, '_, '_> {
-    type Value = usize;
-    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        deserializer.deserialize_identifier(self)
-    }
+
+struct FieldsVisitor<'a, 'db> {
+    seed: PlaceSeed<'a, 'db>,
+    labels: FieldIx<'a, 'db>,
+    slots: Slots<'a, 'db>,
 }
-struct FieldsVisitor<'db, 'data, 'visitor>(&'db Db<'db>, &'visitor TypedOutputLocation<'db, 'data>);
-impl<'de> serde::de::Visitor<'de> for FieldsVisitor<'_, '_, '_> {
+
+impl<'de> serde::de::Visitor<'de> for FieldsVisitor<'_, '_> {
     type Value = ();
 
-    fn visit_map<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
     where
         A: serde::de::MapAccess<'de>,
     {
-        let FieldsVisitor(db, dst) = self;
-        match &dst.typ.shape {
-            DataShape::Struct(VariantData::Fields {
-                fields,
-                labels_for_serde,
-            }) => {
-                while let Some(ix) = seq.next_key_seed(FieldIx(labels_for_serde, fields))? {
-                    seq.next_value_seed(Deserialize(
-                        self.0,
-                        // SAFETY: correctness of reflection data
-                        unsafe {
-                            TypedOutputLocation::new(
-                                &db.type_layout(fields[ix].type_id)
-                                    .map_err(deError::custom)?,
-                                dst.ptr.add(fields[ix].offset).cast(),
-                            )
-                        },
-                    ))?;
+        let FieldsVisitor {
+            seed,
+            labels,
+            slots,
+        } = &self;
+        let mut seen = Seen::new(slots.len());
+        let res = (|| {
+            while let Some(ix) = map.next_key_seed(*labels)? {
+                if ix == slots.len() {
+                    map.next_value::<serde::de::IgnoredAny>()?;
+                    continue;
+                }
+                if seen.contains(ix) {
+                    return Err(A::Error::duplicate_field(labels.label(ix)));
                 }
+                map.next_value_seed(seed.slot(&slots[ix]))?;
+                seen.insert(ix);
             }
-            _ => return Err(deError::custom("unexpected shape when visiting sequence")),
+            for (ix, slot) in slots.iter().enumerate() {
+                if !seen.contains(ix) {
+                    seed.slot(slot)
+                        .deserialize(MissingField(labels.label(ix), PhantomData))?;
+                    seen.insert(ix);
+                }
+            }
+            Ok(())
+        })();
+        if res.is_err() {
+            let written = (0..slots.len()).filter(|&ix| seen.contains(ix));
+            // SAFETY: the fields seen were deserialized.
+            unsafe { seed.drop_written(written.map(|ix| &slots[ix])) };
         }
-        Ok(())
+        res
     }
 
-    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    fn visit_seq<A>(self, seq: A) -> Result<(), A::Error>
     where
         A: serde::de::SeqAccess<'de>,
     {
-        TupleVisitor(self.0, self.1).visit_seq(seq)
+        TupleVisitor(self.seed, self.slots).visit_seq(seq)
     }
 
     fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
-        write!(fmt, "a sequence to fill the fields of {:?}", self.1.typ)
+        fmt.write_str("a struct")
     }
 }
 
-struct EnumVisitor<'db, 'data, 'visitor>(&'db Db<'db>, &'visitor TypedOutputLocation<'db, 'data>);
-impl<'de> serde::de::Visitor<'de> for EnumVisitor<'_, '_, '_> {
-    type Value = ();
-    fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
-    where
-        A: serde::de::EnumAccess<'de>,
-    {
-        use serde::de::VariantAccess;
-        match &self.1.typ.shape {
-            DataShape::Enum {
-                variant_labels_for_serde,
-                variants,
-            } => match data.variant_seed(VariantIx(variant_labels_for_serde, variants)) {
-                Ok((ix, variant)) => {
-                    let arm = &variants[ix];
-                    // SAFETY: TypedOutputLocation contract upheld, correctness of metadata.
-                    unsafe {
-                        self.1.write_discriminant(arm.discriminant);
-                    }
-                    match &arm.variant {
-                        VariantData::Unit => variant.unit_variant(),
-                        VariantData::Fields {
-                            labels_for_serde, ..
-                        } => {
-                            variant.struct_variant(labels_for_serde, FieldsVisitor(self.0, self.1))
-                        }
-                        VariantData::Tuple(fields) => {
-                            // SAFETY: we change the runtime type here so that the tuple visitor code isn't duplicated, but
-                            // now that we've verified the discriminant, the field metadata is accurate when viewed as
-                            // a struct.
-                            unsafe {
-                                variant.tuple_variant(
-                                    fields.len(),
-                                    TupleVisitor(
-                                        self.0,
-                                        &TypedOutputLocation::new(
-                                            &Cow::Borrowed(&ReflectedType {
-                                                typ: DataShape::Tuple(fields.clone()),
-                                                ..self.1.typ.clone()
-                                            }),
-                                            self.1.ptr,
-                                        ),
-                                    ),
-                                )
-                            }
-                        }
-                    }
-                }
-                Err(e) => Err(e),
-            },
-            _shape => Err(deError::custom(
-                "visited an enum when not expecting an enum",
-            )),
+/// Which fields of a struct have been read, without allocating for the first 64.
+pub(crate) struct Seen {
+    small: u64,
+    large: Vec<bool>,
+}
+
+impl Seen {
+    pub(crate) fn new(len: usize) -> Seen {
+        Seen {
+            small: 0,
+            large: alloc::vec![false; len.saturating_sub(64)],
         }
     }
 
+    /// Mark field `ix` as read.
+    pub(crate) fn insert(&mut self, ix: usize) {
+        if ix < 64 {
+            self.small |= 1 << ix;
+        } else {
+            self.large[ix - 64] = true;
+        }
+    }
+
+    pub(crate) fn contains(&self, ix: usize) -> bool {
+        if ix < 64 {
+            self.small & (1 << ix) != 0
+        } else {
+            self.large[ix - 64]
+        }
+    }
+}
+
+/// Stands in for a field missing from its struct. As with serde_derive, an `Option` reads it as
+/// `None`, and anything else fails with `missing_field`.
+pub(crate) struct MissingField<E>(pub(crate) &'static str, pub(crate) PhantomData<E>);
+
+impl<'de, E: deError> serde::Deserializer<'de> for MissingField<E> {
+    type Error = E;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, E> {
+        Err(E::missing_field(self.0))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, E> {
+        visitor.visit_none()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        unit unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier
+        ignored_any
+    }
+}
+
+/// Finds a field by label or index. Unknown labels are skipped, as with serde_derive, so they are
+/// given index `fields.len()`, past every field; an index past the last field is an error.
+#[derive(Clone, Copy)]
+struct FieldIx<'a, 'db>(&'static [&'static str], &'a [Field<'db>]);
+
+impl FieldIx<'_, '_> {
+    fn label(&self, ix: usize) -> &'static str {
+        field_label(self.0, ix, &self.1[ix])
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for FieldIx<'_, '_> {
+    type Value = usize;
+
+    fn visit_u64<E: deError>(self, v: u64) -> Result<usize, E> {
+        if (v as usize) < self.1.len() {
+            Ok(v as usize)
+        } else {
+            Err(E::unknown_field(&v.to_string(), self.0))
+        }
+    }
+
+    fn visit_str<E: deError>(self, v: &str) -> Result<usize, E> {
+        self.visit_bytes(v.as_bytes())
+    }
+
+    fn visit_bytes<E: deError>(self, v: &[u8]) -> Result<usize, E> {
+        Ok((0..self.1.len())
+            .find(|&ix| self.label(ix).as_bytes() == v)
+            .unwrap_or(self.1.len()))
+    }
+
     fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
-        fmt.write_str("an enum")
+        fmt.write_str("a field label")
     }
 }
 
-struct NewtypeVisitor<'db, 'data, 'visitor>(
-    &'db Db<'db>,
-    &'visitor TypedOutputLocation<'db, 'data>,
-);
+impl<'de> DeserializeSeed<'de> for FieldIx<'_, '_> {
+    type Value = usize;
 
-impl<'de> serde::de::Visitor<'de> for NewtypeVisitor<'_, '_, '_> {
+    fn deserialize<D: serde::Deserializer<'de>>(self, d: D) -> Result<usize, D::Error> {
+        d.deserialize_identifier(self)
+    }
+}
+
+struct EnumVisitor<'a, 'db>(
+    PlaceSeed<'a, 'db>,
+    &'static [&'static str],
+    &'a [EnumArm<'db>],
+);
+impl<'de> serde::de::Visitor<'de> for EnumVisitor<'_, '_> {
     type Value = ();
 
-    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    fn visit_enum<A>(self, data: A) -> Result<(), A::Error>
     where
-        D: serde::Deserializer<'de>,
+        A: serde::de::EnumAccess<'de>,
     {
-        use serde::de::DeserializeSeed;
-        match self.1.typ.shape {
-            DataShape::Newtype(id) => Deserialize(
-                self.0,
-                // SAFETY: TypedOutputLocation contract
-                unsafe {
-                    TypedOutputLocation::new(
-                        &self.0.type_layout(id).map_err(deError::custom)?,
-                        self.1.ptr,
-                    )
+        use serde::de::VariantAccess;
+        let EnumVisitor(seed, labels, variants) = self;
+        let (ix, variant) = data.variant_seed(ArmIx(labels, variants))?;
+        let arm = &variants[ix];
+        // SAFETY: `ptr` is a `#[repr(u16)]` enum, with its discriminant first.
+        unsafe { seed.ptr.cast::<u16>().write(arm.discriminant) };
+        match &arm.variant {
+            VariantData::Unit => variant.unit_variant(),
+            VariantData::Tuple(fields) if fields.len() == 1 => {
+                let slots = seed.tuple_slots(fields)?;
+                variant.newtype_variant_seed(seed.slot(&slots[0]))
+            }
+            VariantData::Tuple(fields) => {
+                variant.tuple_variant(fields.len(), TupleVisitor(seed, seed.tuple_slots(fields)?))
+            }
+            VariantData::Fields {
+                labels_for_serde,
+                fields,
+            } => variant.struct_variant(
+                labels_for_serde,
+                FieldsVisitor {
+                    seed,
+                    labels: FieldIx(labels_for_serde, fields),
+                    slots: seed.named_slots(fields)?,
                 },
-            )
-            .deserialize(deserializer),
-
-            _ => Err(deError::custom("visit and shape disagree")),
+            ),
         }
     }
+
     fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
-        fmt.write_str("newtype struct")
+        fmt.write_str("an enum")
     }
 }
@@ -0,0 +1,61 @@
+use crate::metadata::ReflectedType;
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::any::TypeId;
+
+/// Declarations generated for reflected types, each under a name of its own, in the order the
+/// types were first reached.
+pub(crate) struct Decls<T> {
+    /// Where the declaration of each type reached so far is in `decls`.
+    slots: BTreeMap<TypeId, usize>,
+    taken: BTreeSet<String>,
+    /// A declaration is `None` while it is being built.
+    decls: Vec<(String, Option<T>)>,
+}
+
+impl<T> Decls<T> {
+    pub(crate) fn new() -> Self {
+        Decls {
+            slots: BTreeMap::new(),
+            taken: BTreeSet::new(),
+            decls: Vec::new(),
+        }
+    }
+
+    /// The name given to the type with id `id`, if it has been reached.
+    pub(crate) fn name(&self, id: TypeId) -> Option<String> {
+        self.slots.get(&id).map(|&slot| self.decls[slot].0.clone())
+    }
+
+    /// Give `typ` a name, numbered if another type already has its own, and a place for the
+    /// declaration `define` fills in later.
+    pub(crate) fn reserve(&mut self, typ: &ReflectedType<'_>) -> String {
+        let mut name = typ.name.to_string();
+        let mut n = 1;
+        while self.taken.contains(&name) {
+            n += 1;
+            name = alloc::format!("{}{}", typ.name, n);
+        }
+        self.taken.insert(name.clone());
+        self.slots.insert(typ.id, self.decls.len());
+        self.decls.push((name.clone(), None));
+        name
+    }
+
+    /// Fill in the declaration of the type with id `id`, which must have been reserved.
+    pub(crate) fn define(&mut self, id: TypeId, decl: T) {
+        let slot = self.slots[&id];
+        self.decls[slot].1 = Some(decl);
+    }
+
+    /// Every declaration, with its name, in the order the types were reached.
+    pub(crate) fn into_iter(self) -> impl Iterator<Item = (String, T)> {
+        self.decls
+            .into_iter()
+            .filter_map(|(name, decl)| Some((name, decl?)))
+    }
+}
@@ -0,0 +1,779 @@
+use crate::convert::ValueError;
+use crate::metadata::*;
+use crate::place::Place;
+use crate::value::element_shape;
+use crate::Db;
+
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec::Vec,
+};
+use core::alloc::Layout;
+use core::any::{Any, TypeId};
+
+/// A portable description of a reflected type.
+///
+/// Unlike `ReflectedType`, this owns its strings and refers to other types by name rather
+/// than `TypeId`, so it can be serialized in one process and loaded into the `Db` of another
+/// with `Db::insert_described`. Builtins are named as in Rust, reflected types by their
+/// `ReflectedType::name`, and leaves by `core::any::type_name`. Descriptions are `Serialize`
+/// and `Deserialize` with the `descriptions` feature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "descriptions", derive(serde::Serialize, serde::Deserialize))]
+pub struct TypeDescription {
+    pub name: String,
+    pub size: usize,
+    pub align: usize,
+    pub item: ItemDescription,
+}
+
+/// Portable form of `ItemDeclaration`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "descriptions", derive(serde::Serialize, serde::Deserialize))]
+pub enum ItemDescription {
+    /// Name of the wrapped type.
+    Newtype(String),
+    Struct(VariantDescription),
+    Enum {
+        labels: Vec<String>,
+        variants: Vec<ArmDescription>,
+    },
+}
+
+/// Portable form of `VariantData`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "descriptions", derive(serde::Serialize, serde::Deserialize))]
+pub enum VariantDescription {
+    Unit,
+    Fields {
+        labels: Vec<String>,
+        fields: Vec<FieldDescription>,
+    },
+    Tuple(Vec<TupleFieldDescription>),
+}
+
+/// Portable form of `Field`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "descriptions", derive(serde::Serialize, serde::Deserialize))]
+pub struct FieldDescription {
+    pub name: String,
+    pub offset: usize,
+    pub shape: ShapeDescription,
+}
+
+/// Portable form of `TupleField`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "descriptions", derive(serde::Serialize, serde::Deserialize))]
+pub struct TupleFieldDescription {
+    pub offset: usize,
+    pub shape: ShapeDescription,
+}
+
+/// Portable form of `EnumArm`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "descriptions", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArmDescription {
+    pub label: String,
+    pub variant_index: u16,
+    pub discriminant: u16,
+    pub variant: VariantDescription,
+}
+
+/// Portable form of `DataShape`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "descriptions", derive(serde::Serialize, serde::Deserialize))]
+pub enum ShapeDescription {
+    /// A builtin, reflected type or leaf, by name.
+    Type(String),
+    /// `[T; n]`, with `T` by name.
+    Array(String, usize),
+    Slice(Box<ShapeDescription>),
+    Ref(Box<ShapeDescription>),
+    Tuple(Vec<TupleFieldDescription>),
+}
+
+impl<'db> Db<'db> {
+    /// Describe `T` and every reflected type it refers to, `T` first.
+    ///
+    /// Leaves are described by name only, so the process loading the descriptions needs its
+    /// own way of handling them, typically registering the same leaves.
+    pub fn describe<T: Any>(&self) -> Result<Vec<TypeDescription>, ValueError> {
+        let typ = self
+            .known_type(TypeId::of::<T>())
+            .ok_or_else(|| ValueError("type missing from db, cannot describe".into()))?;
+        let mut seen = BTreeMap::new();
+        let mut todo = alloc::vec![typ];
+        let mut out = Vec::new();
+        while let Some(typ) = todo.pop() {
+            match seen.get(typ.name) {
+                Some(id) if *id == typ.id => continue,
+                Some(_) => {
+                    return Err(ValueError(alloc::format!(
+                        "two different types are called {}",
+                        typ.name
+                    )))
+                }
+                None => {
+                    seen.insert(typ.name, typ.id);
+                }
+            }
+            out.push(self.describe_type(typ, &mut todo)?);
+        }
+        Ok(out)
+    }
+
+    /// Insert the reflected types described in `schema`, as produced by `describe`.
+    ///
+    /// Each type needs a `TypeId` to be inserted under and referred to by. `resolve` is asked
+    /// for the id of every name in `schema`, and names it doesn't know are looked up among the
+    /// types and leaves already in this `Db`. Types already registered are never replaced, and
+    /// newtypes are loaded as tuple structs of one field, which serialize the same way.
+    ///
+    /// The layouts in `schema` are checked to hold their fields without overlap, and types
+    /// which would contain themselves are rejected. The inserted types own their data, except
+    /// for names and labels, which this `Db` owns.
+    ///
+    /// # Safety
+    ///
+    /// Nothing can check `schema` against the Rust types whose ids `resolve` returns, so each of
+    /// those types must either be described by `schema` exactly, or never have values: an id
+    /// of a marker type may only be used to handle `ReflectValue`s, as with
+    /// `Db::deserialize_value`, and never with APIs that take a `&T`, like `Db::serialize`.
+    ///
+    /// The names and labels of the inserted types are freed with this `Db`, so they must not
+    /// be kept past it, even though they are `&'static str`s.
+    pub unsafe fn insert_described(
+        &mut self,
+        schema: &[TypeDescription],
+        mut resolve: impl FnMut(&str) -> Option<TypeId>,
+    ) -> Result<(), ValueError> {
+        let mut described = BTreeSet::new();
+        let mut names = Vec::new();
+        for desc in schema {
+            if RustBuiltin::from_name(&desc.name).is_some() {
+                return Err(ValueError(alloc::format!(
+                    "`{}` is a builtin and can't be described",
+                    desc.name
+                )));
+            }
+            if !described.insert(desc.name.as_str()) {
+                return Err(ValueError(alloc::format!(
+                    "`{}` is described twice",
+                    desc.name
+                )));
+            }
+            names.push(desc.name.as_str());
+            desc.item.names(&mut names);
+        }
+        let mut ids = BTreeMap::new();
+        for name in names {
+            if ids.contains_key(name) || RustBuiltin::from_name(name).is_some() {
+                continue;
+            }
+            let id = resolve(name)
+                .or_else(|| self.type_id_named(name))
+                .ok_or_else(|| ValueError(alloc::format!("no type id for `{}`", name)))?;
+            ids.insert(name, id);
+        }
+        let mut strings = Strings::default();
+        let mut loaded = BTreeMap::new();
+        for desc in schema {
+            let id = ids[desc.name.as_str()];
+            if loaded.contains_key(&id)
+                || self.known_type(id).is_some()
+                || self.lookup(|db| &db.serialize_objects, &id).is_some()
+            {
+                return Err(ValueError(alloc::format!(
+                    "`{}` is already registered",
+                    desc.name
+                )));
+            }
+            loaded.insert(id, self.load(desc, &ids, &mut strings)?);
+        }
+        for typ in loaded.values() {
+            self.check_layout(&loaded, typ)?;
+        }
+        let mut finite = BTreeSet::new();
+        for &id in loaded.keys() {
+            self.check_finite(&loaded, id, &mut Vec::new(), &mut finite)?;
+        }
+        for (id, typ) in loaded {
+            self.insert(id, Cow::Owned(typ));
+        }
+        self.strings.append(strings);
+        Ok(())
+    }
+
+    fn type_id_named(&self, name: &str) -> Option<TypeId> {
+        self.known_types
+            .iter()
+            .find(|(_, typ)| typ.name == name)
+            .map(|(id, _)| *id)
+            .or_else(|| {
+                self.leaf_names
+                    .iter()
+                    .find(|(_, leaf)| **leaf == name)
+                    .map(|(id, _)| *id)
+            })
+            .or_else(|| self.base()?.type_id_named(name))
+    }
+
+    pub(crate) fn name_of(&self, id: TypeId) -> Result<String, ValueError> {
+        if let Some(builtin) = RustBuiltin::of(id) {
+            return Ok(builtin.name().into());
+        }
+        self.known_type(id)
+            .map(|typ| typ.name)
+            .or_else(|| self.lookup(|db| &db.leaf_names, &id).copied())
+            .map(Into::into)
+            .ok_or_else(|| ValueError(alloc::format!("no name for typeid {:?}", id)))
+    }
+
+    /// Describe `typ`, adding the reflected types it refers to onto `todo`.
+    fn describe_type<'a>(
+        &'a self,
+        typ: &'a ReflectedType<'db>,
+        todo: &mut Vec<&'a ReflectedType<'db>>,
+    ) -> Result<TypeDescription, ValueError> {
+        let item = match &typ.typ {
+            ItemDeclaration::Newtype(inner) => {
+                todo.push(inner);
+                ItemDescription::Newtype(inner.name.into())
+            }
+            ItemDeclaration::Struct(data) => {
+                ItemDescription::Struct(self.describe_data(data, todo)?)
+            }
+            ItemDeclaration::Enum {
+                variant_labels_for_serde,
+                variants,
+            } => ItemDescription::Enum {
+                labels: variant_labels_for_serde
+                    .iter()
+                    .map(|&label| label.into())
+                    .collect(),
+                variants: variants
+                    .iter()
+                    .map(|arm| {
+                        Ok(ArmDescription {
+                            label: arm.label.into(),
+                            variant_index: arm.variant_index,
+                            discriminant: arm.discriminant,
+                            variant: self.describe_data(&arm.variant, todo)?,
+                        })
+                    })
+                    .collect::<Result<_, ValueError>>()?,
+            },
+        };
+        Ok(TypeDescription {
+            name: typ.name.into(),
+            size: typ.layout.size(),
+            align: typ.layout.align(),
+            item,
+        })
+    }
+
+    fn describe_data<'a>(
+        &'a self,
+        data: &VariantData<'db>,
+        todo: &mut Vec<&'a ReflectedType<'db>>,
+    ) -> Result<VariantDescription, ValueError> {
+        Ok(match data {
+            VariantData::Unit => VariantDescription::Unit,
+            VariantData::Fields {
+                labels_for_serde,
+                fields,
+            } => VariantDescription::Fields {
+                labels: labels_for_serde.iter().map(|&label| label.into()).collect(),
+                fields: fields
+                    .iter()
+                    .map(|f| {
+                        Ok(FieldDescription {
+                            name: f.name.into(),
+                            offset: f.offset,
+                            shape: self.describe_shape(&f.shape, todo)?,
+                        })
+                    })
+                    .collect::<Result<_, ValueError>>()?,
+            },
+            VariantData::Tuple(fields) => {
+                VariantDescription::Tuple(self.describe_tuple(fields, todo)?)
+            }
+        })
+    }
+
+    fn describe_tuple<'a>(
+        &'a self,
+        fields: &[TupleField<'db>],
+        todo: &mut Vec<&'a ReflectedType<'db>>,
+    ) -> Result<Vec<TupleFieldDescription>, ValueError> {
+        fields
+            .iter()
+            .map(|f| {
+                Ok(TupleFieldDescription {
+                    offset: f.offset,
+                    shape: self.describe_shape(&f.shape, todo)?,
+                })
+            })
+            .collect()
+    }
+
+    fn describe_shape<'a>(
+        &'a self,
+        shape: &DataShape<'db>,
+        todo: &mut Vec<&'a ReflectedType<'db>>,
+    ) -> Result<ShapeDescription, ValueError> {
+        let mut refer = |id: TypeId| {
+            if let Some(typ) = self.leaf_type(id) {
+                todo.push(typ);
+            }
+            self.name_of(id)
+        };
+        Ok(match shape {
+            DataShape::Builtin(builtin) => ShapeDescription::Type(builtin.name().into()),
+            &DataShape::Leaf(id) => ShapeDescription::Type(refer(id)?),
+            &DataShape::FixedArray(id, len) => ShapeDescription::Array(refer(id)?, len),
+            DataShape::Slice(elt) => {
+                ShapeDescription::Slice(Box::new(self.describe_shape(elt, todo)?))
+            }
+            DataShape::Ref(inner) => {
+                ShapeDescription::Ref(Box::new(self.describe_shape(inner, todo)?))
+            }
+            DataShape::Tuple(fields) => ShapeDescription::Tuple(self.describe_tuple(fields, todo)?),
+        })
+    }
+
+    /// Build the `ReflectedType` described by `desc`, with type ids from `ids` and names and
+    /// labels kept in `strings`.
+    fn load(
+        &self,
+        desc: &TypeDescription,
+        ids: &BTreeMap<&str, TypeId>,
+        strings: &mut Strings,
+    ) -> Result<ReflectedType<'db>, ValueError> {
+        let layout = Layout::from_size_align(desc.size, desc.align)
+            .map_err(|_| ValueError(alloc::format!("bad layout for {}", desc.name)))?;
+        let typ = match &desc.item {
+            ItemDescription::Newtype(inner) => {
+                ItemDeclaration::Struct(VariantData::Tuple(Cow::Owned(alloc::vec![TupleField {
+                    offset: 0,
+                    #[cfg(feature = "attrs")]
+                    attrs: Cow::Borrowed(&[]),
+                    shape: Box::new(load_shape(
+                        &ShapeDescription::Type(inner.clone()),
+                        ids,
+                        strings,
+                    )),
+                }])))
+            }
+            ItemDescription::Struct(data) => ItemDeclaration::Struct(load_data(data, ids, strings)),
+            ItemDescription::Enum { labels, variants } => {
+                let mut variants: Vec<_> = variants
+                    .iter()
+                    .map(|arm| EnumArm {
+                        label: strings.name(&arm.label),
+                        variant_index: arm.variant_index,
+                        discriminant: arm.discriminant,
+                        #[cfg(feature = "attrs")]
+                        attrs: Cow::Borrowed(&[]),
+                        variant: load_data(&arm.variant, ids, strings),
+                    })
+                    .collect();
+                variants.sort_by_key(|arm| arm.discriminant);
+                ItemDeclaration::Enum {
+                    variant_labels_for_serde: strings.labels(labels),
+                    variants: Cow::Owned(variants),
+                }
+            }
+        };
+        Ok(ReflectedType {
+            id: ids[desc.name.as_str()],
+            name: strings.name(&desc.name),
+            layout,
+            typ,
+            #[cfg(feature = "attrs")]
+            attrs: Cow::Borrowed(&[]),
+        })
+    }
+
+    /// Check that the fields of the loaded type `typ` fit in its layout without overlapping,
+    /// and that an enum has room for its discriminant and a variant for each one.
+    fn check_layout(
+        &self,
+        loaded: &BTreeMap<TypeId, ReflectedType<'db>>,
+        typ: &ReflectedType<'db>,
+    ) -> Result<(), ValueError> {
+        match &typ.typ {
+            ItemDeclaration::Newtype(_) => unreachable!(),
+            ItemDeclaration::Struct(data) => self.check_fields(
+                loaded,
+                typ.name,
+                typ.layout,
+                0,
+                data_shapes(data).into_iter(),
+            ),
+            ItemDeclaration::Enum { variants, .. } => {
+                let tag = Layout::new::<u16>();
+                if typ.layout.size() < tag.size() || typ.layout.align() < tag.align() {
+                    return Err(ValueError(alloc::format!(
+                        "`{}` has no room for its discriminant",
+                        typ.name
+                    )));
+                }
+                if let Some(pair) = variants
+                    .windows(2)
+                    .find(|pair| pair[0].discriminant == pair[1].discriminant)
+                {
+                    return Err(ValueError(alloc::format!(
+                        "`{}` has two variants with discriminant {}",
+                        typ.name,
+                        pair[0].discriminant
+                    )));
+                }
+                for arm in variants.iter() {
+                    self.check_fields(
+                        loaded,
+                        typ.name,
+                        typ.layout,
+                        tag.size(),
+                        data_shapes(&arm.variant).into_iter(),
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Check that `fields`, by offset, fit in `layout` from `start` on without overlapping.
+    fn check_fields<'a>(
+        &self,
+        loaded: &BTreeMap<TypeId, ReflectedType<'db>>,
+        name: &str,
+        layout: Layout,
+        start: usize,
+        fields: impl Iterator<Item = (usize, &'a DataShape<'db>)>,
+    ) -> Result<(), ValueError>
+    where
+        'db: 'a,
+    {
+        let mut spans = Vec::new();
+        for (offset, shape) in fields {
+            let field = self.described_layout(loaded, name, shape)?;
+            let end = offset
+                .checked_add(field.size())
+                .filter(|&end| end <= layout.size());
+            if offset < start
+                || end.is_none()
+                || offset % field.align() != 0
+                || field.align() > layout.align()
+            {
+                return Err(ValueError(alloc::format!(
+                    "a field of `{}` at offset {} doesn't fit its layout",
+                    name,
+                    offset
+                )));
+            }
+            if field.size() != 0 {
+                spans.push((offset, offset + field.size()));
+            }
+        }
+        spans.sort_unstable();
+        if spans.windows(2).any(|pair| pair[0].1 > pair[1].0) {
+            return Err(ValueError(alloc::format!("fields of `{}` overlap", name)));
+        }
+        Ok(())
+    }
+
+    /// The layout of a field of the loaded type called `name`.
+    fn described_layout(
+        &self,
+        loaded: &BTreeMap<TypeId, ReflectedType<'db>>,
+        name: &str,
+        shape: &DataShape<'db>,
+    ) -> Result<Layout, ValueError> {
+        match shape {
+            DataShape::Builtin(builtin) => Ok(builtin.layout()),
+            &DataShape::Leaf(id) => match loaded.get(&id) {
+                Some(typ) => Ok(typ.layout),
+                None => self.layout_of(Place::Shape(shape)),
+            },
+            &DataShape::FixedArray(id, len) => {
+                let elt = self.described_layout(loaded, name, &element_shape(id))?;
+                elt.pad_to_align()
+                    .size()
+                    .checked_mul(len)
+                    .and_then(|size| Layout::from_size_align(size, elt.align()).ok())
+                    .ok_or_else(|| {
+                        ValueError(alloc::format!("an array in `{}` is too large", name))
+                    })
+            }
+            DataShape::Tuple(fields) => {
+                let (mut size, mut align) = (0, 1);
+                for f in fields.iter() {
+                    let layout = self.described_layout(loaded, name, &f.shape)?;
+                    size = f
+                        .offset
+                        .checked_add(layout.size())
+                        .ok_or_else(|| {
+                            ValueError(alloc::format!("a tuple in `{}` is too large", name))
+                        })?
+                        .max(size);
+                    align = align.max(layout.align());
+                }
+                let layout = Layout::from_size_align(size, align)
+                    .map(|layout| layout.pad_to_align())
+                    .map_err(|_| {
+                        ValueError(alloc::format!("a tuple in `{}` is too large", name))
+                    })?;
+                self.check_fields(
+                    loaded,
+                    name,
+                    layout,
+                    0,
+                    fields.iter().map(|f| (f.offset, &*f.shape)),
+                )?;
+                Ok(layout)
+            }
+            DataShape::Slice(_) | DataShape::Ref(_) => Err(ValueError(alloc::format!(
+                "`{}` borrows data, which can't be loaded",
+                name
+            ))),
+        }
+    }
+
+    /// Check that the type with id `id` doesn't contain itself, directly or through the
+    /// types in `path`. The types in `finite` are known not to.
+    fn check_finite(
+        &self,
+        loaded: &BTreeMap<TypeId, ReflectedType<'db>>,
+        id: TypeId,
+        path: &mut Vec<TypeId>,
+        finite: &mut BTreeSet<TypeId>,
+    ) -> Result<(), ValueError> {
+        if finite.contains(&id) {
+            return Ok(());
+        }
+        let typ = match loaded.get(&id).or_else(|| self.leaf_type(id)) {
+            Some(typ) => typ,
+            None => return Ok(()),
+        };
+        if path.contains(&id) {
+            return Err(ValueError(alloc::format!("`{}` contains itself", typ.name)));
+        }
+        let mut contained = Vec::new();
+        match &typ.typ {
+            ItemDeclaration::Newtype(inner) => contained.push(inner.id),
+            ItemDeclaration::Struct(data) => {
+                for (_, shape) in data_shapes(data) {
+                    shape_ids(shape, &mut contained);
+                }
+            }
+            ItemDeclaration::Enum { variants, .. } => {
+                for arm in variants.iter() {
+                    for (_, shape) in data_shapes(&arm.variant) {
+                        shape_ids(shape, &mut contained);
+                    }
+                }
+            }
+        }
+        path.push(id);
+        for inner in contained {
+            self.check_finite(loaded, inner, path, finite)?;
+        }
+        path.pop();
+        finite.insert(id);
+        Ok(())
+    }
+}
+
+/// The fields of `data`, by offset.
+fn data_shapes<'a, 'db>(data: &'a VariantData<'db>) -> Vec<(usize, &'a DataShape<'db>)> {
+    match data {
+        VariantData::Unit => Vec::new(),
+        VariantData::Fields { fields, .. } => fields.iter().map(|f| (f.offset, &f.shape)).collect(),
+        VariantData::Tuple(fields) => fields.iter().map(|f| (f.offset, &*f.shape)).collect(),
+    }
+}
+
+/// The ids of the types `shape` holds by value.
+fn shape_ids(shape: &DataShape<'_>, out: &mut Vec<TypeId>) {
+    match shape {
+        &DataShape::Leaf(id) | &DataShape::FixedArray(id, _) => out.push(id),
+        DataShape::Tuple(fields) => fields.iter().for_each(|f| shape_ids(&f.shape, out)),
+        DataShape::Builtin(_) | DataShape::Slice(_) | DataShape::Ref(_) => {}
+    }
+}
+
+impl ItemDescription {
+    /// Every type name this refers to.
+    fn names<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            ItemDescription::Newtype(inner) => out.push(inner),
+            ItemDescription::Struct(data) => data.names(out),
+            ItemDescription::Enum { variants, .. } => {
+                for arm in variants {
+                    arm.variant.names(out);
+                }
+            }
+        }
+    }
+}
+
+impl VariantDescription {
+    fn names<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            VariantDescription::Unit => {}
+            VariantDescription::Fields { fields, .. } => {
+                for f in fields {
+                    f.shape.names(out);
+                }
+            }
+            VariantDescription::Tuple(fields) => {
+                for f in fields {
+                    f.shape.names(out);
+                }
+            }
+        }
+    }
+}
+
+impl ShapeDescription {
+    fn names<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            ShapeDescription::Type(name) | ShapeDescription::Array(name, _) => out.push(name),
+            ShapeDescription::Slice(inner) | ShapeDescription::Ref(inner) => inner.names(out),
+            ShapeDescription::Tuple(fields) => {
+                for f in fields {
+                    f.shape.names(out);
+                }
+            }
+        }
+    }
+}
+
+/// The names and labels of types loaded by `Db::insert_described`.
+///
+/// The loaded `ReflectedType`s borrow these as `&'static str`s, but they live only as long as
+/// the `Db` that owns them, which drops the types along with them.
+#[derive(Default)]
+pub(crate) struct Strings {
+    names: Vec<Box<str>>,
+    labels: Vec<Box<[&'static str]>>,
+}
+
+impl Strings {
+    fn name(&mut self, name: &str) -> &'static str {
+        let name: Box<str> = name.into();
+        // SAFETY: the string is on the heap, and is not freed until this is dropped with
+        // the types that borrow it.
+        let borrowed = unsafe { &*(&*name as *const str) };
+        self.names.push(name);
+        borrowed
+    }
+
+    fn labels(&mut self, labels: &[String]) -> &'static [&'static str] {
+        let labels: Box<[&'static str]> = labels.iter().map(|label| self.name(label)).collect();
+        // SAFETY: as for `name`.
+        let borrowed = unsafe { &*(&*labels as *const [&'static str]) };
+        self.labels.push(labels);
+        borrowed
+    }
+
+    /// Take ownership of the strings of `other`, whose types have moved here.
+    pub(crate) fn append(&mut self, mut other: Strings) {
+        self.names.append(&mut other.names);
+        self.labels.append(&mut other.labels);
+    }
+}
+
+fn id_of(name: &str, ids: &BTreeMap<&str, TypeId>) -> TypeId {
+    RustBuiltin::from_name(name).map_or_else(|| ids[name], |builtin| builtin.type_id())
+}
+
+fn load_data<'db>(
+    data: &VariantDescription,
+    ids: &BTreeMap<&str, TypeId>,
+    strings: &mut Strings,
+) -> VariantData<'db> {
+    match data {
+        VariantDescription::Unit => VariantData::Unit,
+        VariantDescription::Fields { labels, fields } => VariantData::Fields {
+            labels_for_serde: strings.labels(labels),
+            fields: Cow::Owned(
+                fields
+                    .iter()
+                    .map(|f| Field {
+                        offset: f.offset,
+                        name: strings.name(&f.name),
+                        #[cfg(feature = "attrs")]
+                        attrs: Cow::Borrowed(&[]),
+                        shape: load_shape(&f.shape, ids, strings),
+                    })
+                    .collect(),
+            ),
+        },
+        VariantDescription::Tuple(fields) => VariantData::Tuple(load_tuple(fields, ids, strings)),
+    }
+}
+
+fn load_tuple<'db>(
+    fields: &[TupleFieldDescription],
+    ids: &BTreeMap<&str, TypeId>,
+    strings: &mut Strings,
+) -> Cow<'db, [TupleField<'db>]> {
+    Cow::Owned(
+        fields
+            .iter()
+            .map(|f| TupleField {
+                offset: f.offset,
+                #[cfg(feature = "attrs")]
+                attrs: Cow::Borrowed(&[]),
+                shape: Box::new(load_shape(&f.shape, ids, strings)),
+            })
+            .collect(),
+    )
+}
+
+fn load_shape<'db>(
+    shape: &ShapeDescription,
+    ids: &BTreeMap<&str, TypeId>,
+    strings: &mut Strings,
+) -> DataShape<'db> {
+    match shape {
+        ShapeDescription::Type(name) => match RustBuiltin::from_name(name) {
+            Some(builtin) => DataShape::Builtin(builtin),
+            None => DataShape::Leaf(ids[name.as_str()]),
+        },
+        ShapeDescription::Array(name, len) => DataShape::FixedArray(id_of(name, ids), *len),
+        ShapeDescription::Slice(elt) => DataShape::Slice(Box::new(load_shape(elt, ids, strings))),
+        ShapeDescription::Ref(inner) => DataShape::Ref(Box::new(load_shape(inner, ids, strings))),
+        ShapeDescription::Tuple(fields) => DataShape::Tuple(load_tuple(fields, ids, strings)),
+    }
+}
+
+/// Rust syntax for the shape, like `[u8; 4]` or `(i32, alloc::string::String)`.
+impl core::fmt::Display for ShapeDescription {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            ShapeDescription::Type(name) => f.write_str(name),
+            ShapeDescription::Array(name, len) => write!(f, "[{}; {}]", name, len),
+            ShapeDescription::Slice(elt) => write!(f, "[{}]", elt),
+            ShapeDescription::Ref(inner) => write!(f, "&{}", inner),
+            ShapeDescription::Tuple(fields) => {
+                f.write_str("(")?;
+                for (ix, field) in fields.iter().enumerate() {
+                    if ix != 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", field.shape)?;
+                }
+                if fields.len() == 1 {
+                    f.write_str(",")?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::convert::{ValueError, ValueSerializer};
+use crate::metadata::*;
+use crate::path::{FieldPath, PathSegment};
+use crate::value::{element_shape, field_label, read_builtin, ReflectValue};
+use crate::{active_arm, Db};
+
+use alloc::vec::Vec;
+use core::any::{Any, TypeId};
+
+/// A difference between two values of the same type, found by `Db::diff`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// A builtin or leaf differs.
+    Leaf {
+        path: FieldPath,
+        before: ReflectValue,
+        after: ReflectValue,
+    },
+    /// An enum holds a different variant. Nothing inside it is compared.
+    Variant {
+        path: FieldPath,
+        before: &'static str,
+        after: &'static str,
+    },
+    /// A builtin or leaf in an element of a fixed-size array differs, whether it is the element
+    /// or somewhere inside it.
+    Element {
+        path: FieldPath,
+        before: ReflectValue,
+        after: ReflectValue,
+    },
+}
+
+impl Change {
+    /// Where in the value the change happened.
+    pub fn path(&self) -> &FieldPath {
+        match self {
+            Change::Leaf { path, .. }
+            | Change::Variant { path, .. }
+            | Change::Element { path, .. } => path,
+        }
+    }
+}
+
+impl<'db> Db<'db> {
+    /// Compare two values of a registered type, field by field.
+    ///
+    /// Changes are listed in declaration order. Leaves are compared by the `ReflectValue`
+    /// their `Serialize` implementation produces, and fields with a hook by the one the hook
+    /// produces.
+    pub fn diff<T: Any>(&self, before: &T, after: &T) -> Result<Vec<Change>, ValueError> {
+        let typ = self
+            .known_type(TypeId::of::<T>())
+            .ok_or_else(|| ValueError("type missing from db, cannot diff".into()))?;
+        let mut differ = Differ {
+            db: self,
+            path: Vec::new(),
+            changes: Vec::new(),
+        };
+        // SAFETY: both pointers come from references to the registered type.
+        unsafe {
+            differ.item(
+                typ,
+                before as *const T as *const u8,
+                after as *const T as *const u8,
+                Within::Field,
+            )?;
+        }
+        Ok(differ.changes)
+    }
+}
+
+struct Differ<'a, 'db> {
+    db: &'a Db<'db>,
+    path: Vec<PathSegment>,
+    changes: Vec<Change>,
+}
+
+/// Whether a difference is somewhere in an element of an array, or only in fields.
+#[derive(Clone, Copy)]
+enum Within {
+    Field,
+    Array,
+}
+
+impl<'db> Differ<'_, 'db> {
+    fn nested<R>(&mut self, seg: PathSegment, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.path.push(seg);
+        let r = f(self);
+        self.path.pop();
+        r
+    }
+
+    fn here(&self) -> FieldPath {
+        FieldPath(self.path.clone())
+    }
+
+    fn record(&mut self, within: Within, before: ReflectValue, after: ReflectValue) {
+        if before == after {
+            return;
+        }
+        let path = self.here();
+        self.changes.push(match within {
+            Within::Field => Change::Leaf {
+                path,
+                before,
+                after,
+            },
+            Within::Array => Change::Element {
+                path,
+                before,
+                after,
+            },
+        });
+    }
+
+    /// SAFETY: `a` and `b` must both point to values described by `typ`.
+    unsafe fn item(
+        &mut self,
+        typ: &ReflectedType<'db>,
+        a: *const u8,
+        b: *const u8,
+        within: Within,
+    ) -> Result<(), ValueError> {
+        match &typ.typ {
+            ItemDeclaration::Newtype(inner) => self.item(inner, a, b, within),
+            ItemDeclaration::Struct(data) => self.variant_data(data, a, b, within),
+            ItemDeclaration::Enum { variants, .. } => {
+                let arm_a = active_arm(variants, a).map_err(|e| ValueError(e.into()))?;
+                let arm_b = active_arm(variants, b).map_err(|e| ValueError(e.into()))?;
+                if arm_a.discriminant != arm_b.discriminant {
+                    self.changes.push(Change::Variant {
+                        path: self.here(),
+                        before: arm_a.label,
+                        after: arm_b.label,
+                    });
+                    Ok(())
+                } else {
+                    self.nested(PathSegment::Variant(arm_a.label), |me| {
+                        me.variant_data(&arm_a.variant, a, b, within)
+                    })
+                }
+            }
+        }
+    }
+
+    /// SAFETY: `a` and `b` must both point to the base of values holding `data`.
+    unsafe fn variant_data(
+        &mut self,
+        data: &VariantData<'db>,
+        a: *const u8,
+        b: *const u8,
+        within: Within,
+    ) -> Result<(), ValueError> {
+        match data {
+            VariantData::Unit => Ok(()),
+            VariantData::Fields {
+                labels_for_serde,
+                fields,
+            } => {
+                for (ix, f) in fields.iter().enumerate() {
+                    let label = field_label(labels_for_serde, ix, f);
+                    self.nested(PathSegment::Field(label), |me| {
+                        let (a, b) = (a.add(f.offset), b.add(f.offset));
+                        me.field(attrs_of!(f), &f.shape, a, b, within)
+                    })?;
+                }
+                Ok(())
+            }
+            VariantData::Tuple(fields) => self.tuple(fields, a, b, within),
+        }
+    }
+
+    unsafe fn tuple(
+        &mut self,
+        fields: &[TupleField<'db>],
+        a: *const u8,
+        b: *const u8,
+        within: Within,
+    ) -> Result<(), ValueError> {
+        for (ix, f) in fields.iter().enumerate() {
+            self.nested(PathSegment::Index(ix), |me| {
+                let (a, b) = (a.add(f.offset), b.add(f.offset));
+                me.field(attrs_of!(f), &f.shape, a, b, within)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Fields with a hook are compared by the `ReflectValue` the hook serializes them to.
+    ///
+    /// SAFETY: `a` and `b` must both point to values described by `shape`.
+    unsafe fn field(
+        &mut self,
+        attrs: Option<&[Attr]>,
+        shape: &DataShape<'db>,
+        a: *const u8,
+        b: *const u8,
+        within: Within,
+    ) -> Result<(), ValueError> {
+        match self.db.field_hook(attrs, shape)? {
+            Some(hook) => {
+                let before = hook.serialize(ValueSerializer, a)?;
+                let after = hook.serialize(ValueSerializer, b)?;
+                self.record(within, before, after);
+                Ok(())
+            }
+            None => self.shape(shape, a, b, within),
+        }
+    }
+
+    /// SAFETY: `a` and `b` must both point to values described by `shape`.
+    unsafe fn shape(
+        &mut self,
+        shape: &DataShape<'db>,
+        a: *const u8,
+        b: *const u8,
+        within: Within,
+    ) -> Result<(), ValueError> {
+        let db = self.db;
+        match shape {
+            &DataShape::Builtin(builtin) => {
+                self.record(within, read_builtin(builtin, a), read_builtin(builtin, b));
+                Ok(())
+            }
+            &DataShape::Leaf(id) => match db.leaf_type(id) {
+                Some(typ) => self.item(typ, a, b, within),
+                None => {
+                    let before = db.serialize_leaf_at(ValueSerializer, id, a)?;
+                    let after = db.serialize_leaf_at(ValueSerializer, id, b)?;
+                    self.record(within, before, after);
+                    Ok(())
+                }
+            },
+            &DataShape::FixedArray(id, len) => {
+                let elt = element_shape(id);
+                let stride = db.stride(id).map_err(ValueError)?;
+                for ix in 0..len {
+                    self.nested(PathSegment::Index(ix), |me| {
+                        me.shape(&elt, a.add(ix * stride), b.add(ix * stride), Within::Array)
+                    })?;
+                }
+                Ok(())
+            }
+            DataShape::Tuple(fields) => self.tuple(fields, a, b, within),
+            DataShape::Slice(_) | DataShape::Ref(_) => {
+                Err(ValueError("cannot diff through borrowed data".into()))
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use crate::{Db, Plans};
+
+use alloc::sync::Arc;
+
+/// A `Db` that can no longer change, with its types compiled, for sharing between threads.
+///
+/// Clones share the same database. It derefs to `Db` for everything but registering types.
+#[derive(Clone)]
+pub struct FrozenDb<'db>(Arc<Frozen<'db>>);
+
+struct Frozen<'db> {
+    db: Db<'db>,
+    plans: Plans,
+}
+
+impl<'db> Db<'db> {
+    /// Stop registering types, and compile the ones registered so far.
+    pub fn freeze(self) -> FrozenDb<'db> {
+        let plans = self.compile();
+        FrozenDb(Arc::new(Frozen { db: self, plans }))
+    }
+}
+
+impl FrozenDb<'_> {
+    /// The plans compiled when the database was frozen.
+    pub fn plans(&self) -> &Plans {
+        &self.0.plans
+    }
+}
+
+impl<'db> core::ops::Deref for FrozenDb<'db> {
+    type Target = Db<'db>;
+
+    fn deref(&self) -> &Db<'db> {
+        &self.0.db
+    }
+}
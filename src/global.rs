@@ -0,0 +1,62 @@
+#[cfg(feature = "global")]
+use crate::{Db, FrozenDb};
+
+/// A function adding types to `Db::global`, collected at link time by `register_global!`.
+#[cfg(feature = "global")]
+pub struct GlobalRegistration(#[doc(hidden)] pub fn(&mut Db<'static>));
+
+#[cfg(feature = "global")]
+inventory::collect!(GlobalRegistration);
+
+#[cfg(feature = "global")]
+impl Db<'static> {
+    /// The database of everything registered with `register_global!`, which includes every
+    /// non-generic type deriving `Reflect` that is linked into the program.
+    ///
+    /// It is built the first time it is asked for.
+    pub fn global() -> &'static FrozenDb<'static> {
+        static GLOBAL: std::sync::OnceLock<FrozenDb<'static>> = std::sync::OnceLock::new();
+        GLOBAL.get_or_init(|| {
+            let mut db = Db::new();
+            for registration in inventory::iter::<GlobalRegistration> {
+                (registration.0)(&mut db);
+            }
+            db.freeze()
+        })
+    }
+}
+
+/// Add to `Db::global` from any crate in the program, with a function that is given the
+/// database before it is frozen.
+///
+/// ```ignore
+/// serde_reflect::register_global!(|db| {
+///     db.register_serde_leaf::<String>();
+/// });
+/// ```
+#[cfg(feature = "global")]
+#[macro_export]
+macro_rules! register_global {
+    ($register:expr) => {
+        $crate::inventory::submit! {
+            $crate::GlobalRegistration($register)
+        }
+    };
+}
+
+/// Used by `#[derive(Reflect)]` to register a type globally when the `global` feature is on.
+#[cfg(feature = "global")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_derived {
+    ($t:ty) => {
+        $crate::register_global!(|db| <$t as $crate::Reflect>::register(db));
+    };
+}
+
+#[cfg(not(feature = "global"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_derived {
+    ($t:ty) => {};
+}
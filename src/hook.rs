@@ -0,0 +1,160 @@
+use crate::convert::ValueError;
+use crate::metadata::*;
+use crate::{drop_glue, serialize_object, Db, DeserializeTrampoline, DropGlue, SerializeObject};
+
+use core::any::TypeId;
+use core::marker::PhantomData;
+
+/// Another way to serialize values of type `Field`, like a module for serde's
+/// `#[serde(with = "...")]`.
+///
+/// Once registered under a name with `Db::register_with`, a field can use it instead of the
+/// serde implementations or reflected type of `Field` with `#[reflect(with = "name")]`. This
+/// needs the `attrs` feature, without which `#[derive(Reflect)]` rejects the attribute.
+///
+/// Every path through reflection follows the hook. Dynamic values, diffs and walks see the
+/// field as the `ReflectValue` the hook serializes it to, and generated schemas can't say what
+/// that is: JSON Schema accepts anything, TypeScript has `unknown`, and protobuf has no type.
+pub trait SerdeWith: 'static {
+    type Field: 'static;
+
+    fn serialize<S: serde::Serializer>(val: &Self::Field, s: S) -> Result<S::Ok, S::Error>;
+
+    fn deserialize<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Self::Field, D::Error>;
+}
+
+/// A registered `SerdeWith`.
+#[derive(Clone, Copy)]
+pub(crate) struct Hook {
+    pub(crate) field: TypeId,
+    pub(crate) field_name: &'static str,
+    pub(crate) object: SerializeObject,
+    pub(crate) deserialize: DeserializeTrampoline,
+    pub(crate) drop: DropGlue,
+}
+
+/// A `W::Field`, serialized with `W`.
+#[repr(transparent)]
+struct With<W: SerdeWith>(W::Field, PhantomData<W>);
+
+impl<W: SerdeWith> serde::Serialize for With<W> {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        W::serialize(&self.0, s)
+    }
+}
+
+fn deserialize_with<W: SerdeWith>(
+    d: &mut dyn erased_serde::Deserializer,
+    dst: *mut u8,
+) -> Result<(), erased_serde::Error> {
+    let x = W::deserialize(d)?;
+    // SAFETY: hooks are only used for fields of type `W::Field`.
+    unsafe { dst.cast::<W::Field>().write(x) };
+    Ok(())
+}
+
+impl Hook {
+    /// Serialize the field at `ptr` with the hook.
+    ///
+    /// SAFETY: `ptr` must point to an initialized value of the hook's field type.
+    pub(crate) unsafe fn serialize<S: serde::Serializer>(
+        self,
+        s: S,
+        ptr: *const u8,
+    ) -> Result<S::Ok, S::Error> {
+        erased_serde::serialize(&*(self.object)(ptr), s)
+    }
+
+    /// Deserialize a field into `ptr` with the hook.
+    ///
+    /// SAFETY: `ptr` must be valid for writes of the hook's field type. It is not dropped first.
+    pub(crate) unsafe fn deserialize<'de, D: serde::Deserializer<'de>>(
+        self,
+        d: D,
+        ptr: *mut u8,
+    ) -> Result<(), D::Error> {
+        use serde::de::Error;
+        (self.deserialize)(&mut <dyn erased_serde::Deserializer>::erase(d), ptr)
+            .map_err(D::Error::custom)
+    }
+}
+
+impl<'db> Db<'db> {
+    /// Make `W` available to fields as `#[reflect(with = "name")]`.
+    pub fn register_with<W: SerdeWith>(&mut self, name: &'static str) -> &mut Db<'db> {
+        self.hooks.insert(
+            name,
+            Hook {
+                field: TypeId::of::<W::Field>(),
+                field_name: core::any::type_name::<W::Field>(),
+                object: serialize_object::<With<W>>,
+                deserialize: deserialize_with::<W>,
+                drop: drop_glue::<W::Field>,
+            },
+        );
+        self
+    }
+
+    /// The hook a field with `attrs` and `shape` asks for with `#[reflect(with = "...")]`, if
+    /// any, checked to be for the field's type.
+    pub(crate) fn field_hook(
+        &self,
+        attrs: Option<&[Attr]>,
+        shape: &DataShape<'db>,
+    ) -> Result<Option<Hook>, ValueError> {
+        let name = match hook_name(attrs)? {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let hook = *self
+            .lookup(|db| &db.hooks, &name)
+            .ok_or_else(|| ValueError(alloc::format!("no hook named {:?}", name)))?;
+        let field = match *shape {
+            DataShape::Leaf(id) => Some(id),
+            DataShape::Builtin(builtin) => Some(builtin.type_id()),
+            _ => None,
+        };
+        if field != Some(hook.field) {
+            return Err(ValueError(alloc::format!(
+                "hook {:?} is for fields of type {}",
+                name,
+                hook.field_name
+            )));
+        }
+        Ok(Some(hook))
+    }
+}
+
+/// The name in a `#[reflect(with = "...")]` among `attrs`, if there is one.
+#[allow(unused_variables)]
+pub(crate) fn hook_name(attrs: Option<&[Attr]>) -> Result<Option<&'static str>, ValueError> {
+    #[cfg(feature = "attrs")]
+    if let Some(val) = attrs.and_then(|attrs| Attr::reflect_option(attrs, "with")) {
+        return match val {
+            &RustPrimitive::Str(name) => Ok(Some(name)),
+            other => Err(ValueError(alloc::format!(
+                "`{:?}` is not the name of a hook",
+                other
+            ))),
+        };
+    }
+    Ok(None)
+}
+
+/// Emitted by `#[derive(Reflect)]` for each `#[reflect(with = "...")]`, which would be silently
+/// ignored without the `attrs` feature.
+#[cfg(feature = "attrs")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __field_hook {
+    () => {};
+}
+
+#[cfg(not(feature = "attrs"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __field_hook {
+    () => {
+        compile_error!("`#[reflect(with = \"...\")]` needs the `attrs` feature of serde_reflect");
+    };
+}
@@ -0,0 +1,157 @@
+use crate::convert::ValueError;
+use crate::metadata::*;
+use crate::{Db, FrozenDb};
+
+use alloc::{collections::BTreeMap, string::String};
+use core::any::TypeId;
+
+/// What `Db::merge` does when both databases have something registered for the same type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep what this database has.
+    Keep,
+    /// Take what the other database has.
+    Replace,
+    /// Fail, leaving this database as it was.
+    Error,
+}
+
+/// What a table entry is for.
+trait TableKey: Ord + Copy {
+    /// Says what the entry is for in an error message.
+    fn describe(&self, db: &Db<'_>) -> String;
+}
+
+impl TableKey for TypeId {
+    fn describe(&self, db: &Db<'_>) -> String {
+        db.name_of(*self)
+            .unwrap_or_else(|_| alloc::format!("{:?}", self))
+    }
+}
+
+impl TableKey for (TypeId, u32) {
+    fn describe(&self, db: &Db<'_>) -> String {
+        alloc::format!(
+            "migration from version {} of {}",
+            self.1,
+            self.0.describe(db)
+        )
+    }
+}
+
+impl TableKey for &'static str {
+    fn describe(&self, _: &Db<'_>) -> String {
+        alloc::format!("hook {:?}", self)
+    }
+}
+
+/// Invokes `$m!(table)` for every table of a `Db`.
+macro_rules! each_table {
+    ($m:ident) => {
+        $m!(known_types);
+        $m!(deserialize_trampolines);
+        $m!(serialize_objects);
+        $m!(leaf_layouts);
+        $m!(leaf_names);
+        $m!(drop_glue);
+        $m!(leaf_schemas);
+        $m!(leaf_typescript);
+        $m!(leaf_proto);
+        $m!(migrations);
+        $m!(hooks);
+        #[cfg(feature = "proptest")]
+        $m!(leaf_strategies);
+    };
+}
+
+impl<'db> Db<'db> {
+    /// An empty database layered over `base`.
+    ///
+    /// Lookups that miss in this database fall through to `base`, which is shared rather
+    /// than copied, so this is cheap enough to do for each request. Types and leaves
+    /// registered here take precedence over those in `base`.
+    pub fn with_base(base: FrozenDb<'db>) -> Db<'db> {
+        Db {
+            base: Some(base),
+            ..Db::new()
+        }
+    }
+
+    /// The database this one is layered over, if any.
+    pub fn base(&self) -> Option<&FrozenDb<'db>> {
+        self.base.as_ref()
+    }
+
+    /// Add everything registered in `other` to this database.
+    ///
+    /// `policy` decides what happens to anything registered in both. Only what was registered in
+    /// `other` itself is merged, not its base.
+    pub fn merge(&mut self, other: Db<'db>, policy: ConflictPolicy) -> Result<(), ValueError> {
+        if policy == ConflictPolicy::Error {
+            macro_rules! check {
+                ($table:ident) => {
+                    if let Some(key) = other
+                        .$table
+                        .keys()
+                        .find(|key| self.$table.contains_key(*key))
+                    {
+                        return Err(ValueError(alloc::format!(
+                            "{} is registered in both databases",
+                            key.describe(self)
+                        )));
+                    }
+                };
+            }
+            each_table!(check);
+        }
+        macro_rules! merge {
+            ($table:ident) => {
+                merge_table(&mut self.$table, other.$table, policy);
+            };
+        }
+        each_table!(merge);
+        // types described in `other` may have moved here, and borrow its strings
+        self.strings.append(other.strings);
+        Ok(())
+    }
+
+    /// Look `key` up in one of the tables of this database, and then in its base.
+    pub(crate) fn lookup<'s, K: Ord + 's, V: 's>(
+        &'s self,
+        table: impl Fn(&'s Db<'db>) -> &'s BTreeMap<K, V>,
+        key: &K,
+    ) -> Option<&'s V> {
+        let mut db = self;
+        loop {
+            if let Some(val) = table(db).get(key) {
+                return Some(val);
+            }
+            db = db.base.as_deref()?;
+        }
+    }
+
+    /// Every reflected type known here or in the base, by type id.
+    pub(crate) fn types(&self) -> BTreeMap<TypeId, &ReflectedType<'db>> {
+        let mut types = match &self.base {
+            Some(base) => base.types(),
+            None => BTreeMap::new(),
+        };
+        for (id, typ) in &self.known_types {
+            types.insert(*id, typ.as_ref());
+        }
+        types
+    }
+}
+
+fn merge_table<K: TableKey, V>(
+    table: &mut BTreeMap<K, V>,
+    other: BTreeMap<K, V>,
+    policy: ConflictPolicy,
+) {
+    for (key, val) in other {
+        if policy == ConflictPolicy::Keep && table.contains_key(&key) {
+            continue;
+        }
+        table.insert(key, val);
+    }
+}
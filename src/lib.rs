@@ -12,6 +12,8 @@
 use core::any::TypeId;
 
 extern crate alloc;
+#[cfg(feature = "global")]
+extern crate std;
 use alloc::{borrow::Cow, collections::BTreeMap};
 
 pub type StaticType = Cow<'static, ReflectedType<'static>>;
@@ -21,22 +23,96 @@ pub type DynamicType<'r> = Cow<'r, ReflectedType<'r>>;
 #[allow(unused_imports)]
 #[macro_use]
 extern crate serde_reflect_derive;
+#[cfg(feature = "global")]
+#[doc(hidden)]
+pub use inventory;
 #[doc(hidden)]
 pub use memoffset;
 #[cfg(feature = "derive")]
 #[doc(hidden)]
 pub use serde_reflect_derive::*;
 
+/// The attributes of a field, arm or type, if they are kept.
+#[cfg(feature = "attrs")]
+macro_rules! attrs_of {
+    ($x:expr) => {
+        Some(&$x.attrs[..])
+    };
+}
+
+#[cfg(not(feature = "attrs"))]
+macro_rules! attrs_of {
+    ($x:expr) => {
+        None
+    };
+}
+
+#[cfg(feature = "proptest")]
+mod arbitrary;
+mod compact;
+mod compat;
+mod convert;
 mod de;
+mod decls;
+mod describe;
+mod diff;
+mod frozen;
+mod global;
+mod hook;
+mod layer;
 mod metadata;
+mod patch;
+mod path;
+mod place;
+mod plan;
+mod pod;
+mod proto;
+mod schema;
 mod ser;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod typescript;
+mod value;
+mod version;
+mod visit;
 
+pub use compat::*;
+pub use convert::*;
 pub use de::*;
+pub use describe::*;
+pub use diff::*;
+pub use frozen::*;
+#[cfg(feature = "global")]
+pub use global::*;
+pub use hook::*;
+pub use layer::*;
 pub use metadata::*;
+pub use path::*;
+pub use plan::*;
+pub use schema::*;
 pub use ser::*;
+pub use typescript::*;
+pub use value::*;
+pub use version::*;
+pub use visit::*;
 
 type DeserializeTrampoline =
-    fn(&mut dyn erased_serde::Deserializer, &TypedOutputLocation) -> erased_serde::Result<()>;
+    fn(&mut dyn erased_serde::Deserializer, *mut u8) -> erased_serde::Result<()>;
+type DropGlue = unsafe fn(*mut u8);
+/// Views a pointer to a serde leaf as a trait object, which is only valid to dereference if the
+/// pointer is to an initialized value of the leaf type.
+type SerializeObject = fn(*const u8) -> *const dyn erased_serde::Serialize;
+
+fn serialize_object<T: serde::Serialize + 'static>(
+    ptr: *const u8,
+) -> *const dyn erased_serde::Serialize {
+    ptr.cast::<T>()
+}
+
+/// SAFETY: `ptr` must point to an initialized `T`, which is dropped.
+unsafe fn drop_glue<T>(ptr: *mut u8) {
+    core::ptr::drop_in_place(ptr.cast::<T>())
+}
 
 /// Home of `serialize` and `deserialize`.
 ///
@@ -62,7 +138,19 @@ type DeserializeTrampoline =
 pub struct Db<'r> {
     known_types: BTreeMap<TypeId, DynamicType<'r>>,
     deserialize_trampolines: BTreeMap<TypeId, DeserializeTrampoline>,
-    serialize_vtables: BTreeMap<TypeId, *mut ()>,
+    serialize_objects: BTreeMap<TypeId, SerializeObject>,
+    leaf_layouts: BTreeMap<TypeId, core::alloc::Layout>,
+    leaf_names: BTreeMap<TypeId, &'static str>,
+    drop_glue: BTreeMap<TypeId, DropGlue>,
+    leaf_schemas: BTreeMap<TypeId, LeafSchema>,
+    leaf_typescript: BTreeMap<TypeId, LeafTypeScript>,
+    leaf_proto: BTreeMap<TypeId, alloc::string::String>,
+    migrations: BTreeMap<(TypeId, u32), Migration>,
+    hooks: BTreeMap<&'static str, hook::Hook>,
+    #[cfg(feature = "proptest")]
+    leaf_strategies: BTreeMap<TypeId, arbitrary::ValueStrategy>,
+    base: Option<FrozenDb<'r>>,
+    strings: describe::Strings,
 }
 
 /// Downcast the lifetime of a static type.
@@ -71,19 +159,29 @@ pub fn demote_static<'r>(x: StaticType) -> DynamicType<'r> {
     // of that though. ReflectedType becomes invariant on its lifetime (and it
     // if very hard if not impossible to avoid that). The only references
     // it contains are Cow and they all have the same lifetime.
-    unsafe {
-        match x {
-            Cow::Borrowed(b) => Cow::Borrowed(core::mem::transmute::<
-                &'static ReflectedType<'static>,
-                &'r ReflectedType<'r>,
-            >(b)),
-            Cow::Owned(o) => Cow::Owned(core::mem::transmute::<
-                ReflectedType<'static>,
-                ReflectedType<'r>,
-            >(o)),
+    unsafe { core::mem::transmute::<StaticType, DynamicType<'r>>(x) }
+}
+
+/// The shape of a field of type `T`: a builtin, or a leaf to look up in the database.
+#[doc(hidden)]
+pub fn __shape_of<T: ?Sized + 'static>() -> DataShape<'static> {
+    value::element_shape(TypeId::of::<T>())
+}
+
+/// Offset of field `ix` of a `#[repr(u16)]` enum variant whose fields have `layouts`.
+///
+/// Such a variant is laid out as a `#[repr(C)]` struct of the `u16` tag followed by its fields.
+#[doc(hidden)]
+pub fn __variant_field_offset(layouts: &[core::alloc::Layout], ix: usize) -> usize {
+    let mut variant = core::alloc::Layout::new::<u16>();
+    for (field_ix, &field) in layouts.iter().enumerate() {
+        let (next, offset) = variant.extend(field).expect("enum variant too large");
+        if field_ix == ix {
+            return offset;
         }
-    };
-    unsafe { core::mem::transmute::<&'static ReflectedType<'static>, &'r ReflectedType<'r>>(x) }
+        variant = next;
+    }
+    panic!("no field {} in enum variant", ix)
 }
 
 impl<'db> Db<'db> {
@@ -95,6 +193,8 @@ impl<'db> Db<'db> {
     /// Associate a `ReflectedType` with some runtime Rust type. Uses type id of `T`.
     pub fn register_type<T: Reflect>(&mut self) -> &mut Db<'db> {
         self.insert(TypeId::of::<T::Key>(), demote_static(T::rust_type()));
+        self.drop_glue
+            .insert(TypeId::of::<T::Key>(), drop_glue::<T>);
         self
     }
 
@@ -104,6 +204,8 @@ impl<'db> Db<'db> {
             TypeId::of::<T::Key>(),
             demote_static(Cow::Borrowed(T::RUST_TYPE)),
         );
+        self.drop_glue
+            .insert(TypeId::of::<T::Key>(), drop_glue::<T>);
 
         self
     }
@@ -117,44 +219,82 @@ impl<'db> Db<'db> {
     /// When `T` is encountered during serialization, it will not be reflected into
     /// (even if it has a known type!) and instead the `Serialize`/`Deserialize`
     /// implementations will be used.
-    ///
-    /// `Default::default` is required to steal a trait object pointer without doing untoward.
-    pub fn register_serde_leaf<T: Default + serde::Serialize + for<'de> serde::Deserialize<'de>>(
+    pub fn register_serde_leaf<T: serde::Serialize + for<'de> serde::Deserialize<'de> + 'static>(
         &mut self,
     ) -> &mut Db<'db> {
         fn de<T: for<'b> serde::Deserialize<'b>>(
             d: &mut dyn erased_serde::Deserializer,
-            dst: &TypedOutputLocation,
+            dst: *mut u8,
         ) -> Result<(), erased_serde::Error> {
             let x: T = T::deserialize(d)?;
             // SAFETY: if everything has gone right, this is the crucial line that the correctness
             // of the reflection system is to ensure.
-            unsafe { dst.ptr.cast::<T>().write(x) };
+            unsafe { dst.cast::<T>().write(x) };
             Ok(())
         }
         let typeid = TypeId::of::<T>();
-        let vtable = unsafe {
-            core::mem::transmute::<&dyn erased_serde::Serialize, TraitObject>(
-                &Default::default() as &dyn erased_serde::Serialize
-            )
-            .vtable
-        };
-        self.serialize_vtables.insert(typeid, vtable);
+        self.serialize_objects.insert(typeid, serialize_object::<T>);
         self.deserialize_trampolines.insert(typeid, de::<T>);
+        self.leaf_layouts
+            .insert(typeid, core::alloc::Layout::new::<T>());
+        self.leaf_names.insert(typeid, core::any::type_name::<T>());
+        self.drop_glue.insert(typeid, drop_glue::<T>);
         self
     }
 
-    fn type_layout(&self, id: TypeId) -> Result<DynamicType<'db>, alloc::string::String> {
-        self.known_types
-            .get(&id)
-            .cloned()
-            .ok_or_else(|| alloc::format!("reflection db missing type info for typeid {:?}", id))
+    /// The reflected type registered for `id`, if any.
+    pub fn known_type(&self, id: TypeId) -> Option<&ReflectedType<'db>> {
+        self.lookup(|db| &db.known_types, &id)
+            .map(|typ| typ.as_ref())
+    }
+
+    /// The reflected type to look through a `DataShape::Leaf(id)` into, if any.
+    ///
+    /// A leaf registered with `register_serde_leaf` is never reflected into, even if it has a
+    /// known type. A layer's registrations take precedence over its base's.
+    pub(crate) fn leaf_type(&self, id: TypeId) -> Option<&ReflectedType<'db>> {
+        let mut db = self;
+        loop {
+            if db.serialize_objects.contains_key(&id) {
+                return None;
+            }
+            if let Some(typ) = db.known_types.get(&id) {
+                return Some(typ.as_ref());
+            }
+            db = db.base.as_deref()?;
+        }
+    }
+
+    /// The `core::any::type_name` of the type inside the serde leaf `id`, if it is an `Option`.
+    ///
+    /// serde_derive reads a missing `Option` field as `None`, so these fields may be left out.
+    pub(crate) fn option_leaf(&self, id: TypeId) -> Option<&'static str> {
+        let name = self.lookup(|db| &db.leaf_names, &id)?;
+        name.strip_prefix("core::option::Option<")?
+            .strip_suffix('>')
+    }
+
+    /// Distance in bytes between consecutive elements of a `[T; n]` where `T` has type id `id`.
+    pub(crate) fn stride(&self, id: TypeId) -> Result<usize, alloc::string::String> {
+        if let Some(builtin) = RustBuiltin::of(id) {
+            return Ok(builtin.size());
+        }
+        self.leaf_type(id)
+            .map(|typ| typ.layout)
+            .or_else(|| self.lookup(|db| &db.leaf_layouts, &id).copied())
+            .map(|layout| layout.pad_to_align().size())
+            .ok_or_else(|| alloc::format!("reflection db missing layout for typeid {:?}", id))
     }
 }
 
 /// Trait for types which can ponder at runtime and produce a description of themselves.
 ///
 /// There is a blanket impl for types which implement `StaticReflect`.
+///
+/// # Safety
+///
+/// `rust_type` must describe the layout of `Self` exactly: reflection reads and writes values
+/// through the offsets and shapes it gives.
 pub unsafe trait Reflect {
     /// `TypeId::of::<R::Key>()` is the database index for this type.
     type Key: 'static;
@@ -194,7 +334,7 @@ impl<'db, 'data> TypedOutputLocation<'db, 'data> {
     /// If `ptr` doesn't point to a large-enough region,
     /// then eventually deserialization is going to write a bunch of data
     /// into a suspect location.
-    pub unsafe fn new(typ: &DynamicType<'db>, ptr: *mut u8) -> Self {
+    pub unsafe fn new<'z: 'db>(typ: &'z DynamicType<'db>, ptr: *mut u8) -> Self {
         let _data = Default::default();
         TypedOutputLocation {
             typ: typ.as_ref(),
@@ -233,19 +373,18 @@ impl<'db, 'data> TypedLocation<'db, 'data> {
     }
 }
 
-impl TypedLocation<'_, '_> {
-    /// SAFETY: the location must be an enum.
-    unsafe fn read_discriminant(&self) -> u16 {
-        debug_assert!(matches!(self.typ.shape, DataShape::Enum { .. }));
-        self.ptr.cast::<u16>().read()
-    }
-}
-
-impl TypedOutputLocation<'_, '_> {
-    /// SAFETY: the location must be an enum
-    unsafe fn write_discriminant(&self, disc: u16) {
-        self.ptr.cast::<u16>().write(disc)
-    }
+/// Find the arm of an enum whose discriminant is stored at `ptr`.
+///
+/// SAFETY: `ptr` must point to an enum described by `variants`.
+pub(crate) unsafe fn active_arm<'v, 'db>(
+    variants: &'v [EnumArm<'db>],
+    ptr: *const u8,
+) -> Result<&'v EnumArm<'db>, &'static str> {
+    let tag = ptr.cast::<u16>().read();
+    variants
+        .binary_search_by_key(&tag, |arm| arm.discriminant)
+        .map(|ix| &variants[ix])
+        .map_err(|_| "runtime discriminant not described in reflection db")
 }
 
 //#region Conversions
@@ -303,11 +442,3 @@ impl<'db, 'data> From<TypedOutputLocation<'db, 'data>> for TypedLocation<'db, 'd
 }
 
 //#endregion
-
-// the type in std is unstable. the layout isn't.
-// https://doc.rust-lang.org/std/raw/struct.TraitObject.html
-#[repr(C)]
-pub(crate) struct TraitObject {
-    pub(crate) data: *mut (),
-    pub(crate) vtable: *mut (),
-}
@@ -1,5 +1,5 @@
 use alloc::borrow::Cow;
-use core::any::TypeId;
+use core::{alloc::Layout, any::TypeId};
 
 /// A named field, offset from the base of its containing type.
 #[derive(Clone, Debug)]
@@ -41,6 +41,19 @@ pub enum Attr {
     NameValue(&'static str, &'static RustPrimitive<'static>),
 }
 
+impl Attr {
+    /// The value given for `key` in a `#[reflect(key = value)]` attribute among `attrs`.
+    pub fn reflect_option(attrs: &[Attr], key: &str) -> Option<&'static RustPrimitive<'static>> {
+        attrs.iter().find_map(|attr| match attr {
+            Attr::List("reflect", options) => options.iter().find_map(|opt| match opt {
+                Attr::NameValue(name, val) if *name == key => Some(*val),
+                _ => None,
+            }),
+            _ => None,
+        })
+    }
+}
+
 /// A primitive Rust value that can occur in an attribute.
 #[derive(Clone, Debug)]
 pub enum RustPrimitive<'a> {
@@ -73,7 +86,8 @@ pub enum ItemDeclaration<'a> {
     /// `enum Foo { ... }`
     ///
     /// The offsets in the variant fields are relative to the enum base, ie,
-    /// the addr of the discriminant.
+    /// the addr of the discriminant. Reflected enums are `#[repr(u16)]`, so the
+    /// discriminant is a `u16` there.
     Enum {
         variant_labels_for_serde: &'static [&'static str],
         /// Sorted by discriminant (first key)
@@ -121,15 +135,15 @@ pub enum DataShape<'a> {
     /// `[T; n]`
     FixedArray(TypeId, usize),
     /// `&[T]`
-    Slice(Cow<'a, DataShape<'a>>),
+    Slice(alloc::boxed::Box<DataShape<'a>>),
     // TODO: if it is a fat pointer, what do i want to do about it?
     /// `&T`. ⚠️⚠️ might be a fat pointer! ⚠️⚠️
-    Ref(Cow<'a, DataShape<'a>>),
+    Ref(alloc::boxed::Box<DataShape<'a>>),
     /// `(T, U, ...)`
     Tuple(Cow<'a, [TupleField<'a>]>),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum RustBuiltin {
     U8,
     I8,
@@ -144,3 +158,101 @@ pub enum RustBuiltin {
     BOOLIN,
     CHAR,
 }
+
+impl RustBuiltin {
+    /// The builtin whose type id is `id`, if there is one.
+    pub fn of(id: TypeId) -> Option<RustBuiltin> {
+        use RustBuiltin::*;
+        let builtins: [(TypeId, RustBuiltin); 12] = [
+            (TypeId::of::<u8>(), U8),
+            (TypeId::of::<i8>(), I8),
+            (TypeId::of::<u16>(), U16),
+            (TypeId::of::<i16>(), I16),
+            (TypeId::of::<u32>(), U32),
+            (TypeId::of::<i32>(), I32),
+            (TypeId::of::<u64>(), U64),
+            (TypeId::of::<i64>(), I64),
+            (TypeId::of::<u128>(), U128),
+            (TypeId::of::<i128>(), I128),
+            (TypeId::of::<bool>(), BOOLIN),
+            (TypeId::of::<char>(), CHAR),
+        ];
+        builtins
+            .iter()
+            .find(|(tid, _)| *tid == id)
+            .map(|&(_, builtin)| builtin)
+    }
+
+    /// Size of the builtin in memory, in bytes.
+    pub fn size(&self) -> usize {
+        self.layout().size()
+    }
+
+    /// Layout of the builtin in memory.
+    pub fn layout(&self) -> Layout {
+        use RustBuiltin::*;
+        match self {
+            U8 => Layout::new::<u8>(),
+            I8 => Layout::new::<i8>(),
+            U16 => Layout::new::<u16>(),
+            I16 => Layout::new::<i16>(),
+            U32 => Layout::new::<u32>(),
+            I32 => Layout::new::<i32>(),
+            U64 => Layout::new::<u64>(),
+            I64 => Layout::new::<i64>(),
+            U128 => Layout::new::<u128>(),
+            I128 => Layout::new::<i128>(),
+            BOOLIN => Layout::new::<bool>(),
+            CHAR => Layout::new::<char>(),
+        }
+    }
+
+    /// Type id of the builtin type.
+    pub fn type_id(&self) -> TypeId {
+        use RustBuiltin::*;
+        match self {
+            U8 => TypeId::of::<u8>(),
+            I8 => TypeId::of::<i8>(),
+            U16 => TypeId::of::<u16>(),
+            I16 => TypeId::of::<i16>(),
+            U32 => TypeId::of::<u32>(),
+            I32 => TypeId::of::<i32>(),
+            U64 => TypeId::of::<u64>(),
+            I64 => TypeId::of::<i64>(),
+            U128 => TypeId::of::<u128>(),
+            I128 => TypeId::of::<i128>(),
+            BOOLIN => TypeId::of::<bool>(),
+            CHAR => TypeId::of::<char>(),
+        }
+    }
+
+    /// Name of the builtin type, as written in Rust.
+    pub fn name(&self) -> &'static str {
+        use RustBuiltin::*;
+        match self {
+            U8 => "u8",
+            I8 => "i8",
+            U16 => "u16",
+            I16 => "i16",
+            U32 => "u32",
+            I32 => "i32",
+            U64 => "u64",
+            I64 => "i64",
+            U128 => "u128",
+            I128 => "i128",
+            BOOLIN => "bool",
+            CHAR => "char",
+        }
+    }
+
+    /// The builtin called `name` in Rust, if there is one.
+    pub fn from_name(name: &str) -> Option<RustBuiltin> {
+        use RustBuiltin::*;
+        [
+            U8, I8, U16, I16, U32, I32, U64, I64, U128, I128, BOOLIN, CHAR,
+        ]
+        .iter()
+        .copied()
+        .find(|builtin| builtin.name() == name)
+    }
+}
@@ -0,0 +1,349 @@
+use crate::convert::ValueError;
+use crate::metadata::*;
+use crate::place::Place;
+use crate::value::{element_shape, field_label, AnySeed, ReflectValue};
+use crate::{active_arm, Db};
+
+use alloc::{string::String, vec::Vec};
+use core::any::{Any, TypeId};
+use serde::de::{DeserializeSeed, Error as deError};
+
+impl<'db> Db<'db> {
+    /// Apply a JSON Merge Patch ([RFC 7396]) to a value, in place.
+    ///
+    /// The patch can come from any format. Where the patch has a map and the value has a
+    /// struct with named fields, only the fields mentioned are touched. Anything else in the
+    /// patch replaces the corresponding part of the value, which is deserialized off to the
+    /// side and then swapped in, dropping the old part. If the patch fails partway, the parts
+    /// already replaced stay replaced.
+    ///
+    /// A `null` replaces with whatever `null` deserializes to, which for `Option` fields is
+    /// `None`. Fields which are not optional cannot be removed.
+    ///
+    /// [RFC 7396]: https://tools.ietf.org/html/rfc7396
+    pub fn apply_patch<'de, T: Any, D>(&self, target: &mut T, patch: D) -> Result<(), D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let typ = self
+            .known_type(TypeId::of::<T>())
+            .ok_or_else(|| deError::custom("type missing from db, cannot patch"))?;
+        MergeSeed(self, Place::Item(typ), target as *mut T as *mut u8).deserialize(patch)
+    }
+
+    /// Apply a JSON Patch ([RFC 6902]) to a value, in place.
+    ///
+    /// The patch must come from a self-describing format. `add` and `replace` both replace the
+    /// value at `path`, since reflected types have no optional members to add. `remove`
+    /// replaces with `null`, as `apply_patch` does, so only optional parts can be removed or
+    /// moved from. `test`, `copy` and `move` are supported. Operations are applied in order,
+    /// and if one fails the ones before it stay applied.
+    ///
+    /// [RFC 6902]: https://tools.ietf.org/html/rfc6902
+    pub fn apply_json_patch<'de, T: Any, D>(&self, target: &mut T, patch: D) -> Result<(), D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let typ = self
+            .known_type(TypeId::of::<T>())
+            .ok_or_else(|| deError::custom("type missing from db, cannot patch"))?;
+        let ops = match AnySeed.deserialize(patch)? {
+            ReflectValue::Seq(ops) => ops,
+            _ => {
+                return Err(deError::custom(
+                    "a JSON patch must be a sequence of operations",
+                ))
+            }
+        };
+        let root = target as *mut T as *mut u8;
+        for op in ops {
+            // SAFETY: root is a unique reference to a value of the registered type.
+            unsafe { self.apply_patch_op(Place::Item(typ), root, op) }.map_err(deError::custom)?;
+        }
+        Ok(())
+    }
+
+    /// SAFETY: `root` must point to an initialized value described by `place`.
+    unsafe fn apply_patch_op(
+        &self,
+        place: Place<'_, 'db>,
+        root: *mut u8,
+        op: ReflectValue,
+    ) -> Result<(), ValueError> {
+        let string = |key: &str| match op.get(key) {
+            Some(ReflectValue::Str(s)) => Ok(s.as_str()),
+            _ => Err(ValueError(alloc::format!(
+                "patch operation missing string member `{}`",
+                key
+            ))),
+        };
+        let value = || {
+            op.get("value")
+                .cloned()
+                .ok_or_else(|| ValueError("patch operation missing member `value`".into()))
+        };
+        let path = pointer(string("path")?)?;
+        match string("op")? {
+            "add" | "replace" => {
+                let value = value()?;
+                self.at_pointer(place, root, &path, |place, ptr| {
+                    let val = place.seed(self).deserialize(value)?;
+                    self.replace(place, ptr, val)
+                })
+            }
+            "remove" => self.at_pointer(place, root, &path, |place, ptr| {
+                let val = place.seed(self).deserialize(ReflectValue::Unit)?;
+                self.replace(place, ptr, val)
+            }),
+            "test" => {
+                let value = value()?;
+                self.at_pointer(place, root, &path, |place, ptr| {
+                    let expected = self.normalize(place, place.seed(self).deserialize(value)?)?;
+                    if expected == self.read(place, ptr)? {
+                        Ok(())
+                    } else {
+                        Err(ValueError(alloc::format!(
+                            "patch test failed at {}",
+                            string("path")?
+                        )))
+                    }
+                })
+            }
+            "copy" => {
+                let from = pointer(string("from")?)?;
+                let value =
+                    self.at_pointer(place, root, &from, |place, ptr| self.read(place, ptr))?;
+                self.at_pointer(place, root, &path, |place, ptr| {
+                    let val = place.seed(self).deserialize(value)?;
+                    self.replace(place, ptr, val)
+                })
+            }
+            "move" => {
+                let from = pointer(string("from")?)?;
+                let value =
+                    self.at_pointer(place, root, &from, |place, ptr| self.read(place, ptr))?;
+                if from == path {
+                    return Ok(());
+                }
+                if path.starts_with(&from) {
+                    return Err(ValueError(alloc::format!(
+                        "cannot move {} into itself",
+                        string("from")?
+                    )));
+                }
+                // check both halves before touching anything
+                let removed = self.at_pointer(place, root, &from, |place, _| {
+                    place.seed(self).deserialize(ReflectValue::Unit)
+                })?;
+                let value = self.at_pointer(place, root, &path, |place, _| {
+                    place.seed(self).deserialize(value)
+                })?;
+                self.at_pointer(place, root, &from, |place, ptr| {
+                    self.replace(place, ptr, removed)
+                })?;
+                self.at_pointer(place, root, &path, |place, ptr| {
+                    self.replace(place, ptr, value)
+                })
+            }
+            other => Err(ValueError(alloc::format!(
+                "unsupported patch operation `{}`",
+                other
+            ))),
+        }
+    }
+
+    /// Find the part of the value at `ptr` named by a JSON pointer, and call `f` with it.
+    ///
+    /// Newtypes are transparent, as they are in JSON. Going through an enum requires naming
+    /// the active variant.
+    ///
+    /// SAFETY: `ptr` must point to an initialized value described by `place`.
+    unsafe fn at_pointer<R>(
+        &self,
+        place: Place<'_, 'db>,
+        ptr: *mut u8,
+        path: &[String],
+        f: impl FnOnce(Place<'_, 'db>, *mut u8) -> Result<R, ValueError>,
+    ) -> Result<R, ValueError> {
+        let (seg, rest) = match path.split_first() {
+            Some(split) => split,
+            None => return f(place, ptr),
+        };
+        match place.resolve(self) {
+            Place::Item(typ) => match &typ.typ {
+                ItemDeclaration::Newtype(inner) => {
+                    self.at_pointer(Place::Item(inner), ptr, path, f)
+                }
+                ItemDeclaration::Struct(data) => self.data_at_pointer(data, ptr, path, f),
+                ItemDeclaration::Enum { variants, .. } => {
+                    let arm = active_arm(variants, ptr).map_err(|e| ValueError(e.into()))?;
+                    if arm.label != seg {
+                        return Err(ValueError(alloc::format!(
+                            "variant {} of {} is not active",
+                            seg,
+                            typ.name
+                        )));
+                    }
+                    self.data_at_pointer(&arm.variant, ptr, rest, f)
+                }
+            },
+            Place::Shape(shape) => match shape {
+                &DataShape::FixedArray(id, len) => {
+                    let ix = index(seg, len)?;
+                    let elt = element_shape(id);
+                    let stride = self.stride(id).map_err(ValueError)?;
+                    self.at_pointer(Place::Shape(&elt), ptr.add(ix * stride), rest, f)
+                }
+                DataShape::Tuple(fields) => {
+                    let field = &fields[index(seg, fields.len())?];
+                    let place = self.field_place(attrs_of!(field), &field.shape)?;
+                    self.at_pointer(place, ptr.add(field.offset), rest, f)
+                }
+                _ => Err(ValueError(alloc::format!(
+                    "cannot find `{}` inside a leaf",
+                    seg
+                ))),
+            },
+            Place::Hooked(..) => Err(ValueError(alloc::format!(
+                "cannot find `{}` inside a field with a hook",
+                seg
+            ))),
+        }
+    }
+
+    unsafe fn data_at_pointer<R>(
+        &self,
+        data: &VariantData<'db>,
+        ptr: *mut u8,
+        path: &[String],
+        f: impl FnOnce(Place<'_, 'db>, *mut u8) -> Result<R, ValueError>,
+    ) -> Result<R, ValueError> {
+        let (seg, rest) = match (data, path.split_first()) {
+            (_, None) => return Err(ValueError("pointer ends inside an enum".into())),
+            (VariantData::Tuple(fields), _) if fields.len() == 1 => {
+                let place = self.field_place(attrs_of!(fields[0]), &fields[0].shape)?;
+                return self.at_pointer(place, ptr.add(fields[0].offset), path, f);
+            }
+            (_, Some(split)) => split,
+        };
+        match data {
+            VariantData::Unit => Err(ValueError(alloc::format!("no field `{}`", seg))),
+            VariantData::Tuple(fields) => {
+                let field = &fields[index(seg, fields.len())?];
+                let place = self.field_place(attrs_of!(field), &field.shape)?;
+                self.at_pointer(place, ptr.add(field.offset), rest, f)
+            }
+            VariantData::Fields {
+                labels_for_serde,
+                fields,
+            } => {
+                let field = fields
+                    .iter()
+                    .enumerate()
+                    .find(|(ix, field)| field_label(labels_for_serde, *ix, field) == seg)
+                    .map(|(_, field)| field)
+                    .ok_or_else(|| ValueError(alloc::format!("no field `{}`", seg)))?;
+                let place = self.field_place(attrs_of!(field), &field.shape)?;
+                self.at_pointer(place, ptr.add(field.offset), rest, f)
+            }
+        }
+    }
+}
+
+/// Split a JSON pointer ([RFC 6901]) into its unescaped segments.
+///
+/// [RFC 6901]: https://tools.ietf.org/html/rfc6901
+fn pointer(s: &str) -> Result<Vec<String>, ValueError> {
+    if s.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !s.starts_with('/') {
+        return Err(ValueError(alloc::format!(
+            "JSON pointer `{}` does not start with `/`",
+            s
+        )));
+    }
+    Ok(s[1..]
+        .split('/')
+        .map(|seg| seg.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+fn index(seg: &str, len: usize) -> Result<usize, ValueError> {
+    seg.parse::<usize>()
+        .ok()
+        .filter(|&ix| ix < len)
+        .ok_or_else(|| ValueError(alloc::format!("`{}` is not an index below {}", seg, len)))
+}
+
+/// Deserializes a merge patch into a place, touching only what the patch mentions.
+struct MergeSeed<'a, 'db>(&'a Db<'db>, Place<'a, 'db>, *mut u8);
+
+impl<'de> DeserializeSeed<'de> for MergeSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D>(self, src: D) -> Result<(), D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let MergeSeed(db, place, ptr) = self;
+        let place = place.resolve(db);
+        match place {
+            Place::Item(ReflectedType {
+                name,
+                typ:
+                    ItemDeclaration::Struct(VariantData::Fields {
+                        labels_for_serde,
+                        fields,
+                    }),
+                ..
+            }) => src.deserialize_struct(
+                name,
+                labels_for_serde,
+                MergeFields(db, labels_for_serde, fields, ptr),
+            ),
+            _ => {
+                let val = place.seed(db).deserialize(src)?;
+                // SAFETY: MergeSeed is only built for initialized places.
+                unsafe { db.replace(place, ptr, val) }.map_err(deError::custom)
+            }
+        }
+    }
+}
+
+struct MergeFields<'a, 'db>(
+    &'a Db<'db>,
+    &'static [&'static str],
+    &'a [Field<'db>],
+    *mut u8,
+);
+
+impl<'de> serde::de::Visitor<'de> for MergeFields<'_, '_> {
+    type Value = ();
+
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        let MergeFields(db, labels_for_serde, fields, ptr) = self;
+        while let Some(key) = map.next_key::<String>()? {
+            let field = fields
+                .iter()
+                .enumerate()
+                .find(|(ix, f)| field_label(labels_for_serde, *ix, f) == key)
+                .map(|(_, f)| f)
+                .ok_or_else(|| A::Error::unknown_field(&key, labels_for_serde))?;
+            // SAFETY: the field is inside the value, by the correctness of the reflection data.
+            let field_ptr = unsafe { ptr.add(field.offset) };
+            let place = db
+                .field_place(attrs_of!(field), &field.shape)
+                .map_err(A::Error::custom)?;
+            map.next_value_seed(MergeSeed(db, place, field_ptr))?;
+        }
+        Ok(())
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fmt.write_str("a map of fields to patch")
+    }
+}
@@ -0,0 +1,32 @@
+use alloc::vec::Vec;
+
+/// One step along the way from a value to some part of it.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PathSegment {
+    /// A named field of a struct or struct variant.
+    Field(&'static str),
+    /// A positional field of a tuple, or an element of an array.
+    Index(usize),
+    /// Into the data of the named variant of an enum.
+    Variant(&'static str),
+}
+
+/// The location of some part of a value, relative to the value.
+///
+/// Displays like `config.servers[2]::Tcp.port`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FieldPath(pub Vec<PathSegment>);
+
+impl core::fmt::Display for FieldPath {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        for (ix, seg) in self.0.iter().enumerate() {
+            match seg {
+                PathSegment::Field(name) if ix == 0 => fmt.write_str(name)?,
+                PathSegment::Field(name) => write!(fmt, ".{}", name)?,
+                PathSegment::Index(i) => write!(fmt, "[{}]", i)?,
+                PathSegment::Variant(label) => write!(fmt, "::{}", label)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,497 @@
+//! Reading, writing and dropping reflected values in place.
+//!
+//! The values read out of memory and written into it are in the same form that `ValueSeed`
+//! deserializes, so any `ReflectValue` can be checked against a place by running it through
+//! `Place::seed` first.
+
+use crate::convert::{ValueError, ValueSerializer};
+use crate::hook::Hook;
+use crate::metadata::*;
+use crate::value::{element_shape, field_label, read_builtin, ReflectValue, ValueSeed};
+use crate::{active_arm, Db};
+
+use alloc::{boxed::Box, string::ToString};
+use core::alloc::Layout;
+
+/// Something in memory which can be described by reflection metadata.
+#[derive(Clone, Copy)]
+pub(crate) enum Place<'a, 'db> {
+    Item(&'a ReflectedType<'db>),
+    Shape(&'a DataShape<'db>),
+    /// A field of this shape, read and written with its `#[reflect(with = "...")]` hook.
+    Hooked(Hook, &'a DataShape<'db>),
+}
+
+impl<'a, 'db> Place<'a, 'db> {
+    /// Look through leaves which have a reflected type.
+    pub(crate) fn resolve(self, db: &'a Db<'db>) -> Self {
+        match self {
+            Place::Shape(&DataShape::Leaf(id)) => db.leaf_type(id).map_or(self, Place::Item),
+            _ => self,
+        }
+    }
+
+    /// Seed for deserializing a `ReflectValue` that can be written to this place.
+    pub(crate) fn seed(self, db: &'a Db<'db>) -> ValueSeed<'a, 'db> {
+        match self {
+            Place::Item(typ) => ValueSeed::Type(db, typ),
+            Place::Shape(shape) => ValueSeed::Shape(db, shape),
+            Place::Hooked(..) => ValueSeed::Any,
+        }
+    }
+}
+
+fn err(msg: &str) -> ValueError {
+    ValueError(msg.to_string())
+}
+
+impl<'db> Db<'db> {
+    /// The place of a field with `attrs` and `shape`, which goes through its hook if it has one.
+    pub(crate) fn field_place<'a>(
+        &self,
+        attrs: Option<&[Attr]>,
+        shape: &'a DataShape<'db>,
+    ) -> Result<Place<'a, 'db>, ValueError> {
+        Ok(match self.field_hook(attrs, shape)? {
+            Some(hook) => Place::Hooked(hook, shape),
+            None => Place::Shape(shape),
+        })
+    }
+
+    pub(crate) fn layout_of(&self, place: Place<'_, 'db>) -> Result<Layout, ValueError> {
+        match place.resolve(self) {
+            Place::Item(typ) => Ok(typ.layout),
+            Place::Shape(shape) => match shape {
+                DataShape::Builtin(builtin) => Ok(builtin.layout()),
+                &DataShape::Leaf(id) => self
+                    .lookup(|db| &db.leaf_layouts, &id)
+                    .copied()
+                    .ok_or_else(|| {
+                        ValueError(alloc::format!(
+                            "reflection db missing layout for typeid {:?}",
+                            id
+                        ))
+                    }),
+                &DataShape::FixedArray(id, len) => {
+                    let elt = self.layout_of(Place::Shape(&element_shape(id)))?;
+                    Layout::from_size_align(self.stride(id).map_err(ValueError)? * len, elt.align())
+                        .map_err(|_| err("array too large"))
+                }
+                DataShape::Tuple(fields) => {
+                    let (mut size, mut align) = (0, 1);
+                    for f in fields.iter() {
+                        let layout = self.layout_of(Place::Shape(&f.shape))?;
+                        size = size.max(f.offset + layout.size());
+                        align = align.max(layout.align());
+                    }
+                    Layout::from_size_align(size, align)
+                        .map(|layout| layout.pad_to_align())
+                        .map_err(|_| err("tuple too large"))
+                }
+                DataShape::Slice(_) | DataShape::Ref(_) => {
+                    Err(err("borrowed data has no owned layout"))
+                }
+            },
+            Place::Hooked(_, shape) => self.layout_of(Place::Shape(shape)),
+        }
+    }
+
+    /// Read the value at `ptr` into a `ReflectValue`.
+    ///
+    /// SAFETY: `ptr` must point to an initialized value described by `place`.
+    pub(crate) unsafe fn read(
+        &self,
+        place: Place<'_, 'db>,
+        ptr: *const u8,
+    ) -> Result<ReflectValue, ValueError> {
+        match place.resolve(self) {
+            Place::Item(typ) => match &typ.typ {
+                ItemDeclaration::Newtype(inner) => Ok(ReflectValue::Newtype(
+                    typ.name,
+                    Box::new(self.read(Place::Item(inner), ptr)?),
+                )),
+                ItemDeclaration::Struct(data) => self.read_data(typ.name, data, ptr),
+                ItemDeclaration::Enum { variants, .. } => {
+                    let arm = active_arm(variants, ptr).map_err(err)?;
+                    Ok(ReflectValue::Variant {
+                        name: typ.name,
+                        label: arm.label,
+                        variant_index: arm.variant_index as u32,
+                        data: Box::new(match self.read_data(arm.label, &arm.variant, ptr)? {
+                            ReflectValue::UnitStruct(_) => ReflectValue::Unit,
+                            data => data,
+                        }),
+                    })
+                }
+            },
+            Place::Shape(shape) => match shape {
+                &DataShape::Builtin(builtin) => Ok(read_builtin(builtin, ptr)),
+                &DataShape::Leaf(id) => self.serialize_leaf_at(ValueSerializer, id, ptr),
+                &DataShape::FixedArray(id, len) => {
+                    let elt = element_shape(id);
+                    let stride = self.stride(id).map_err(ValueError)?;
+                    (0..len)
+                        .map(|ix| self.read(Place::Shape(&elt), ptr.add(ix * stride)))
+                        .collect::<Result<_, _>>()
+                        .map(ReflectValue::Tuple)
+                }
+                DataShape::Tuple(fields) => fields
+                    .iter()
+                    .map(|f| {
+                        self.read(self.field_place(attrs_of!(f), &f.shape)?, ptr.add(f.offset))
+                    })
+                    .collect::<Result<_, _>>()
+                    .map(ReflectValue::Tuple),
+                DataShape::Slice(_) | DataShape::Ref(_) => Err(err("cannot read borrowed data")),
+            },
+            Place::Hooked(hook, _) => hook.serialize(ValueSerializer, ptr),
+        }
+    }
+
+    unsafe fn read_data(
+        &self,
+        name: &'static str,
+        data: &VariantData<'db>,
+        ptr: *const u8,
+    ) -> Result<ReflectValue, ValueError> {
+        match data {
+            VariantData::Unit => Ok(ReflectValue::UnitStruct(name)),
+            VariantData::Tuple(fields) if fields.len() == 1 => Ok(ReflectValue::Newtype(
+                name,
+                Box::new(self.read(
+                    self.field_place(attrs_of!(fields[0]), &fields[0].shape)?,
+                    ptr.add(fields[0].offset),
+                )?),
+            )),
+            VariantData::Tuple(fields) => fields
+                .iter()
+                .map(|f| self.read(self.field_place(attrs_of!(f), &f.shape)?, ptr.add(f.offset)))
+                .collect::<Result<_, _>>()
+                .map(|elts| ReflectValue::TupleStruct(name, elts)),
+            VariantData::Fields {
+                labels_for_serde,
+                fields,
+            } => fields
+                .iter()
+                .enumerate()
+                .map(|(ix, f)| {
+                    Ok((
+                        field_label(labels_for_serde, ix, f),
+                        self.read(self.field_place(attrs_of!(f), &f.shape)?, ptr.add(f.offset))?,
+                    ))
+                })
+                .collect::<Result<_, _>>()
+                .map(|fields| ReflectValue::Struct(name, fields)),
+        }
+    }
+
+    /// Write a value, as produced by `place.seed(db)`, to `ptr`.
+    ///
+    /// SAFETY: `ptr` must be valid for writes of the type described by `place`. Its previous
+    /// contents are not dropped. If this fails, whatever was written is dropped again.
+    pub(crate) unsafe fn write(
+        &self,
+        place: Place<'_, 'db>,
+        ptr: *mut u8,
+        val: ReflectValue,
+    ) -> Result<(), ValueError> {
+        match place.resolve(self) {
+            Place::Item(typ) => match (&typ.typ, val) {
+                (ItemDeclaration::Newtype(inner), ReflectValue::Newtype(_, val)) => {
+                    self.write(Place::Item(inner), ptr, *val)
+                }
+                (ItemDeclaration::Struct(data), val) => self.write_data(data, ptr, val),
+                (
+                    ItemDeclaration::Enum { variants, .. },
+                    ReflectValue::Variant { label, data, .. },
+                ) => {
+                    let arm = variants
+                        .iter()
+                        .find(|arm| arm.label == label)
+                        .ok_or_else(|| err("no such variant"))?;
+                    ptr.cast::<u16>().write(arm.discriminant);
+                    self.write_data(&arm.variant, ptr, *data)
+                }
+                _ => Err(err("value does not match the reflected type")),
+            },
+            Place::Shape(shape) => match shape {
+                &DataShape::Builtin(builtin) => write_builtin(builtin, ptr, val),
+                &DataShape::Leaf(id) => self
+                    .deserialize_leaf_at(val, id, ptr)
+                    .map_err(|e| ValueError(e.to_string())),
+                &DataShape::FixedArray(id, len) => match val {
+                    ReflectValue::Tuple(elts) if elts.len() == len => {
+                        let elt = element_shape(id);
+                        let stride = self.stride(id).map_err(ValueError)?;
+                        for (ix, val) in elts.into_iter().enumerate() {
+                            if let Err(e) =
+                                self.write(Place::Shape(&elt), ptr.add(ix * stride), val)
+                            {
+                                for done in 0..ix {
+                                    self.drop_in_place(Place::Shape(&elt), ptr.add(done * stride));
+                                }
+                                return Err(e);
+                            }
+                        }
+                        Ok(())
+                    }
+                    _ => Err(err("expected an array")),
+                },
+                DataShape::Tuple(fields) => self.write_tuple(fields, ptr, val),
+                DataShape::Slice(_) | DataShape::Ref(_) => Err(err("cannot write borrowed data")),
+            },
+            Place::Hooked(hook, _) => hook.deserialize(val, ptr),
+        }
+    }
+
+    unsafe fn write_tuple(
+        &self,
+        fields: &[TupleField<'db>],
+        ptr: *mut u8,
+        val: ReflectValue,
+    ) -> Result<(), ValueError> {
+        match val {
+            ReflectValue::Tuple(elts) | ReflectValue::TupleStruct(_, elts)
+                if elts.len() == fields.len() =>
+            {
+                for (ix, (f, val)) in fields.iter().zip(elts).enumerate() {
+                    let res = self
+                        .field_place(attrs_of!(f), &f.shape)
+                        .and_then(|place| self.write(place, ptr.add(f.offset), val));
+                    if let Err(e) = res {
+                        let written = fields[..ix].iter();
+                        self.drop_written(
+                            written.map(|f| (attrs_of!(f), &*f.shape, f.offset)),
+                            ptr,
+                        );
+                        return Err(e);
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(err("expected a tuple")),
+        }
+    }
+
+    unsafe fn write_data(
+        &self,
+        data: &VariantData<'db>,
+        ptr: *mut u8,
+        val: ReflectValue,
+    ) -> Result<(), ValueError> {
+        match (data, val) {
+            (VariantData::Unit, ReflectValue::Unit)
+            | (VariantData::Unit, ReflectValue::UnitStruct(_)) => Ok(()),
+            (VariantData::Tuple(fields), ReflectValue::Newtype(_, val)) if fields.len() == 1 => {
+                let place = self.field_place(attrs_of!(fields[0]), &fields[0].shape)?;
+                self.write(place, ptr.add(fields[0].offset), *val)
+            }
+            (VariantData::Tuple(fields), val) => self.write_tuple(fields, ptr, val),
+            (
+                VariantData::Fields {
+                    labels_for_serde,
+                    fields,
+                },
+                ReflectValue::Struct(_, mut vals),
+            ) => {
+                for (ix, f) in fields.iter().enumerate() {
+                    let label = field_label(labels_for_serde, ix, f);
+                    let res = match vals.iter().position(|(l, _)| *l == label) {
+                        Some(pos) => {
+                            let (_, val) = vals.swap_remove(pos);
+                            self.field_place(attrs_of!(f), &f.shape)
+                                .and_then(|place| self.write(place, ptr.add(f.offset), val))
+                        }
+                        None => Err(ValueError(alloc::format!("missing field {}", label))),
+                    };
+                    if let Err(e) = res {
+                        let written = fields[..ix].iter();
+                        self.drop_written(written.map(|f| (attrs_of!(f), &f.shape, f.offset)), ptr);
+                        return Err(e);
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(err("value does not match the reflected type")),
+        }
+    }
+
+    /// Drop the fields of the value at `ptr` which a failed write had already written.
+    ///
+    /// SAFETY: each field must be initialized, at its offset from `ptr`.
+    unsafe fn drop_written<'f>(
+        &self,
+        fields: impl Iterator<Item = (Option<&'f [Attr]>, &'f DataShape<'db>, usize)>,
+        ptr: *mut u8,
+    ) where
+        'db: 'f,
+    {
+        for (attrs, shape, offset) in fields {
+            self.drop_field(attrs, shape, ptr.add(offset));
+        }
+    }
+
+    /// Drop the field with `attrs` and `shape` at `ptr`, with its hook if it has one.
+    ///
+    /// SAFETY: as for `drop_in_place`.
+    unsafe fn drop_field(&self, attrs: Option<&[Attr]>, shape: &DataShape<'db>, ptr: *mut u8) {
+        let place = self
+            .field_place(attrs, shape)
+            .unwrap_or(Place::Shape(shape));
+        self.drop_in_place(place, ptr)
+    }
+
+    /// Drop the value at `ptr`.
+    ///
+    /// Types registered through `register_type`, `register_const` or `register_serde_leaf`, and
+    /// fields with a hook, are dropped with their own drop glue. Other types have their fields
+    /// dropped one by one, and leaves with no drop glue are leaked.
+    ///
+    /// SAFETY: `ptr` must point to an initialized value described by `place`, which must not
+    /// be used again.
+    pub(crate) unsafe fn drop_in_place(&self, place: Place<'_, 'db>, ptr: *mut u8) {
+        match place.resolve(self) {
+            Place::Item(typ) => {
+                if let Some(glue) = self.lookup(|db| &db.drop_glue, &typ.id) {
+                    return glue(ptr);
+                }
+                match &typ.typ {
+                    ItemDeclaration::Newtype(inner) => self.drop_in_place(Place::Item(inner), ptr),
+                    ItemDeclaration::Struct(data) => self.drop_data(data, ptr),
+                    ItemDeclaration::Enum { variants, .. } => {
+                        if let Ok(arm) = active_arm(variants, ptr) {
+                            self.drop_data(&arm.variant, ptr)
+                        }
+                    }
+                }
+            }
+            Place::Shape(shape) => match shape {
+                DataShape::Builtin(_) | DataShape::Slice(_) | DataShape::Ref(_) => {}
+                DataShape::Leaf(id) => {
+                    if let Some(glue) = self.lookup(|db| &db.drop_glue, id) {
+                        glue(ptr)
+                    }
+                }
+                &DataShape::FixedArray(id, len) => {
+                    let elt = element_shape(id);
+                    if let Ok(stride) = self.stride(id) {
+                        for ix in 0..len {
+                            self.drop_in_place(Place::Shape(&elt), ptr.add(ix * stride));
+                        }
+                    }
+                }
+                DataShape::Tuple(fields) => {
+                    for f in fields.iter() {
+                        self.drop_field(attrs_of!(f), &f.shape, ptr.add(f.offset));
+                    }
+                }
+            },
+            Place::Hooked(hook, _) => (hook.drop)(ptr),
+        }
+    }
+
+    unsafe fn drop_data(&self, data: &VariantData<'db>, ptr: *mut u8) {
+        match data {
+            VariantData::Unit => {}
+            VariantData::Tuple(fields) => {
+                for f in fields.iter() {
+                    self.drop_field(attrs_of!(f), &f.shape, ptr.add(f.offset));
+                }
+            }
+            VariantData::Fields { fields, .. } => {
+                for f in fields.iter() {
+                    self.drop_field(attrs_of!(f), &f.shape, ptr.add(f.offset));
+                }
+            }
+        }
+    }
+
+    /// Replace the value at `ptr` with `val`, as produced by `place.seed(db)`, dropping the
+    /// old value.
+    ///
+    /// The new value is built off to the side, so if this fails `ptr` is left untouched and
+    /// nothing is leaked.
+    ///
+    /// SAFETY: `ptr` must point to an initialized value described by `place`.
+    pub(crate) unsafe fn replace(
+        &self,
+        place: Place<'_, 'db>,
+        ptr: *mut u8,
+        val: ReflectValue,
+    ) -> Result<(), ValueError> {
+        let layout = self.layout_of(place)?;
+        with_scratch(layout, |scratch| {
+            self.write(place, scratch, val)?;
+            self.drop_in_place(place, ptr);
+            core::ptr::copy_nonoverlapping(scratch, ptr, layout.size());
+            Ok(())
+        })
+    }
+
+    /// `val`, as produced by `place.seed(db)`, in the form `read` gives it.
+    ///
+    /// The two can differ: a seed reads numbers in leaves as whatever width they were written
+    /// with, while `read` has them at the width of their type. So the value is written off to
+    /// the side and read back.
+    pub(crate) fn normalize(
+        &self,
+        place: Place<'_, 'db>,
+        val: ReflectValue,
+    ) -> Result<ReflectValue, ValueError> {
+        let layout = self.layout_of(place)?;
+        // SAFETY: the scratch space has the layout of `place`, and is only read once written.
+        unsafe {
+            with_scratch(layout, |scratch| {
+                self.write(place, scratch, val)?;
+                let val = self.read(place, scratch);
+                self.drop_in_place(place, scratch);
+                val
+            })
+        }
+    }
+}
+
+/// Call `f` with space for a value of `layout`, which is freed again afterwards.
+///
+/// SAFETY: `f` must leave nothing in the space that needs dropping.
+unsafe fn with_scratch<T>(layout: Layout, f: impl FnOnce(*mut u8) -> T) -> T {
+    if layout.size() == 0 {
+        return f(layout.align() as *mut u8);
+    }
+    let scratch = alloc::alloc::alloc(layout);
+    if scratch.is_null() {
+        alloc::alloc::handle_alloc_error(layout);
+    }
+    let res = f(scratch);
+    alloc::alloc::dealloc(scratch, layout);
+    res
+}
+
+/// SAFETY: `ptr` must be valid for writes of the builtin's type.
+pub(crate) unsafe fn write_builtin(
+    builtin: RustBuiltin,
+    ptr: *mut u8,
+    val: ReflectValue,
+) -> Result<(), ValueError> {
+    match (builtin, val) {
+        (RustBuiltin::U8, ReflectValue::U8(v)) => ptr.write(v),
+        (RustBuiltin::I8, ReflectValue::I8(v)) => ptr.cast::<i8>().write(v),
+        (RustBuiltin::U16, ReflectValue::U16(v)) => ptr.cast::<u16>().write(v),
+        (RustBuiltin::I16, ReflectValue::I16(v)) => ptr.cast::<i16>().write(v),
+        (RustBuiltin::U32, ReflectValue::U32(v)) => ptr.cast::<u32>().write(v),
+        (RustBuiltin::I32, ReflectValue::I32(v)) => ptr.cast::<i32>().write(v),
+        (RustBuiltin::U64, ReflectValue::U64(v)) => ptr.cast::<u64>().write(v),
+        (RustBuiltin::I64, ReflectValue::I64(v)) => ptr.cast::<i64>().write(v),
+        (RustBuiltin::U128, ReflectValue::U128(v)) => ptr.cast::<u128>().write(v),
+        (RustBuiltin::I128, ReflectValue::I128(v)) => ptr.cast::<i128>().write(v),
+        (RustBuiltin::BOOLIN, ReflectValue::Bool(v)) => ptr.cast::<bool>().write(v),
+        (RustBuiltin::CHAR, ReflectValue::Char(v)) => ptr.cast::<char>().write(v),
+        (builtin, _) => {
+            return Err(ValueError(alloc::format!(
+                "value does not match builtin {:?}",
+                builtin
+            )))
+        }
+    }
+    Ok(())
+}
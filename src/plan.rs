@@ -0,0 +1,925 @@
+use crate::de::{deserialize_builtin, MissingField, Seen};
+use crate::metadata::*;
+use crate::ser::serialize_builtin;
+use crate::value::{element_shape, field_label};
+use crate::{Db, DeserializeTrampoline, DropGlue, SerializeObject};
+
+use alloc::{boxed::Box, collections::BTreeMap, string::String, string::ToString, vec::Vec};
+use core::any::{Any, TypeId};
+use core::marker::PhantomData;
+use serde::de::{DeserializeSeed, Error as deError};
+use serde::ser::{
+    Error as serError, SerializeStruct, SerializeStructVariant, SerializeTuple,
+    SerializeTupleStruct, SerializeTupleVariant,
+};
+
+/// Every type in a `Db`, compiled for serialization and deserialization.
+///
+/// Compiling resolves each field to the plan for its type and builds perfect hash tables for
+/// field and variant labels, so that running a plan does no map lookups or label scans. The
+/// plans are a snapshot: types registered after `Db::compile` aren't in them.
+pub struct Plans {
+    nodes: Vec<Node>,
+    roots: BTreeMap<TypeId, usize>,
+    /// Drop glue of the nodes for types that have their own, to drop what a failed
+    /// deserialization wrote.
+    glue: BTreeMap<usize, DropGlue>,
+}
+
+enum Node {
+    Builtin(RustBuiltin),
+    /// A serde leaf, or a field with a hook.
+    Leaf {
+        object: SerializeObject,
+        deserialize: DeserializeTrampoline,
+    },
+    Newtype(&'static str, usize),
+    Struct(&'static str, Data),
+    Enum {
+        name: &'static str,
+        labels: &'static [&'static str],
+        /// In variant index order.
+        arms: Box<[Arm]>,
+        names: Box<[&'static str]>,
+        /// Discriminant and position in `arms`, sorted by discriminant.
+        by_discriminant: Box<[(u16, usize)]>,
+        table: LabelTable,
+    },
+    Tuple(Box<[Slot]>),
+    Array {
+        elt: usize,
+        len: usize,
+        stride: usize,
+    },
+    /// Types that can't be serialized, with the reason why. They only fail when reached.
+    Error(String),
+}
+
+/// A field: where it is, and the plan for its type.
+#[derive(Clone, Copy)]
+struct Slot {
+    offset: usize,
+    node: usize,
+}
+
+enum Data {
+    Unit,
+    /// A single-field tuple struct or variant, which serde treats as a newtype.
+    Newtype(Slot),
+    Tuple(Box<[Slot]>),
+    Fields {
+        labels: &'static [&'static str],
+        /// The label of each field, including those missing from `labels`.
+        names: Box<[&'static str]>,
+        fields: Box<[Slot]>,
+        table: LabelTable,
+    },
+}
+
+struct Arm {
+    label: &'static str,
+    variant_index: u32,
+    discriminant: u16,
+    data: Data,
+}
+
+impl<'db> Db<'db> {
+    /// Compile every registered type into a `Plans`.
+    pub fn compile(&self) -> Plans {
+        let mut compiler = Compiler {
+            db: self,
+            nodes: Vec::new(),
+            items: BTreeMap::new(),
+            leaves: BTreeMap::new(),
+            glue: BTreeMap::new(),
+        };
+        for typ in self.types().values() {
+            compiler.item(typ);
+        }
+        Plans {
+            nodes: compiler.nodes,
+            roots: compiler.items,
+            glue: compiler.glue,
+        }
+    }
+}
+
+impl Plans {
+    /// Serialize some value, like `Db::serialize`.
+    pub fn serialize<S: serde::Serializer, T: Any>(
+        &self,
+        s: S,
+        val: &T,
+    ) -> Result<S::Ok, S::Error> {
+        use serde::Serialize;
+        let node = self.root::<T>().map_err(S::Error::custom)?;
+        At {
+            plans: self,
+            node,
+            ptr: val as *const T as *const u8,
+        }
+        .serialize(s)
+    }
+
+    /// Deserialize some value, like `Db::deserialize`.
+    ///
+    /// If this fails, the parts of the value read so far are dropped again.
+    pub fn deserialize<'de, T: Any, D>(&self, src: D) -> Result<T, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let node = self.root::<T>().map_err(D::Error::custom)?;
+        let mut uninit = core::mem::MaybeUninit::<T>::uninit();
+        To {
+            plans: self,
+            node,
+            ptr: uninit.as_mut_ptr().cast(),
+        }
+        .deserialize(src)?;
+        // SAFETY: the plan for `T` initializes all of it, or fails.
+        Ok(unsafe { uninit.assume_init() })
+    }
+
+    fn root<T: Any>(&self) -> Result<usize, &'static str> {
+        self.roots
+            .get(&TypeId::of::<T>())
+            .copied()
+            .ok_or("type missing from plans")
+    }
+
+    /// Drop the value at `ptr`, as written by running plan `node`.
+    ///
+    /// SAFETY: `ptr` must point to an initialized value described by `node`, which must not be
+    /// used again.
+    unsafe fn drop_node(&self, node: usize, ptr: *mut u8) {
+        if let Some(glue) = self.glue.get(&node) {
+            return glue(ptr);
+        }
+        match &self.nodes[node] {
+            Node::Builtin(_) | Node::Leaf { .. } | Node::Error(_) => {}
+            &Node::Newtype(_, inner) => self.drop_node(inner, ptr),
+            Node::Struct(_, data) => self.drop_data(data, ptr),
+            Node::Enum {
+                arms,
+                by_discriminant,
+                ..
+            } => {
+                let tag = ptr.cast::<u16>().read();
+                if let Ok(ix) = by_discriminant.binary_search_by_key(&tag, |&(d, _)| d) {
+                    self.drop_data(&arms[by_discriminant[ix].1].data, ptr)
+                }
+            }
+            Node::Tuple(fields) => self.drop_slots(fields, ptr),
+            &Node::Array { elt, len, stride } => {
+                for ix in 0..len {
+                    self.drop_node(elt, ptr.add(ix * stride));
+                }
+            }
+        }
+    }
+
+    /// SAFETY: as for `drop_node`, with the value holding `data` at `ptr`.
+    unsafe fn drop_data(&self, data: &Data, ptr: *mut u8) {
+        match data {
+            Data::Unit => {}
+            Data::Newtype(slot) => self.drop_slots(core::slice::from_ref(slot), ptr),
+            Data::Tuple(fields) | Data::Fields { fields, .. } => self.drop_slots(fields, ptr),
+        }
+    }
+
+    /// SAFETY: as for `drop_node`, with each of `slots` initialized in the value at `ptr`.
+    unsafe fn drop_slots(&self, slots: &[Slot], ptr: *mut u8) {
+        for slot in slots {
+            self.drop_node(slot.node, ptr.add(slot.offset));
+        }
+    }
+}
+
+struct Compiler<'a, 'db> {
+    db: &'a Db<'db>,
+    nodes: Vec<Node>,
+    /// Plans of reflected types, which may be recursive.
+    items: BTreeMap<TypeId, usize>,
+    /// Plans of builtins and serde leaves, shared between their uses.
+    leaves: BTreeMap<TypeId, usize>,
+    glue: BTreeMap<usize, DropGlue>,
+}
+
+impl<'db> Compiler<'_, 'db> {
+    fn push(&mut self, node: Node) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    fn item(&mut self, typ: &ReflectedType<'db>) -> usize {
+        if let Some(&ix) = self.items.get(&typ.id) {
+            return ix;
+        }
+        // placeholder, so that recursive uses find it
+        let ix = self.push(Node::Error(String::new()));
+        self.items.insert(typ.id, ix);
+        self.add_glue(ix, typ.id);
+        self.nodes[ix] = match self.item_node(typ) {
+            Ok(node) => node,
+            Err(e) => Node::Error(e),
+        };
+        ix
+    }
+
+    fn item_node(&mut self, typ: &ReflectedType<'db>) -> Result<Node, String> {
+        Ok(match &typ.typ {
+            ItemDeclaration::Newtype(inner) => Node::Newtype(typ.name, self.item(inner)),
+            ItemDeclaration::Struct(data) => Node::Struct(typ.name, self.data(typ.name, data)?),
+            ItemDeclaration::Enum {
+                variant_labels_for_serde,
+                variants,
+            } => {
+                let mut arms: Vec<Arm> = variants
+                    .iter()
+                    .map(|arm| {
+                        Ok(Arm {
+                            label: arm.label,
+                            variant_index: arm.variant_index as u32,
+                            discriminant: arm.discriminant,
+                            data: self.data(typ.name, &arm.variant)?,
+                        })
+                    })
+                    .collect::<Result<_, String>>()?;
+                arms.sort_by_key(|arm| arm.variant_index);
+                let mut by_discriminant: Vec<(u16, usize)> = arms
+                    .iter()
+                    .enumerate()
+                    .map(|(ix, arm)| (arm.discriminant, ix))
+                    .collect();
+                by_discriminant.sort_unstable();
+                let names: Box<[&'static str]> = arms.iter().map(|arm| arm.label).collect();
+                Node::Enum {
+                    name: typ.name,
+                    labels: variant_labels_for_serde,
+                    table: LabelTable::new(&names).map_err(|label| {
+                        alloc::format!("`{}` has two variants labelled `{}`", typ.name, label)
+                    })?,
+                    names,
+                    arms: arms.into(),
+                    by_discriminant: by_discriminant.into(),
+                }
+            }
+        })
+    }
+
+    /// Plan `data`, of the type called `name`.
+    fn data(&mut self, name: &str, data: &VariantData<'db>) -> Result<Data, String> {
+        Ok(match data {
+            VariantData::Unit => Data::Unit,
+            VariantData::Tuple(fields) if fields.len() == 1 => {
+                Data::Newtype(self.field(attrs_of!(fields[0]), fields[0].offset, &fields[0].shape))
+            }
+            VariantData::Tuple(fields) => Data::Tuple(self.tuple(fields)),
+            VariantData::Fields {
+                labels_for_serde,
+                fields,
+            } => {
+                let names: Box<[&'static str]> = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(ix, f)| field_label(labels_for_serde, ix, f))
+                    .collect();
+                Data::Fields {
+                    labels: labels_for_serde,
+                    table: LabelTable::new(&names).map_err(|label| {
+                        alloc::format!("`{}` has two fields labelled `{}`", name, label)
+                    })?,
+                    names,
+                    fields: fields
+                        .iter()
+                        .map(|f| self.field(attrs_of!(f), f.offset, &f.shape))
+                        .collect(),
+                }
+            }
+        })
+    }
+
+    fn tuple(&mut self, fields: &[TupleField<'db>]) -> Box<[Slot]> {
+        fields
+            .iter()
+            .map(|f| self.field(attrs_of!(f), f.offset, &f.shape))
+            .collect()
+    }
+
+    /// A field, serialized with its hook if it has one.
+    fn field(&mut self, attrs: Option<&[Attr]>, offset: usize, shape: &DataShape<'db>) -> Slot {
+        let node = match self.db.field_hook(attrs, shape) {
+            Ok(Some(hook)) => {
+                let ix = self.push(Node::Leaf {
+                    object: hook.object,
+                    deserialize: hook.deserialize,
+                });
+                self.glue.insert(ix, hook.drop);
+                ix
+            }
+            Ok(None) => self.shape(shape),
+            Err(e) => self.push(Node::Error(e.0)),
+        };
+        Slot { offset, node }
+    }
+
+    fn shape(&mut self, shape: &DataShape<'db>) -> usize {
+        let db = self.db;
+        match shape {
+            &DataShape::Builtin(builtin) => self.leaf(builtin.type_id(), || Node::Builtin(builtin)),
+            &DataShape::Leaf(id) => match db.leaf_type(id) {
+                Some(typ) => self.item(typ),
+                None => self.leaf(id, || {
+                    match (
+                        db.lookup(|db| &db.serialize_objects, &id),
+                        db.lookup(|db| &db.deserialize_trampolines, &id),
+                    ) {
+                        (Some(&object), Some(&deserialize)) => Node::Leaf {
+                            object,
+                            deserialize,
+                        },
+                        _ => Node::Error("leaf type missing from reflection db".into()),
+                    }
+                }),
+            },
+            &DataShape::FixedArray(id, len) => {
+                let elt = self.shape(&element_shape(id));
+                match db.stride(id) {
+                    Ok(stride) => self.push(Node::Array { elt, len, stride }),
+                    Err(e) => self.push(Node::Error(e)),
+                }
+            }
+            DataShape::Tuple(fields) => {
+                let slots = self.tuple(fields);
+                self.push(Node::Tuple(slots))
+            }
+            DataShape::Slice(_) | DataShape::Ref(_) => {
+                self.push(Node::Error("cannot deserialize borrowed data".to_string()))
+            }
+        }
+    }
+
+    fn leaf(&mut self, id: TypeId, node: impl FnOnce() -> Node) -> usize {
+        if let Some(&ix) = self.leaves.get(&id) {
+            return ix;
+        }
+        let ix = self.push(node());
+        self.leaves.insert(id, ix);
+        self.add_glue(ix, id);
+        ix
+    }
+
+    /// Give node `ix` the drop glue registered for type `id`, if there is any.
+    fn add_glue(&mut self, ix: usize, id: TypeId) {
+        if let Some(&glue) = self.db.lookup(|db| &db.drop_glue, &id) {
+            self.glue.insert(ix, glue);
+        }
+    }
+}
+
+/// A perfect hash table from labels to their positions.
+struct LabelTable {
+    seed: u32,
+    /// Position of the label hashing to each slot, or `u16::MAX` for none. Empty if the
+    /// labels are searched in order instead.
+    slots: Box<[u16]>,
+}
+
+impl LabelTable {
+    /// A table for `labels`, or the label they have twice, for which there can't be one.
+    ///
+    /// Labels the table can't be made for in a few tries are searched in order instead.
+    fn new<'l>(labels: &[&'l str]) -> Result<LabelTable, &'l str> {
+        let mut sorted = labels.to_vec();
+        sorted.sort_unstable();
+        if let Some(pair) = sorted.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(pair[0]);
+        }
+        let mut size = (labels.len() * 2).next_power_of_two();
+        while labels.len() < u16::MAX as usize && size <= labels.len().max(1) * 64 {
+            for seed in 0..32 {
+                let mut slots = alloc::vec![u16::MAX; size];
+                let collision = labels.iter().enumerate().any(|(ix, label)| {
+                    let slot = &mut slots[hash(seed, label.as_bytes()) as usize & (size - 1)];
+                    let taken = *slot != u16::MAX;
+                    *slot = ix as u16;
+                    taken
+                });
+                if !collision {
+                    return Ok(LabelTable {
+                        seed,
+                        slots: slots.into(),
+                    });
+                }
+            }
+            size *= 2;
+        }
+        Ok(LabelTable {
+            seed: 0,
+            slots: Box::new([]),
+        })
+    }
+
+    /// The position of `key` among `labels`, which the table was built from.
+    fn find(&self, labels: &[&str], key: &[u8]) -> Option<usize> {
+        if self.slots.is_empty() {
+            return labels.iter().position(|label| label.as_bytes() == key);
+        }
+        let slot = hash(self.seed, key) as usize & (self.slots.len() - 1);
+        let ix = self.slots[slot] as usize;
+        labels
+            .get(ix)
+            .filter(|label| label.as_bytes() == key)
+            .map(|_| ix)
+    }
+}
+
+/// FNV-1a, starting from `seed`.
+fn hash(seed: u32, key: &[u8]) -> u32 {
+    let mut h = 0x811c_9dc5 ^ seed.wrapping_mul(0x9e37_79b9);
+    for &b in key {
+        h ^= b as u32;
+        h = h.wrapping_mul(0x0100_0193);
+    }
+    h
+}
+
+/// Serializes the value at `ptr` following a plan.
+#[derive(Clone, Copy)]
+struct At<'a> {
+    plans: &'a Plans,
+    node: usize,
+    ptr: *const u8,
+}
+
+impl<'a> At<'a> {
+    fn slot(&self, slot: &Slot) -> At<'a> {
+        At {
+            plans: self.plans,
+            node: slot.node,
+            // SAFETY: the slot describes a field of the value at `ptr`.
+            ptr: unsafe { self.ptr.add(slot.offset) },
+        }
+    }
+}
+
+impl serde::Serialize for At<'_> {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let ptr = self.ptr;
+        // SAFETY: the plan describes the value at `ptr`, which is initialized.
+        match &self.plans.nodes[self.node] {
+            &Node::Builtin(builtin) => unsafe { serialize_builtin(s, builtin, ptr) },
+            &Node::Leaf { object, .. } => {
+                // SAFETY: `object` came from register_serde_leaf or register_with, for this type.
+                let obj = unsafe { &*object(ptr) };
+                erased_serde::serialize(obj, s)
+            }
+            &Node::Newtype(name, inner) => s.serialize_newtype_struct(
+                name,
+                &At {
+                    node: inner,
+                    ..*self
+                },
+            ),
+            Node::Struct(name, data) => match data {
+                Data::Unit => s.serialize_unit_struct(name),
+                Data::Newtype(slot) => s.serialize_newtype_struct(name, &self.slot(slot)),
+                Data::Tuple(fields) => {
+                    let mut tup = s.serialize_tuple_struct(name, fields.len())?;
+                    for f in fields.iter() {
+                        tup.serialize_field(&self.slot(f))?;
+                    }
+                    tup.end()
+                }
+                Data::Fields { names, fields, .. } => {
+                    let mut struc = s.serialize_struct(name, fields.len())?;
+                    for (label, f) in names.iter().zip(fields.iter()) {
+                        struc.serialize_field(label, &self.slot(f))?;
+                    }
+                    struc.end()
+                }
+            },
+            Node::Enum {
+                name,
+                arms,
+                by_discriminant,
+                ..
+            } => {
+                let tag = unsafe { ptr.cast::<u16>().read() };
+                let arm = match by_discriminant.binary_search_by_key(&tag, |&(d, _)| d) {
+                    Ok(ix) => &arms[by_discriminant[ix].1],
+                    Err(_) => {
+                        return Err(S::Error::custom(
+                            "runtime discriminant not described in reflection db",
+                        ))
+                    }
+                };
+                let (label, index) = (arm.label, arm.variant_index);
+                match &arm.data {
+                    Data::Unit => s.serialize_unit_variant(name, index, label),
+                    Data::Newtype(slot) => {
+                        s.serialize_newtype_variant(name, index, label, &self.slot(slot))
+                    }
+                    Data::Tuple(fields) => {
+                        let mut tup =
+                            s.serialize_tuple_variant(name, index, label, fields.len())?;
+                        for f in fields.iter() {
+                            tup.serialize_field(&self.slot(f))?;
+                        }
+                        tup.end()
+                    }
+                    Data::Fields { names, fields, .. } => {
+                        let mut struc =
+                            s.serialize_struct_variant(name, index, label, fields.len())?;
+                        for (label, f) in names.iter().zip(fields.iter()) {
+                            struc.serialize_field(label, &self.slot(f))?;
+                        }
+                        struc.end()
+                    }
+                }
+            }
+            Node::Tuple(fields) => {
+                let mut tup = s.serialize_tuple(fields.len())?;
+                for f in fields.iter() {
+                    tup.serialize_element(&self.slot(f))?;
+                }
+                tup.end()
+            }
+            &Node::Array { elt, len, stride } => {
+                let mut tup = s.serialize_tuple(len)?;
+                for ix in 0..len {
+                    tup.serialize_element(&At {
+                        node: elt,
+                        ptr: unsafe { ptr.add(ix * stride) },
+                        ..*self
+                    })?;
+                }
+                tup.end()
+            }
+            Node::Error(e) => Err(S::Error::custom(e)),
+        }
+    }
+}
+
+/// Deserializes into `ptr` following a plan.
+#[derive(Clone, Copy)]
+struct To<'a> {
+    plans: &'a Plans,
+    node: usize,
+    ptr: *mut u8,
+}
+
+impl<'a> To<'a> {
+    fn slot(&self, slot: &Slot) -> To<'a> {
+        To {
+            plans: self.plans,
+            node: slot.node,
+            // SAFETY: the slot describes a field of the value at `ptr`.
+            ptr: unsafe { self.ptr.add(slot.offset) },
+        }
+    }
+
+    /// Drop the fields in `slots`, after a later field failed to deserialize.
+    ///
+    /// SAFETY: each of `slots` must have been deserialized into.
+    unsafe fn drop_written<'s>(&self, slots: impl Iterator<Item = &'s Slot>) {
+        for slot in slots {
+            self.plans.drop_node(slot.node, self.ptr.add(slot.offset));
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for To<'_> {
+    type Value = ();
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, d: D) -> Result<(), D::Error> {
+        let ptr = self.ptr;
+        match &self.plans.nodes[self.node] {
+            // SAFETY: the plan describes the value at `ptr`, which is valid for writes.
+            &Node::Builtin(builtin) => unsafe { deserialize_builtin(d, builtin, ptr) },
+            &Node::Leaf { deserialize, .. } => {
+                deserialize(&mut <dyn erased_serde::Deserializer>::erase(d), ptr)
+                    .map_err(|e| D::Error::custom(e.to_string()))
+            }
+            &Node::Newtype(name, inner) => d.deserialize_newtype_struct(
+                name,
+                NewtypeVisitor(To {
+                    node: inner,
+                    ..self
+                }),
+            ),
+            Node::Struct(name, data) => match data {
+                Data::Unit => d.deserialize_unit_struct(name, UnitVisitor),
+                Data::Newtype(slot) => {
+                    d.deserialize_newtype_struct(name, NewtypeVisitor(self.slot(slot)))
+                }
+                Data::Tuple(fields) => {
+                    d.deserialize_tuple_struct(name, fields.len(), TupleVisitor(self, fields))
+                }
+                Data::Fields {
+                    labels,
+                    names,
+                    fields,
+                    table,
+                } => d.deserialize_struct(
+                    name,
+                    labels,
+                    FieldsVisitor {
+                        to: self,
+                        labels: LabelIx {
+                            labels,
+                            names,
+                            table,
+                            variant: false,
+                        },
+                        fields,
+                    },
+                ),
+            },
+            Node::Enum { name, labels, .. } => d.deserialize_enum(name, labels, EnumVisitor(self)),
+            Node::Tuple(fields) => d.deserialize_tuple(fields.len(), TupleVisitor(self, fields)),
+            &Node::Array { len, .. } => d.deserialize_tuple(len, ArrayVisitor(self)),
+            Node::Error(e) => Err(D::Error::custom(e)),
+        }
+    }
+}
+
+struct UnitVisitor;
+impl<'de> serde::de::Visitor<'de> for UnitVisitor {
+    type Value = ();
+
+    fn visit_unit<E: deError>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fmt.write_str("a unit struct")
+    }
+}
+
+struct NewtypeVisitor<'a>(To<'a>);
+impl<'de> serde::de::Visitor<'de> for NewtypeVisitor<'_> {
+    type Value = ();
+
+    fn visit_newtype_struct<D>(self, d: D) -> Result<(), D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        self.0.deserialize(d)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        seq.next_element_seed(self.0)?
+            .ok_or_else(|| A::Error::invalid_length(0, &self))
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fmt.write_str("a newtype struct")
+    }
+}
+
+struct TupleVisitor<'a>(To<'a>, &'a [Slot]);
+impl<'de> serde::de::Visitor<'de> for TupleVisitor<'_> {
+    type Value = ();
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        for (ix, f) in self.1.iter().enumerate() {
+            let err = match seq.next_element_seed(self.0.slot(f)) {
+                Ok(Some(())) => continue,
+                Ok(None) => A::Error::invalid_length(ix, &self),
+                Err(e) => e,
+            };
+            // SAFETY: the fields before `ix` were deserialized.
+            unsafe { self.0.drop_written(self.1[..ix].iter()) };
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(fmt, "a sequence of {} elements", self.1.len())
+    }
+}
+
+struct ArrayVisitor<'a>(To<'a>);
+impl<'de> serde::de::Visitor<'de> for ArrayVisitor<'_> {
+    type Value = ();
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let to = self.0;
+        let (elt, len, stride) = match to.plans.nodes[to.node] {
+            Node::Array { elt, len, stride } => (elt, len, stride),
+            _ => unreachable!(),
+        };
+        for ix in 0..len {
+            let slot = To {
+                node: elt,
+                // SAFETY: the array has `len` elements, `stride` apart.
+                ptr: unsafe { to.ptr.add(ix * stride) },
+                ..to
+            };
+            let err = match seq.next_element_seed(slot) {
+                Ok(Some(())) => continue,
+                Ok(None) => A::Error::invalid_length(ix, &self),
+                Err(e) => e,
+            };
+            for done in 0..ix {
+                // SAFETY: the elements before `ix` were deserialized.
+                unsafe { to.plans.drop_node(elt, to.ptr.add(done * stride)) };
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fmt.write_str("an array")
+    }
+}
+
+struct FieldsVisitor<'a> {
+    to: To<'a>,
+    labels: LabelIx<'a>,
+    fields: &'a [Slot],
+}
+
+impl<'de> serde::de::Visitor<'de> for FieldsVisitor<'_> {
+    type Value = ();
+
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        let names = self.labels.names;
+        let mut seen = Seen::new(names.len());
+        let res = (|| {
+            while let Some(ix) = map.next_key_seed(self.labels)? {
+                if ix == names.len() {
+                    map.next_value::<serde::de::IgnoredAny>()?;
+                    continue;
+                }
+                if seen.contains(ix) {
+                    return Err(A::Error::duplicate_field(names[ix]));
+                }
+                map.next_value_seed(self.to.slot(&self.fields[ix]))?;
+                seen.insert(ix);
+            }
+            for (ix, slot) in self.fields.iter().enumerate() {
+                if !seen.contains(ix) {
+                    self.to
+                        .slot(slot)
+                        .deserialize(MissingField(names[ix], PhantomData))?;
+                    seen.insert(ix);
+                }
+            }
+            Ok(())
+        })();
+        if res.is_err() {
+            let written = (0..names.len()).filter(|&ix| seen.contains(ix));
+            // SAFETY: the fields seen were deserialized.
+            unsafe { self.to.drop_written(written.map(|ix| &self.fields[ix])) };
+        }
+        res
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<(), A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        TupleVisitor(self.to, self.fields).visit_seq(seq)
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fmt.write_str("a struct")
+    }
+}
+
+/// Finds a field or variant by label or index.
+#[derive(Clone, Copy)]
+struct LabelIx<'a> {
+    /// What serde knows the labels as.
+    labels: &'static [&'static str],
+    names: &'a [&'static str],
+    table: &'a LabelTable,
+    variant: bool,
+}
+
+impl LabelIx<'_> {
+    fn unknown<E: deError>(&self, label: &str) -> E {
+        if self.variant {
+            E::unknown_variant(label, self.labels)
+        } else {
+            E::unknown_field(label, self.labels)
+        }
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for LabelIx<'_> {
+    type Value = usize;
+
+    fn visit_u64<E: deError>(self, v: u64) -> Result<usize, E> {
+        if (v as usize) < self.names.len() {
+            Ok(v as usize)
+        } else {
+            Err(self.unknown(&v.to_string()))
+        }
+    }
+
+    fn visit_str<E: deError>(self, v: &str) -> Result<usize, E> {
+        self.visit_bytes(v.as_bytes())
+    }
+
+    fn visit_bytes<E: deError>(self, v: &[u8]) -> Result<usize, E> {
+        match self.table.find(self.names, v) {
+            Some(ix) => Ok(ix),
+            // an unknown field is skipped, as with serde_derive: it is given index `names.len()`,
+            // past every field
+            None if !self.variant => Ok(self.names.len()),
+            None => Err(self.unknown(core::str::from_utf8(v).unwrap_or("<non-utf8 label>"))),
+        }
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fmt.write_str(if self.variant {
+            "a variant label"
+        } else {
+            "a field label"
+        })
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for LabelIx<'_> {
+    type Value = usize;
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, d: D) -> Result<usize, D::Error> {
+        d.deserialize_identifier(self)
+    }
+}
+
+struct EnumVisitor<'a>(To<'a>);
+impl<'de> serde::de::Visitor<'de> for EnumVisitor<'_> {
+    type Value = ();
+
+    fn visit_enum<A>(self, data: A) -> Result<(), A::Error>
+    where
+        A: serde::de::EnumAccess<'de>,
+    {
+        use serde::de::VariantAccess;
+        let to = self.0;
+        let (labels, names, arms, table) = match &to.plans.nodes[to.node] {
+            Node::Enum {
+                labels,
+                names,
+                arms,
+                table,
+                ..
+            } => (*labels, names, arms, table),
+            _ => unreachable!(),
+        };
+        let (ix, variant) = data.variant_seed(LabelIx {
+            labels,
+            names,
+            table,
+            variant: true,
+        })?;
+        let arm = &arms[ix];
+        // SAFETY: `ptr` is an enum, with its discriminant first.
+        unsafe { to.ptr.cast::<u16>().write(arm.discriminant) };
+        match &arm.data {
+            Data::Unit => variant.unit_variant(),
+            Data::Newtype(slot) => variant.newtype_variant_seed(to.slot(slot)),
+            Data::Tuple(fields) => variant.tuple_variant(fields.len(), TupleVisitor(to, fields)),
+            Data::Fields {
+                labels,
+                names,
+                fields,
+                table,
+            } => variant.struct_variant(
+                labels,
+                FieldsVisitor {
+                    to,
+                    labels: LabelIx {
+                        labels,
+                        names,
+                        table,
+                        variant: false,
+                    },
+                    fields,
+                },
+            ),
+        }
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fmt.write_str("an enum")
+    }
+}
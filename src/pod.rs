@@ -0,0 +1,199 @@
+use crate::convert::ValueError;
+use crate::hook::hook_name;
+use crate::metadata::*;
+use crate::value::element_shape;
+use crate::Db;
+
+use alloc::vec::Vec;
+use core::any::{Any, TypeId};
+
+/// The layout of a plain-old-data type: builtins with no padding between them, in the same
+/// order in memory as they are declared.
+pub(crate) struct Pod {
+    pub(crate) size: usize,
+    runs: Vec<Run>,
+    /// Whether only single byte integers are allowed in, and not fields with hooks, as for
+    /// the compact codec.
+    bytes_only: bool,
+}
+
+/// `count` consecutive builtins of the same type.
+struct Run {
+    offset: usize,
+    builtin: RustBuiltin,
+    count: usize,
+}
+
+impl Pod {
+    fn new(bytes_only: bool) -> Pod {
+        Pod {
+            size: 0,
+            runs: Vec::new(),
+            bytes_only,
+        }
+    }
+
+    /// Add `count` of `builtin` at `offset`, if they come right after what is already there.
+    fn push(&mut self, offset: usize, builtin: RustBuiltin, count: usize) -> Option<()> {
+        let byte = matches!(builtin, RustBuiltin::U8 | RustBuiltin::I8);
+        if offset != self.size || (self.bytes_only && !byte) {
+            return None;
+        }
+        self.size += builtin.size() * count;
+        match self.runs.last_mut() {
+            Some(last) if last.builtin == builtin => last.count += count,
+            _ => self.runs.push(Run {
+                offset,
+                builtin,
+                count,
+            }),
+        }
+        Some(())
+    }
+
+    /// Check that the little-endian image `bytes` holds valid bools and chars.
+    fn validate(&self, bytes: &[u8]) -> Result<(), ValueError> {
+        for run in &self.runs {
+            let size = run.builtin.size();
+            for ix in 0..run.count {
+                let at = run.offset + ix * size;
+                match run.builtin {
+                    RustBuiltin::BOOLIN if bytes[at] > 1 => {
+                        return Err(ValueError(alloc::format!("invalid bool at byte {}", at)))
+                    }
+                    RustBuiltin::CHAR => {
+                        let mut le = [0; 4];
+                        le.copy_from_slice(&bytes[at..at + 4]);
+                        if core::char::from_u32(u32::from_le_bytes(le)).is_none() {
+                            return Err(ValueError(alloc::format!("invalid char at byte {}", at)));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Swap the bytes of every builtin in `bytes` between little-endian and native order.
+    fn swap_native_le(&self, bytes: &mut [u8]) {
+        if cfg!(target_endian = "little") {
+            return;
+        }
+        for run in &self.runs {
+            let size = run.builtin.size();
+            for ix in 0..run.count {
+                let at = run.offset + ix * size;
+                bytes[at..at + size].reverse();
+            }
+        }
+    }
+}
+
+impl<'db> Db<'db> {
+    /// Whether `T` is plain old data: builtins and fixed arrays of them, with no padding, in
+    /// declaration order.
+    pub fn is_pod<T: Any>(&self) -> bool {
+        self.known_type(TypeId::of::<T>())
+            .and_then(|typ| self.pod_item(typ, false))
+            .is_some()
+    }
+
+    /// The bytes of a plain old data value, with every builtin in little-endian order.
+    ///
+    /// This is a single copy on little-endian targets.
+    pub fn to_pod_bytes<T: Any>(&self, val: &T) -> Result<Vec<u8>, ValueError> {
+        let pod = self.pod_of::<T>()?;
+        // SAFETY: `val` is a `T`, which is `pod.size` bytes with no padding.
+        let mut bytes =
+            unsafe { core::slice::from_raw_parts(val as *const T as *const u8, pod.size) }.to_vec();
+        pod.swap_native_le(&mut bytes);
+        Ok(bytes)
+    }
+
+    /// Read a plain old data value written by `to_pod_bytes`.
+    pub fn from_pod_bytes<T: Any>(&self, bytes: &[u8]) -> Result<T, ValueError> {
+        let pod = self.pod_of::<T>()?;
+        if bytes.len() != pod.size {
+            return Err(ValueError(alloc::format!(
+                "expected {} bytes, got {}",
+                pod.size,
+                bytes.len()
+            )));
+        }
+        pod.validate(bytes)?;
+        let mut uninit = core::mem::MaybeUninit::<T>::uninit();
+        // SAFETY: `T` is `pod.size` bytes of builtins, and we just checked they are valid.
+        unsafe {
+            let dst = core::slice::from_raw_parts_mut(uninit.as_mut_ptr().cast(), pod.size);
+            dst.copy_from_slice(bytes);
+            pod.swap_native_le(dst);
+            Ok(uninit.assume_init())
+        }
+    }
+
+    fn pod_of<T: Any>(&self) -> Result<Pod, ValueError> {
+        let typ = self
+            .known_type(TypeId::of::<T>())
+            .ok_or_else(|| ValueError("type missing from db".into()))?;
+        self.pod_item(typ, false)
+            .ok_or_else(|| ValueError(alloc::format!("{} is not plain old data", typ.name)))
+    }
+
+    /// The layout of `typ` if it is plain old data, made only of single byte integers if
+    /// `bytes_only`.
+    pub(crate) fn pod_item(&self, typ: &ReflectedType<'db>, bytes_only: bool) -> Option<Pod> {
+        let mut pod = Pod::new(bytes_only);
+        self.add_item(typ, 0, &mut pod)?;
+        // no padding at the end either
+        Some(pod).filter(|pod| pod.size == typ.layout.size())
+    }
+
+    fn add_item(&self, typ: &ReflectedType<'db>, base: usize, pod: &mut Pod) -> Option<()> {
+        match &typ.typ {
+            ItemDeclaration::Newtype(inner) => self.add_item(inner, base, pod),
+            ItemDeclaration::Struct(VariantData::Unit) => Some(()),
+            ItemDeclaration::Struct(VariantData::Tuple(fields)) => fields
+                .iter()
+                .try_for_each(|f| self.add_field(attrs_of!(f), &f.shape, base + f.offset, pod)),
+            ItemDeclaration::Struct(VariantData::Fields { fields, .. }) => fields
+                .iter()
+                .try_for_each(|f| self.add_field(attrs_of!(f), &f.shape, base + f.offset, pod)),
+            // the discriminant doesn't take all of its bit patterns
+            ItemDeclaration::Enum { .. } => None,
+        }
+    }
+
+    fn add_field(
+        &self,
+        attrs: Option<&[Attr]>,
+        shape: &DataShape<'db>,
+        base: usize,
+        pod: &mut Pod,
+    ) -> Option<()> {
+        if pod.bytes_only && !matches!(hook_name(attrs), Ok(None)) {
+            return None;
+        }
+        self.add_shape(shape, base, pod)
+    }
+
+    fn add_shape(&self, shape: &DataShape<'db>, base: usize, pod: &mut Pod) -> Option<()> {
+        match shape {
+            &DataShape::Builtin(builtin) => pod.push(base, builtin, 1),
+            &DataShape::Leaf(id) => self
+                .leaf_type(id)
+                .and_then(|typ| self.add_item(typ, base, pod)),
+            &DataShape::FixedArray(id, len) => match element_shape(id) {
+                DataShape::Builtin(builtin) => pod.push(base, builtin, len),
+                elt => {
+                    let stride = self.stride(id).ok()?;
+                    (0..len).try_for_each(|ix| self.add_shape(&elt, base + ix * stride, pod))
+                }
+            },
+            DataShape::Tuple(fields) => fields
+                .iter()
+                .try_for_each(|f| self.add_field(attrs_of!(f), &f.shape, base + f.offset, pod)),
+            DataShape::Slice(_) | DataShape::Ref(_) => None,
+        }
+    }
+}
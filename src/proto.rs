@@ -0,0 +1,268 @@
+use crate::convert::ValueError;
+use crate::decls::Decls;
+use crate::metadata::*;
+use crate::value::{element_shape, field_label};
+use crate::Db;
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::any::{Any, TypeId};
+use core::fmt::Write;
+
+impl<'db> Db<'db> {
+    /// Use `typ` for leaves of type `T` in generated protobuf schemas.
+    ///
+    /// `typ` goes right before the field name, so it can include a label, as in
+    /// `optional string` for an `Option<String>`.
+    pub fn register_leaf_proto<T: Any>(&mut self, typ: &str) -> &mut Db<'db> {
+        self.leaf_proto.insert(TypeId::of::<T>(), typ.into());
+        self
+    }
+
+    /// A proto3 schema with a message for `T` and every reflected type reachable from it.
+    ///
+    /// Builtins map to the smallest protobuf scalar that holds them, with 128-bit integers as
+    /// `bytes`. Newtypes and single-field tuple structs are transparent. An enum becomes a
+    /// message with a nested message per variant and a `oneof` over them. Fixed-size arrays
+    /// become `repeated` fields, but anonymous tuples and nested arrays have no protobuf
+    /// equivalent and are an error.
+    ///
+    /// Field numbers count from 1 in declaration order, or with the `attrs` feature can be
+    /// set with `#[reflect(tag = N)]` on a field or variant.
+    pub fn proto_schema<T: Any>(&self) -> Result<String, ValueError> {
+        let typ = self
+            .known_type(TypeId::of::<T>())
+            .ok_or_else(|| ValueError("type missing from db, cannot describe".into()))?;
+        let mut builder = ProtoBuilder {
+            db: self,
+            messages: Decls::new(),
+        };
+        builder.message_name(typ)?;
+        let mut out = String::from("syntax = \"proto3\";\n");
+        for (_, message) in builder.messages.into_iter() {
+            out.push('\n');
+            out.push_str(&message);
+        }
+        Ok(out)
+    }
+}
+
+/// Field number for the field at `ix`, from its `#[reflect(tag = N)]` if it has one.
+#[allow(unused_variables)]
+fn tag(attrs: Option<&[Attr]>, ix: usize) -> Result<u32, ValueError> {
+    #[cfg(feature = "attrs")]
+    if let Some(val) = attrs.and_then(|attrs| Attr::reflect_option(attrs, "tag")) {
+        return match val {
+            // 19000 to 19999 are reserved for protobuf itself
+            &RustPrimitive::Int(n) if (1..1 << 29).contains(&n) && !(19000..20000).contains(&n) => {
+                Ok(n as u32)
+            }
+            other => Err(ValueError(alloc::format!(
+                "`{:?}` is not a protobuf field number",
+                other
+            ))),
+        };
+    }
+    Ok(ix as u32 + 1)
+}
+
+/// Check that the field numbers in a message are all different.
+fn unique_tags(message: &str, tags: &[u32]) -> Result<(), ValueError> {
+    for (ix, tag) in tags.iter().enumerate() {
+        if tags[..ix].contains(tag) {
+            return Err(ValueError(alloc::format!(
+                "field number {} is used twice in {}",
+                tag,
+                message
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn snake_case(label: &str) -> String {
+    let mut out = String::new();
+    for (ix, c) in label.chars().enumerate() {
+        if c.is_uppercase() {
+            if ix != 0 {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+struct ProtoBuilder<'a, 'db> {
+    db: &'a Db<'db>,
+    messages: Decls<String>,
+}
+
+impl<'db> ProtoBuilder<'_, 'db> {
+    /// The protobuf type standing for `typ`, adding a message for it if need be.
+    fn message_name(&mut self, typ: &ReflectedType<'db>) -> Result<String, ValueError> {
+        if let Some(name) = self.messages.name(typ.id) {
+            return Ok(name);
+        }
+        match &typ.typ {
+            ItemDeclaration::Newtype(inner) => return self.message_name(inner),
+            ItemDeclaration::Struct(VariantData::Tuple(fields)) if fields.len() == 1 => {
+                return self.field(attrs_of!(fields[0]), &fields[0].shape).and_then(
+                    |(repeated, typ)| {
+                        if repeated {
+                            Err(ValueError(
+                                "a newtype around an array has no protobuf equivalent".into(),
+                            ))
+                        } else {
+                            Ok(typ)
+                        }
+                    },
+                )
+            }
+            _ => {}
+        }
+        let name = self.messages.reserve(typ);
+        let mut message = alloc::format!("message {} {{\n", name);
+        match &typ.typ {
+            ItemDeclaration::Newtype(_) => unreachable!(),
+            ItemDeclaration::Struct(data) => self.fields(&mut message, &name, data, "  ")?,
+            ItemDeclaration::Enum { variants, .. } => {
+                let mut oneof = String::from("  oneof value {\n");
+                let mut tags = Vec::new();
+                for arm in variants.iter() {
+                    let tag = tag(attrs_of!(arm), arm.variant_index as usize)?;
+                    tags.push(tag);
+                    let _ = writeln!(message, "  message {} {{", arm.label);
+                    self.fields(&mut message, arm.label, &arm.variant, "    ")?;
+                    message.push_str("  }\n");
+                    let _ = writeln!(
+                        oneof,
+                        "    {} {} = {};",
+                        arm.label,
+                        snake_case(arm.label),
+                        tag
+                    );
+                }
+                unique_tags(&name, &tags)?;
+                oneof.push_str("  }\n");
+                message.push_str(&oneof);
+            }
+        }
+        message.push_str("}\n");
+        self.messages.define(typ.id, message);
+        Ok(name)
+    }
+
+    /// Write the fields of `data` into `out`, one per line.
+    fn fields(
+        &mut self,
+        out: &mut String,
+        message: &str,
+        data: &VariantData<'db>,
+        indent: &str,
+    ) -> Result<(), ValueError> {
+        let mut tags = Vec::new();
+        match data {
+            VariantData::Unit => {}
+            VariantData::Fields {
+                labels_for_serde,
+                fields,
+            } => {
+                for (ix, f) in fields.iter().enumerate() {
+                    let tag = tag(attrs_of!(f), ix)?;
+                    tags.push(tag);
+                    let (repeated, typ) = self.field(attrs_of!(f), &f.shape)?;
+                    let _ = writeln!(
+                        out,
+                        "{}{}{} {} = {};",
+                        indent,
+                        if repeated { "repeated " } else { "" },
+                        typ,
+                        field_label(labels_for_serde, ix, f),
+                        tag
+                    );
+                }
+            }
+            VariantData::Tuple(fields) => {
+                for (ix, f) in fields.iter().enumerate() {
+                    let tag = tag(attrs_of!(f), ix)?;
+                    tags.push(tag);
+                    let (repeated, typ) = self.field(attrs_of!(f), &f.shape)?;
+                    let _ = writeln!(
+                        out,
+                        "{}{}{} field_{} = {};",
+                        indent,
+                        if repeated { "repeated " } else { "" },
+                        typ,
+                        ix,
+                        tag
+                    );
+                }
+            }
+        }
+        unique_tags(message, &tags)
+    }
+
+    /// Like `field_type`, for a field with `attrs`. Reflection can't see what a field's hook
+    /// writes, so it has no protobuf type.
+    fn field(
+        &mut self,
+        attrs: Option<&[Attr]>,
+        shape: &DataShape<'db>,
+    ) -> Result<(bool, String), ValueError> {
+        match self.db.field_hook(attrs, shape)? {
+            Some(hook) => Err(ValueError(alloc::format!(
+                "a field of type {} with a hook has no protobuf type",
+                hook.field_name
+            ))),
+            None => self.field_type(shape),
+        }
+    }
+
+    /// The protobuf type of a field, and whether it is `repeated`.
+    fn field_type(&mut self, shape: &DataShape<'db>) -> Result<(bool, String), ValueError> {
+        let db = self.db;
+        let scalar = |typ: &str| Ok((false, typ.to_string()));
+        match shape {
+            &DataShape::Builtin(builtin) => scalar(match builtin {
+                RustBuiltin::U8 | RustBuiltin::U16 | RustBuiltin::U32 => "uint32",
+                RustBuiltin::I8 | RustBuiltin::I16 | RustBuiltin::I32 => "int32",
+                RustBuiltin::U64 => "uint64",
+                RustBuiltin::I64 => "int64",
+                // little-endian, 16 bytes
+                RustBuiltin::U128 | RustBuiltin::I128 => "bytes",
+                RustBuiltin::BOOLIN => "bool",
+                RustBuiltin::CHAR => "string",
+            }),
+            &DataShape::Leaf(id) => match (db.leaf_type(id), db.lookup(|db| &db.leaf_proto, &id)) {
+                (Some(typ), _) => Ok((false, self.message_name(typ)?)),
+                (None, Some(typ)) => Ok((false, typ.clone())),
+                (None, None) if id == TypeId::of::<String>() => scalar("string"),
+                (None, None) => Err(ValueError(alloc::format!(
+                    "no protobuf type registered for leaf {}",
+                    db.name_of(id)
+                        .unwrap_or_else(|_| alloc::format!("{:?}", id))
+                ))),
+            },
+            &DataShape::FixedArray(id, _) => self.repeated(&element_shape(id)),
+            DataShape::Slice(elt) => self.repeated(elt),
+            DataShape::Ref(inner) => self.field_type(inner),
+            DataShape::Tuple(_) => Err(ValueError(
+                "anonymous tuples have no protobuf equivalent, use a tuple struct".into(),
+            )),
+        }
+    }
+
+    fn repeated(&mut self, elt: &DataShape<'db>) -> Result<(bool, String), ValueError> {
+        match self.field_type(elt)? {
+            (false, typ) => Ok((true, typ)),
+            (true, _) => Err(ValueError(
+                "nested arrays have no protobuf equivalent".into(),
+            )),
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use crate::convert::ValueError;
+use crate::decls::Decls;
+use crate::metadata::*;
+use crate::value::{element_shape, field_label, ReflectValue};
+use crate::Db;
+
+use alloc::{string::String, vec, vec::Vec};
+use core::any::{Any, TypeId};
+
+/// What a JSON Schema should say about a leaf type.
+///
+/// Reflection doesn't see inside leaves, so without one of these a leaf accepts anything,
+/// except `String` which is known to be a string.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LeafSchema {
+    /// The schema for the leaf, such as `{"type": "string", "format": "date-time"}`.
+    pub schema: ReflectValue,
+    /// Whether struct fields of this type may be left out, as `Option` fields can be.
+    pub optional: bool,
+}
+
+impl<'db> Db<'db> {
+    /// Describe leaves of type `T` in generated JSON Schemas.
+    pub fn register_leaf_schema<T: Any>(&mut self, schema: LeafSchema) -> &mut Db<'db> {
+        self.leaf_schemas.insert(TypeId::of::<T>(), schema);
+        self
+    }
+
+    /// A JSON Schema (draft 2020-12) document for the serialized form of `T`.
+    ///
+    /// Every reflected type reachable from `T` gets an entry in `$defs`, named after the type,
+    /// and the document refers to the one for `T`. Field and variant labels are the ones serde
+    /// uses, and enums are externally tagged. The result can be serialized with any serde
+    /// format; with serde_json it is a schema ready to use.
+    pub fn json_schema<T: Any>(&self) -> Result<ReflectValue, ValueError> {
+        let typ = self
+            .known_type(TypeId::of::<T>())
+            .ok_or_else(|| ValueError("type missing from db, cannot describe".into()))?;
+        let mut builder = SchemaBuilder {
+            db: self,
+            defs: Decls::new(),
+        };
+        let root = builder.def_name(typ)?;
+        let defs = builder
+            .defs
+            .into_iter()
+            .map(|(name, def)| (ReflectValue::Str(name), def))
+            .collect();
+        Ok(object(vec![
+            (
+                "$schema",
+                string("https://json-schema.org/draft/2020-12/schema"),
+            ),
+            ("title", string(typ.name)),
+            ("$ref", reference(&root)),
+            ("$defs", ReflectValue::Map(defs)),
+        ]))
+    }
+}
+
+fn string(s: &str) -> ReflectValue {
+    ReflectValue::Str(s.into())
+}
+
+fn reference(def_name: &str) -> ReflectValue {
+    ReflectValue::Str(alloc::format!("#/$defs/{}", def_name))
+}
+
+fn object(entries: Vec<(&str, ReflectValue)>) -> ReflectValue {
+    ReflectValue::Map(entries.into_iter().map(|(k, v)| (string(k), v)).collect())
+}
+
+struct SchemaBuilder<'a, 'db> {
+    db: &'a Db<'db>,
+    defs: Decls<ReflectValue>,
+}
+
+impl<'db> SchemaBuilder<'_, 'db> {
+    /// A `$ref` to the definition of `typ`.
+    fn item_ref(&mut self, typ: &ReflectedType<'db>) -> Result<ReflectValue, ValueError> {
+        let name = self.def_name(typ)?;
+        Ok(object(vec![("$ref", reference(&name))]))
+    }
+
+    /// The name of the `$defs` entry for `typ`, adding it if need be.
+    fn def_name(&mut self, typ: &ReflectedType<'db>) -> Result<String, ValueError> {
+        if let Some(name) = self.defs.name(typ.id) {
+            return Ok(name);
+        }
+        let name = self.defs.reserve(typ);
+        let def = self.item(typ)?;
+        self.defs.define(typ.id, def);
+        Ok(name)
+    }
+
+    fn item(&mut self, typ: &ReflectedType<'db>) -> Result<ReflectValue, ValueError> {
+        match &typ.typ {
+            ItemDeclaration::Newtype(inner) => self.item_ref(inner),
+            ItemDeclaration::Struct(data) => self.variant_data(data),
+            ItemDeclaration::Enum { variants, .. } => {
+                let mut units = Vec::new();
+                let mut alternatives = Vec::new();
+                for arm in variants.iter() {
+                    match &arm.variant {
+                        VariantData::Unit => units.push(string(arm.label)),
+                        data => {
+                            let data = self.variant_data(data)?;
+                            alternatives.push(object(vec![
+                                ("type", string("object")),
+                                ("properties", object(vec![(arm.label, data)])),
+                                ("required", ReflectValue::Seq(vec![string(arm.label)])),
+                                // serde reads a variant from a map with just its label in it,
+                                // so there is no room here for unknown fields
+                                ("additionalProperties", ReflectValue::Bool(false)),
+                            ]));
+                        }
+                    }
+                }
+                if !units.is_empty() {
+                    alternatives.insert(
+                        0,
+                        object(vec![
+                            ("type", string("string")),
+                            ("enum", ReflectValue::Seq(units)),
+                        ]),
+                    );
+                }
+                Ok(object(vec![("oneOf", ReflectValue::Seq(alternatives))]))
+            }
+        }
+    }
+
+    fn variant_data(&mut self, data: &VariantData<'db>) -> Result<ReflectValue, ValueError> {
+        match data {
+            VariantData::Unit => Ok(object(vec![("type", string("null"))])),
+            VariantData::Fields {
+                labels_for_serde,
+                fields,
+            } => {
+                let mut properties = Vec::new();
+                let mut required = Vec::new();
+                for (ix, f) in fields.iter().enumerate() {
+                    let label = field_label(labels_for_serde, ix, f);
+                    let hooked = self.db.field_hook(attrs_of!(f), &f.shape)?.is_some();
+                    properties.push((string(label), self.field(attrs_of!(f), &f.shape)?));
+                    let optional = match f.shape {
+                        DataShape::Leaf(id) if !hooked => {
+                            match self.db.lookup(|db| &db.leaf_schemas, &id) {
+                                Some(leaf) => leaf.optional,
+                                None => self.db.option_leaf(id).is_some(),
+                            }
+                        }
+                        _ => false,
+                    };
+                    if !optional {
+                        required.push(string(label));
+                    }
+                }
+                // unknown fields are skipped, so they are allowed
+                Ok(object(vec![
+                    ("type", string("object")),
+                    ("properties", ReflectValue::Map(properties)),
+                    ("required", ReflectValue::Seq(required)),
+                ]))
+            }
+            // serde treats a single-field tuple struct as a newtype
+            VariantData::Tuple(fields) if fields.len() == 1 => {
+                self.field(attrs_of!(fields[0]), &fields[0].shape)
+            }
+            VariantData::Tuple(fields) => self.tuple(fields),
+        }
+    }
+
+    fn tuple(&mut self, fields: &[TupleField<'db>]) -> Result<ReflectValue, ValueError> {
+        let items = fields
+            .iter()
+            .map(|f| self.field(attrs_of!(f), &f.shape))
+            .collect::<Result<Vec<_>, _>>()?;
+        let len = ReflectValue::U64(fields.len() as u64);
+        Ok(object(vec![
+            ("type", string("array")),
+            ("prefixItems", ReflectValue::Seq(items)),
+            ("items", ReflectValue::Bool(false)),
+            ("minItems", len.clone()),
+            ("maxItems", len),
+        ]))
+    }
+
+    /// A field with a hook accepts anything, since reflection can't see what the hook writes.
+    fn field(
+        &mut self,
+        attrs: Option<&[Attr]>,
+        shape: &DataShape<'db>,
+    ) -> Result<ReflectValue, ValueError> {
+        match self.db.field_hook(attrs, shape)? {
+            Some(_) => Ok(ReflectValue::Bool(true)),
+            None => self.shape(shape),
+        }
+    }
+
+    fn shape(&mut self, shape: &DataShape<'db>) -> Result<ReflectValue, ValueError> {
+        let db = self.db;
+        match shape {
+            &DataShape::Builtin(builtin) => Ok(builtin_schema(builtin)),
+            &DataShape::Leaf(id) => {
+                match (db.leaf_type(id), db.lookup(|db| &db.leaf_schemas, &id)) {
+                    (Some(typ), _) => self.item_ref(typ),
+                    (None, Some(leaf)) => Ok(leaf.schema.clone()),
+                    (None, None) if id == TypeId::of::<String>() => {
+                        Ok(object(vec![("type", string("string"))]))
+                    }
+                    // an `Option` of something known is that or null
+                    (None, None) => Ok(match db.option_leaf(id).and_then(named_schema) {
+                        Some(inner) => object(vec![(
+                            "anyOf",
+                            ReflectValue::Seq(vec![inner, object(vec![("type", string("null"))])]),
+                        )]),
+                        None => ReflectValue::Bool(true),
+                    }),
+                }
+            }
+            &DataShape::FixedArray(id, len) => {
+                let items = self.shape(&element_shape(id))?;
+                let len = ReflectValue::U64(len as u64);
+                Ok(object(vec![
+                    ("type", string("array")),
+                    ("items", items),
+                    ("minItems", len.clone()),
+                    ("maxItems", len),
+                ]))
+            }
+            DataShape::Slice(elt) => Ok(object(vec![
+                ("type", string("array")),
+                ("items", self.shape(elt)?),
+            ])),
+            DataShape::Ref(inner) => self.shape(inner),
+            DataShape::Tuple(fields) => self.tuple(fields),
+        }
+    }
+}
+
+/// The schema for a leaf of the builtin or `String` type named `name`, which reflection knows
+/// without a `LeafSchema`.
+fn named_schema(name: &str) -> Option<ReflectValue> {
+    if name == core::any::type_name::<String>() {
+        return Some(object(vec![("type", string("string"))]));
+    }
+    RustBuiltin::from_name(name).map(builtin_schema)
+}
+
+fn builtin_schema(builtin: RustBuiltin) -> ReflectValue {
+    let integer = |min: ReflectValue, max: ReflectValue| {
+        object(vec![
+            ("type", string("integer")),
+            ("minimum", min),
+            ("maximum", max),
+        ])
+    };
+    use ReflectValue as V;
+    match builtin {
+        RustBuiltin::U8 => integer(V::U8(0), V::U8(u8::MAX)),
+        RustBuiltin::I8 => integer(V::I8(i8::MIN), V::I8(i8::MAX)),
+        RustBuiltin::U16 => integer(V::U16(0), V::U16(u16::MAX)),
+        RustBuiltin::I16 => integer(V::I16(i16::MIN), V::I16(i16::MAX)),
+        RustBuiltin::U32 => integer(V::U32(0), V::U32(u32::MAX)),
+        RustBuiltin::I32 => integer(V::I32(i32::MIN), V::I32(i32::MAX)),
+        RustBuiltin::U64 => integer(V::U64(0), V::U64(u64::MAX)),
+        RustBuiltin::I64 => integer(V::I64(i64::MIN), V::I64(i64::MAX)),
+        // not every JSON implementation can represent the full 128-bit range
+        RustBuiltin::U128 => object(vec![("type", string("integer")), ("minimum", V::U8(0))]),
+        RustBuiltin::I128 => object(vec![("type", string("integer"))]),
+        RustBuiltin::BOOLIN => object(vec![("type", string("boolean"))]),
+        RustBuiltin::CHAR => object(vec![
+            ("type", string("string")),
+            ("minLength", V::U8(1)),
+            ("maxLength", V::U8(1)),
+        ]),
+    }
+}
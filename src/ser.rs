@@ -1,11 +1,12 @@
 use crate::metadata::*;
-use crate::{Db, TraitObject, TypedLocation};
+use crate::place::Place;
+use crate::value::{element_shape, field_label};
+use crate::{active_arm, Db, TypedLocation};
 
-use alloc::string::String;
 use core::any::{Any, TypeId};
 use serde::ser::Error as serError;
 use serde::ser::{
-    SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple, SerializeTupleStruct,
+    SerializeStruct, SerializeStructVariant, SerializeTuple, SerializeTupleStruct,
     SerializeTupleVariant,
 };
 
@@ -18,209 +19,230 @@ impl<'db> Db<'db> {
         s: S,
         val: &T,
     ) -> Result<S::Ok, S::Error> {
-        use serde::ser::{Error, Serialize};
+        use serde::Serialize;
         let rust_type = self
-            .known_types
-            .get(&TypeId::of::<T>())
+            .known_type(TypeId::of::<T>())
             .ok_or_else(|| S::Error::custom("type missing from db, cannot serialize"))?;
-        Serialize(
-            self,
-            TypedLocation {
-                typ: rust_type,
-                ptr: val as *const _ as *const _,
-                _data: Default::default(),
-            },
-        )
+        PlaceSer {
+            db: self,
+            place: Place::Item(rust_type),
+            ptr: val as *const T as *const u8,
+        }
         .serialize(s)
     }
 
-    fn serialize_leaf<S: serde::Serializer>(
+    /// Serialize the leaf with type id `id` at `ptr`.
+    ///
+    /// SAFETY: `ptr` must point to an initialized value of the leaf type.
+    pub(crate) unsafe fn serialize_leaf_at<S: serde::Serializer>(
         &self,
         s: S,
-        src: &TypedLocation<'_, '_>,
+        id: TypeId,
+        ptr: *const u8,
     ) -> Result<S::Ok, S::Error> {
-        let vtable = *self.serialize_vtables.get(&src.typ.id).unwrap();
-        let obj = unsafe {
-            // SAFETY: the vtable is correct because we populated it correctly in register_leaf.
-            core::mem::transmute::<TraitObject, &dyn erased_serde::Serialize>(TraitObject {
-                vtable,
-                data: src.ptr as *mut _,
-            })
-        };
-        erased_serde::serialize(obj, s)
+        let object = *self
+            .lookup(|db| &db.serialize_objects, &id)
+            .ok_or_else(|| S::Error::custom("leaf type missing from reflection db"))?;
+        erased_serde::serialize(&*object(ptr), s)
     }
+}
 
-    fn serialize_fields<I: IntoIterator<Item = &'db TupleField<'db>>, Err>(
-        &self,
-        mut f: impl FnMut(TypedLocation<'db, '_>) -> Result<(), Err>,
-        fields_of: &TypedLocation<'db, '_>,
-        fields: I,
-        ty_err: &impl Fn(String) -> Err,
-    ) -> Result<(), Err> {
-        for &TupleField {
-            offset, type_id, ..
-        } in fields.into_iter()
-        {
-            let field_type = self.type_layout(type_id).map_err(ty_err)?;
-            // SAFETY: TypedLocation contract
-            unsafe {
-                f(TypedLocation::new(&field_type, fields_of.ptr.add(offset)))?;
-            }
+/// Wrapper for serializing a value from memory via reflection. You're better off using `Db::serialize`.
+pub struct Serialize<'db, 'data>(
+    pub(crate) &'db Db<'db>,
+    pub(crate) TypedLocation<'db, 'data>,
+);
+
+impl serde::Serialize for Serialize<'_, '_> {
+    fn serialize<S>(&self, dst: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let Serialize(db, input) = self;
+        PlaceSer {
+            db,
+            place: Place::Item(input.typ),
+            ptr: input.ptr,
         }
-        Ok(())
+        .serialize(dst)
     }
+}
 
-    fn serialize_named_fields<'data, I: IntoIterator<Item = &'db Field<'db>>, Err>(
+/// Serializes the value at `ptr`, which `place` describes.
+#[derive(Clone, Copy)]
+struct PlaceSer<'a, 'db> {
+    db: &'a Db<'db>,
+    place: Place<'a, 'db>,
+    ptr: *const u8,
+}
+
+impl<'a, 'db> PlaceSer<'a, 'db> {
+    /// The field of this value with `attrs` and `shape`, at `offset`.
+    fn field<E: serError>(
         &self,
-        mut f: impl FnMut(&'static str, TypedLocation<'db, 'data>) -> Result<(), Err>,
-        fields_of: &'db TypedLocation<'db, 'data>,
-        fields: I,
-        ty_err: &impl Fn(String) -> Err,
-    ) -> Result<(), Err> {
-        for &Field {
-            offset,
-            type_id,
-            name,
-            ..
-        } in fields.into_iter()
-        {
-            let field_type = self.type_layout(type_id).map_err(ty_err)?;
-            // SAFETY: TypedLocation contract
-            unsafe {
-                f(
-                    name,
-                    TypedLocation::new(&field_type, fields_of.ptr.add(offset)),
-                )?;
-            }
-        }
-        Ok(())
+        attrs: Option<&[Attr]>,
+        shape: &'a DataShape<'db>,
+        offset: usize,
+    ) -> Result<PlaceSer<'a, 'db>, E> {
+        Ok(PlaceSer {
+            db: self.db,
+            place: self.db.field_place(attrs, shape).map_err(E::custom)?,
+            // SAFETY: the field is part of the value at `ptr`.
+            ptr: unsafe { self.ptr.add(offset) },
+        })
     }
 }
 
-/// Wrapper for serializing a value from memory via reflection. You're better off using `Db::serialize`.
-pub struct Serialize<'db, 'data>(&'db Db<'db>, TypedLocation<'db, 'data>);
-
-impl serde::Serialize for Serialize<'_, '_> {
+impl serde::Serialize for PlaceSer<'_, '_> {
     fn serialize<S>(&self, dst: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let Serialize(db, input) = self;
-        match &input.typ.shape {
-            DataShape::Leaf(tid) => db.serialize_leaf(dst, input),
-            DataShape::Tuple(fields) => {
-                let mut tup = dst.serialize_tuple(fields.len())?;
-                db.serialize_fields(
-                    |src| tup.serialize_element(&Serialize(db, src)),
-                    input,
-                    fields.iter(),
-                    &serError::custom,
-                )?;
-                tup.end()
-            }
-            DataShape::Newtype(nested_type) => dst.serialize_newtype_struct(
-                input.typ.name,
-                &Serialize(
-                    db,
-                    // SAFETY: we uphold the contract by assuming that the database
-                    unsafe {
-                        TypedLocation::new(
-                            &db.type_layout(*nested_type).map_err(serError::custom)?,
-                            input.ptr,
-                        )
+        let db = self.db;
+        let ptr = self.ptr;
+        // SAFETY: `place` describes the value at `ptr`, which is initialized.
+        match self.place.resolve(db) {
+            Place::Item(typ) => match &typ.typ {
+                ItemDeclaration::Newtype(inner) => dst.serialize_newtype_struct(
+                    typ.name,
+                    &PlaceSer {
+                        place: Place::Item(inner),
+                        ..*self
                     },
                 ),
-            ),
-            DataShape::Struct(VariantData::Unit) => dst.serialize_unit_struct(input.typ.name),
-            DataShape::Struct(VariantData::Tuple(fields)) => {
-                let mut tup = dst.serialize_tuple_struct(input.typ.name, fields.len())?;
-                db.serialize_fields(
-                    |src| tup.serialize_field(&Serialize(db, src)),
-                    input,
-                    fields.iter(),
-                    &serError::custom,
-                )?;
-                tup.end()
-            }
-            DataShape::Struct(VariantData::Fields { fields, .. }) => {
-                let mut struc = dst.serialize_struct(input.typ.name, fields.len())?;
-                db.serialize_named_fields(
-                    |name, src| struc.serialize_field(name, &Serialize(db, src)),
-                    input,
-                    fields.iter(),
-                    &serError::custom,
-                )?;
-                struc.end()
-            }
-            DataShape::Enum { variants, .. } => {
-                // SAFETY: we know we're looking at an enum.
-                let tag = unsafe { input.read_discriminant() };
-                let arm_idx = variants.binary_search_by_key(&tag, |arm| arm.discriminant);
-                let arm = match arm_idx {
-                    // SAFETY: we just got ix
-                    Ok(ix) => variants.get(ix).unwrap(),
-                    Err(_) => {
-                        return Err(serError::custom(
-                            "runtime discriminant not described in reflection db",
-                        ))
-                    }
-                };
-                match &arm.variant {
-                    VariantData::Unit => dst.serialize_unit_variant(
-                        input.typ.name,
-                        arm.variant_index as u32,
-                        arm.label,
-                    ),
-                    VariantData::Fields { fields, .. } => {
-                        let mut struc = dst.serialize_struct_variant(
-                            input.typ.name,
-                            arm.variant_index as u32,
-                            arm.label,
-                            fields.len(),
-                        )?;
-                        db.serialize_named_fields(
-                            |name, src| struc.serialize_field(name, &Serialize(db, src)),
-                            input,
-                            fields.iter(),
-                            &serError::custom,
-                        )?;
-                        struc.end()
+                ItemDeclaration::Struct(data) => match data {
+                    VariantData::Unit => dst.serialize_unit_struct(typ.name),
+                    // serde treats single-field tuple structs as newtypes
+                    VariantData::Tuple(fields) if fields.len() == 1 => {
+                        let f = &fields[0];
+                        dst.serialize_newtype_struct(
+                            typ.name,
+                            &self.field::<S::Error>(attrs_of!(f), &f.shape, f.offset)?,
+                        )
                     }
                     VariantData::Tuple(fields) => {
-                        let mut tup = dst.serialize_tuple_variant(
-                            input.typ.name,
-                            arm.variant_index as u32,
-                            arm.label,
-                            fields.len(),
-                        )?;
-                        db.serialize_fields(
-                            |src| tup.serialize_field(&Serialize(db, src)),
-                            input,
-                            fields.iter(),
-                            &serError::custom,
-                        )?;
+                        let mut tup = dst.serialize_tuple_struct(typ.name, fields.len())?;
+                        for f in fields.iter() {
+                            tup.serialize_field(&self.field::<S::Error>(
+                                attrs_of!(f),
+                                &f.shape,
+                                f.offset,
+                            )?)?;
+                        }
                         tup.end()
                     }
+                    VariantData::Fields {
+                        labels_for_serde,
+                        fields,
+                    } => {
+                        let mut struc = dst.serialize_struct(typ.name, fields.len())?;
+                        for (ix, f) in fields.iter().enumerate() {
+                            struc.serialize_field(
+                                field_label(labels_for_serde, ix, f),
+                                &self.field::<S::Error>(attrs_of!(f), &f.shape, f.offset)?,
+                            )?;
+                        }
+                        struc.end()
+                    }
+                },
+                ItemDeclaration::Enum { variants, .. } => {
+                    let arm = unsafe { active_arm(variants, ptr) }.map_err(S::Error::custom)?;
+                    let (name, index, label) = (typ.name, arm.variant_index as u32, arm.label);
+                    match &arm.variant {
+                        VariantData::Unit => dst.serialize_unit_variant(name, index, label),
+                        VariantData::Tuple(fields) if fields.len() == 1 => {
+                            let f = &fields[0];
+                            dst.serialize_newtype_variant(
+                                name,
+                                index,
+                                label,
+                                &self.field::<S::Error>(attrs_of!(f), &f.shape, f.offset)?,
+                            )
+                        }
+                        VariantData::Tuple(fields) => {
+                            let mut tup =
+                                dst.serialize_tuple_variant(name, index, label, fields.len())?;
+                            for f in fields.iter() {
+                                tup.serialize_field(&self.field::<S::Error>(
+                                    attrs_of!(f),
+                                    &f.shape,
+                                    f.offset,
+                                )?)?;
+                            }
+                            tup.end()
+                        }
+                        VariantData::Fields {
+                            labels_for_serde,
+                            fields,
+                        } => {
+                            let mut struc =
+                                dst.serialize_struct_variant(name, index, label, fields.len())?;
+                            for (ix, f) in fields.iter().enumerate() {
+                                struc.serialize_field(
+                                    field_label(labels_for_serde, ix, f),
+                                    &self.field::<S::Error>(attrs_of!(f), &f.shape, f.offset)?,
+                                )?;
+                            }
+                            struc.end()
+                        }
+                    }
                 }
-            }
-            /*src.deserialize_enum(
-                dst.typ.name,
-                variant_labels_for_serde,
-                OutVisitor(self, &dst),
-            ),*/
-            &DataShape::FixedArray(type_id, len) => {
-                let typ = db.type_layout(type_id).map_err(serError::custom)?;
-                let mut seq = dst.serialize_seq(Some(len))?;
-                let stride = typ.layout.pad_to_align().size();
-                for ix in 0..len {
-                    seq.serialize_element(&Serialize(
-                        db,
-                        // SAFETY: ix is inbounds and TypedLocation contract
-                        unsafe { TypedLocation::new(&typ, input.ptr.add(ix * stride).cast()) },
-                    ))?;
+            },
+            Place::Shape(shape) => match shape {
+                &DataShape::Builtin(builtin) => unsafe { serialize_builtin(dst, builtin, ptr) },
+                &DataShape::Leaf(id) => unsafe { db.serialize_leaf_at(dst, id, ptr) },
+                &DataShape::FixedArray(id, len) => {
+                    let elt = element_shape(id);
+                    let stride = db.stride(id).map_err(S::Error::custom)?;
+                    let mut tup = dst.serialize_tuple(len)?;
+                    for ix in 0..len {
+                        tup.serialize_element(&PlaceSer {
+                            db,
+                            place: Place::Shape(&elt),
+                            // SAFETY: the array has `len` elements, `stride` apart.
+                            ptr: unsafe { ptr.add(ix * stride) },
+                        })?;
+                    }
+                    tup.end()
+                }
+                DataShape::Tuple(fields) => {
+                    let mut tup = dst.serialize_tuple(fields.len())?;
+                    for f in fields.iter() {
+                        tup.serialize_element(&self.field::<S::Error>(
+                            attrs_of!(f),
+                            &f.shape,
+                            f.offset,
+                        )?)?;
+                    }
+                    tup.end()
+                }
+                DataShape::Slice(_) | DataShape::Ref(_) => {
+                    Err(S::Error::custom("cannot serialize borrowed data"))
                 }
-                seq.end()
-            }
+            },
+            Place::Hooked(hook, _) => unsafe { hook.serialize(dst, ptr) },
         }
     }
 }
+
+/// SAFETY: `ptr` must point to an initialized value of the builtin's type.
+pub(crate) unsafe fn serialize_builtin<S: serde::Serializer>(
+    s: S,
+    builtin: RustBuiltin,
+    ptr: *const u8,
+) -> Result<S::Ok, S::Error> {
+    match builtin {
+        RustBuiltin::U8 => s.serialize_u8(ptr.read()),
+        RustBuiltin::I8 => s.serialize_i8(ptr.cast::<i8>().read()),
+        RustBuiltin::U16 => s.serialize_u16(ptr.cast::<u16>().read()),
+        RustBuiltin::I16 => s.serialize_i16(ptr.cast::<i16>().read()),
+        RustBuiltin::U32 => s.serialize_u32(ptr.cast::<u32>().read()),
+        RustBuiltin::I32 => s.serialize_i32(ptr.cast::<i32>().read()),
+        RustBuiltin::U64 => s.serialize_u64(ptr.cast::<u64>().read()),
+        RustBuiltin::I64 => s.serialize_i64(ptr.cast::<i64>().read()),
+        RustBuiltin::U128 => s.serialize_u128(ptr.cast::<u128>().read()),
+        RustBuiltin::I128 => s.serialize_i128(ptr.cast::<i128>().read()),
+        RustBuiltin::BOOLIN => s.serialize_bool(ptr.cast::<bool>().read()),
+        RustBuiltin::CHAR => s.serialize_char(ptr.cast::<char>().read()),
+    }
+}
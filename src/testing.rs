@@ -0,0 +1,239 @@
+//! Checks that reflection reads and writes a type exactly as its serde derives do.
+//!
+//! A type moving from `serde_derive` to `#[derive(Reflect)]` can keep both while `check_type`
+//! compares them, and drop the serde derives once it passes.
+
+use crate::convert::{ValueError, ValueSerializer};
+use crate::Db;
+
+use alloc::{borrow::ToOwned, string::String, string::ToString, vec::Vec};
+use core::any::Any;
+use core::fmt::{Debug, Display};
+use postcard::ser_flavors::{AllocVec, Flavor};
+use proptest::test_runner::{TestCaseError, TestRunner};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A format the checks are made in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// JSON, with serde_json.
+    Json,
+    /// A compact binary format with variable-length integers, with postcard.
+    Postcard,
+    /// A binary format with fixed-width integers, with bincode's default options.
+    Bincode,
+    /// A self-describing binary format, CBOR with ciborium.
+    Cbor,
+}
+
+/// Serializes a value with reflection, for formats that only take a `Serialize` value.
+struct Reflected<'a, 'db, T>(&'a Db<'db>, &'a T);
+
+impl<T: Any> Serialize for Reflected<'_, '_, T> {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(s, self.1)
+    }
+}
+
+/// The options `bincode::serialize` and `bincode::deserialize` use.
+fn bincode_options() -> impl bincode::Options {
+    use bincode::Options;
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+}
+
+impl Format {
+    /// Every format, in the order they are checked.
+    pub const ALL: [Format; 4] = [
+        Format::Json,
+        Format::Postcard,
+        Format::Bincode,
+        Format::Cbor,
+    ];
+
+    /// Write `val` with its `Serialize` impl.
+    pub fn write_serde<T: Serialize>(self, val: &T) -> Result<Vec<u8>, ValueError> {
+        match self {
+            Format::Json => serde_json::to_vec(val).map_err(error),
+            Format::Postcard => postcard::to_allocvec(val).map_err(error),
+            Format::Bincode => bincode::serialize(val).map_err(error),
+            Format::Cbor => {
+                let mut out = Vec::new();
+                ciborium::ser::into_writer(val, &mut out).map_err(error)?;
+                Ok(out)
+            }
+        }
+    }
+
+    /// Write `val` with reflection.
+    pub fn write_reflected<T: Any>(self, db: &Db<'_>, val: &T) -> Result<Vec<u8>, ValueError> {
+        let mut out = Vec::new();
+        match self {
+            Format::Json => db
+                .serialize(&mut serde_json::Serializer::new(&mut out), val)
+                .map_err(error)?,
+            Format::Postcard => {
+                let mut ser = postcard::Serializer {
+                    output: AllocVec::new(),
+                };
+                db.serialize(&mut ser, val).map_err(error)?;
+                out = ser.output.finalize().map_err(error)?;
+            }
+            Format::Bincode => {
+                let mut ser = bincode::Serializer::new(&mut out, bincode_options());
+                db.serialize(&mut ser, val).map_err(error)?;
+            }
+            Format::Cbor => {
+                ciborium::ser::into_writer(&Reflected(db, val), &mut out).map_err(error)?
+            }
+        }
+        Ok(out)
+    }
+
+    /// Read a `T` from `bytes` with its `Deserialize` impl.
+    pub fn read_serde<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, ValueError> {
+        match self {
+            Format::Json => serde_json::from_slice(bytes).map_err(error),
+            Format::Postcard => postcard::from_bytes(bytes).map_err(error),
+            Format::Bincode => bincode::deserialize(bytes).map_err(error),
+            Format::Cbor => ciborium::de::from_reader(bytes).map_err(error),
+        }
+    }
+
+    /// Read a `T` from `bytes` with reflection.
+    ///
+    /// As with `read_serde`, trailing input is an error in JSON, but not in the binary formats.
+    /// ciborium has no public `Deserializer`, so CBOR is read into a `ciborium::Value` first,
+    /// and reflection reads the `T` from that.
+    pub fn read_reflected<T: Any>(self, db: &Db<'_>, bytes: &[u8]) -> Result<T, ValueError> {
+        match self {
+            Format::Json => {
+                let mut de = serde_json::Deserializer::from_slice(bytes);
+                let val = db.deserialize(&mut de).map_err(error)?;
+                de.end().map_err(error)?;
+                Ok(val)
+            }
+            Format::Postcard => db
+                .deserialize(&mut postcard::Deserializer::from_bytes(bytes))
+                .map_err(error),
+            Format::Bincode => db
+                .deserialize(&mut bincode::Deserializer::from_slice(
+                    bytes,
+                    bincode_options(),
+                ))
+                .map_err(error),
+            Format::Cbor => {
+                let cbor: ciborium::value::Value =
+                    ciborium::de::from_reader(bytes).map_err(error)?;
+                db.from_value(cbor.serialize(ValueSerializer)?)
+            }
+        }
+    }
+
+    /// `bytes`, readably.
+    fn show(self, bytes: &[u8]) -> String {
+        match self {
+            Format::Json => String::from_utf8_lossy(bytes).into_owned(),
+            Format::Postcard | Format::Bincode | Format::Cbor => alloc::format!("{:?}", bytes),
+        }
+    }
+}
+
+fn error(e: impl Display) -> ValueError {
+    ValueError(e.to_string())
+}
+
+/// Check `check_value` for values of `T` generated by `Db::arbitrary`.
+///
+/// Leaves of `T` need strategies registered with `Db::register_leaf_strategy`. The number of
+/// values checked can be set with the `PROPTEST_CASES` environment variable.
+pub fn check_type<T>(db: &Db<'_>) -> Result<(), ValueError>
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug + Any,
+{
+    TestRunner::default()
+        .run(&db.arbitrary::<T>()?, |val| {
+            check_value(db, &val).map_err(|e| TestCaseError::fail(e.0))
+        })
+        .map_err(error)
+}
+
+/// Check that reflection writes `val` exactly as serde does in every format, and reads back
+/// what serde wrote.
+///
+/// JSON is also checked with the objects in `val` rewritten with their keys sorted, and with
+/// an unknown key added.
+pub fn check_value<T>(db: &Db<'_>, val: &T) -> Result<(), ValueError>
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug + Any,
+{
+    for &format in Format::ALL.iter() {
+        let expected = format.write_serde(val)?;
+        let written = format.write_reflected(db, val).map_err(|e| {
+            ValueError(alloc::format!(
+                "{:?}: reflection can't write {:?}: {}",
+                format,
+                val,
+                e
+            ))
+        })?;
+        if written != expected {
+            return Err(ValueError(alloc::format!(
+                "{:?}: reflection wrote {:?} as {}, but serde wrote {}",
+                format,
+                val,
+                format.show(&written),
+                format.show(&expected)
+            )));
+        }
+        check_input::<T>(db, format, &expected)?;
+    }
+    let mut json = serde_json::to_value(val).map_err(error)?;
+    check_input::<T>(db, Format::Json, &serde_json::to_vec(&json).map_err(error)?)?;
+    add_unknown_key(&mut json);
+    check_input::<T>(db, Format::Json, &serde_json::to_vec(&json).map_err(error)?)
+}
+
+/// Check that reflection reads `bytes` in `format` as serde does, if serde reads them at all.
+///
+/// Input serde rejects isn't checked, since reflection is free to be more lenient.
+pub fn check_input<T>(db: &Db<'_>, format: Format, bytes: &[u8]) -> Result<(), ValueError>
+where
+    T: DeserializeOwned + PartialEq + Debug + Any,
+{
+    let expected = match format.read_serde::<T>(bytes) {
+        Ok(val) => val,
+        Err(_) => return Ok(()),
+    };
+    let read = format.read_reflected::<T>(db, bytes).map_err(|e| {
+        ValueError(alloc::format!(
+            "{:?}: reflection rejected {}, which serde reads as {:?}: {}",
+            format,
+            format.show(bytes),
+            expected,
+            e
+        ))
+    })?;
+    if read != expected {
+        return Err(ValueError(alloc::format!(
+            "{:?}: reflection read {} as {:?}, but serde read {:?}",
+            format,
+            format.show(bytes),
+            read,
+            expected
+        )));
+    }
+    Ok(())
+}
+
+fn add_unknown_key(json: &mut serde_json::Value) {
+    match json {
+        serde_json::Value::Object(map) => {
+            map.values_mut().for_each(add_unknown_key);
+            map.insert("unknown key".to_owned(), serde_json::Value::Null);
+        }
+        serde_json::Value::Array(elts) => elts.iter_mut().for_each(add_unknown_key),
+        _ => {}
+    }
+}
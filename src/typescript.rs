@@ -0,0 +1,253 @@
+use crate::convert::ValueError;
+use crate::decls::Decls;
+use crate::metadata::*;
+use crate::value::{element_shape, field_label};
+use crate::Db;
+
+use alloc::{string::String, vec::Vec};
+use core::any::{Any, TypeId};
+use core::fmt::Write;
+
+/// What generated TypeScript should say about a leaf type.
+///
+/// Without one of these a leaf is `unknown`, except `String` which is `string`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LeafTypeScript {
+    /// A TypeScript type expression, such as `string | null`.
+    pub typ: String,
+    /// Whether struct fields of this type may be left out, as `Option` fields can be.
+    pub optional: bool,
+}
+
+impl<'db> Db<'db> {
+    /// Describe leaves of type `T` in generated TypeScript.
+    pub fn register_leaf_typescript<T: Any>(&mut self, ts: LeafTypeScript) -> &mut Db<'db> {
+        self.leaf_typescript.insert(TypeId::of::<T>(), ts);
+        self
+    }
+
+    /// TypeScript declarations for the JSON form of `T`, as serde_json would write it.
+    ///
+    /// Every reflected type reachable from `T` gets an exported declaration named after the
+    /// type, `T` first. Structs with named fields become interfaces, and enums become unions
+    /// of their externally tagged variants.
+    ///
+    /// Integers are `number`, including 64- and 128-bit ones: serde_json writes them as plain
+    /// JSON numbers, which `JSON.parse` reads as `number`s, rounding those past 2^53.
+    pub fn typescript<T: Any>(&self) -> Result<String, ValueError> {
+        let typ = self
+            .known_type(TypeId::of::<T>())
+            .ok_or_else(|| ValueError("type missing from db, cannot describe".into()))?;
+        let mut builder = TsBuilder {
+            db: self,
+            decls: Decls::new(),
+        };
+        builder.decl_name(typ)?;
+        let mut out = String::new();
+        for (ix, (_, decl)) in builder.decls.into_iter().enumerate() {
+            if ix != 0 {
+                out.push('\n');
+            }
+            out.push_str(&decl);
+        }
+        Ok(out)
+    }
+}
+
+struct TsBuilder<'a, 'db> {
+    db: &'a Db<'db>,
+    decls: Decls<String>,
+}
+
+impl<'db> TsBuilder<'_, 'db> {
+    /// The name of the declaration for `typ`, adding it if need be.
+    fn decl_name(&mut self, typ: &ReflectedType<'db>) -> Result<String, ValueError> {
+        if let Some(name) = self.decls.name(typ.id) {
+            return Ok(name);
+        }
+        let name = self.decls.reserve(typ);
+        let decl = match &typ.typ {
+            ItemDeclaration::Struct(VariantData::Fields {
+                labels_for_serde,
+                fields,
+            }) => alloc::format!(
+                "export interface {} {}\n",
+                name,
+                self.fields(labels_for_serde, fields, "")?
+            ),
+            _ => {
+                let body = self.item(typ)?;
+                // unions start on a new line
+                let space = if body.starts_with('\n') { "" } else { " " };
+                alloc::format!("export type {} ={}{};\n", name, space, body)
+            }
+        };
+        self.decls.define(typ.id, decl);
+        Ok(name)
+    }
+
+    fn item(&mut self, typ: &ReflectedType<'db>) -> Result<String, ValueError> {
+        match &typ.typ {
+            ItemDeclaration::Newtype(inner) => self.decl_name(inner),
+            ItemDeclaration::Struct(data) => self.variant_data(data, ""),
+            ItemDeclaration::Enum { variants, .. } => {
+                let mut alternatives = Vec::new();
+                for arm in variants.iter() {
+                    alternatives.push(match &arm.variant {
+                        VariantData::Unit => string_literal(arm.label),
+                        data => alloc::format!(
+                            "{{ {}: {} }}",
+                            property(arm.label),
+                            self.variant_data(data, "  ")?
+                        ),
+                    });
+                }
+                if alternatives.is_empty() {
+                    return Ok("never".into());
+                }
+                let mut out = String::new();
+                for alt in alternatives {
+                    out.push_str("\n  | ");
+                    out.push_str(&alt);
+                }
+                Ok(out)
+            }
+        }
+    }
+
+    /// The type of some variant data, indented by `indent` if it spans lines.
+    fn variant_data(
+        &mut self,
+        data: &VariantData<'db>,
+        indent: &str,
+    ) -> Result<String, ValueError> {
+        match data {
+            VariantData::Unit => Ok("null".into()),
+            VariantData::Fields {
+                labels_for_serde,
+                fields,
+            } => self.fields(labels_for_serde, fields, indent),
+            // serde treats a single-field tuple struct as a newtype
+            VariantData::Tuple(fields) if fields.len() == 1 => {
+                self.field(attrs_of!(fields[0]), &fields[0].shape)
+            }
+            VariantData::Tuple(fields) => self.tuple(fields),
+        }
+    }
+
+    fn fields(
+        &mut self,
+        labels_for_serde: &'static [&'static str],
+        fields: &[Field<'db>],
+        indent: &str,
+    ) -> Result<String, ValueError> {
+        let mut out = String::from("{\n");
+        for (ix, f) in fields.iter().enumerate() {
+            let hooked = self.db.field_hook(attrs_of!(f), &f.shape)?.is_some();
+            let optional = match f.shape {
+                DataShape::Leaf(id) if !hooked => self
+                    .db
+                    .lookup(|db| &db.leaf_typescript, &id)
+                    .is_some_and(|ts| ts.optional),
+                _ => false,
+            };
+            let _ = writeln!(
+                out,
+                "{}  {}{}: {};",
+                indent,
+                property(field_label(labels_for_serde, ix, f)),
+                if optional { "?" } else { "" },
+                self.field(attrs_of!(f), &f.shape)?
+            );
+        }
+        out.push_str(indent);
+        out.push('}');
+        Ok(out)
+    }
+
+    fn tuple(&mut self, fields: &[TupleField<'db>]) -> Result<String, ValueError> {
+        let items = fields
+            .iter()
+            .map(|f| self.field(attrs_of!(f), &f.shape))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(alloc::format!("[{}]", items.join(", ")))
+    }
+
+    /// A field with a hook is `unknown`, since reflection can't see what the hook writes.
+    fn field(
+        &mut self,
+        attrs: Option<&[Attr]>,
+        shape: &DataShape<'db>,
+    ) -> Result<String, ValueError> {
+        match self.db.field_hook(attrs, shape)? {
+            Some(_) => Ok("unknown".into()),
+            None => self.shape(shape),
+        }
+    }
+
+    fn shape(&mut self, shape: &DataShape<'db>) -> Result<String, ValueError> {
+        let db = self.db;
+        match shape {
+            DataShape::Builtin(RustBuiltin::BOOLIN) => Ok("boolean".into()),
+            DataShape::Builtin(RustBuiltin::CHAR) => Ok("string".into()),
+            // even for 64- and 128-bit integers, since that is what `JSON.parse` gives
+            DataShape::Builtin(_) => Ok("number".into()),
+            &DataShape::Leaf(id) => {
+                match (db.leaf_type(id), db.lookup(|db| &db.leaf_typescript, &id)) {
+                    (Some(typ), _) => self.decl_name(typ),
+                    (None, Some(ts)) => Ok(ts.typ.clone()),
+                    (None, None) if id == TypeId::of::<String>() => Ok("string".into()),
+                    (None, None) => Ok("unknown".into()),
+                }
+            }
+            &DataShape::FixedArray(id, _) => Ok(array_of(self.shape(&element_shape(id))?)),
+            DataShape::Slice(elt) => Ok(array_of(self.shape(elt)?)),
+            DataShape::Ref(inner) => self.shape(inner),
+            DataShape::Tuple(fields) => self.tuple(fields),
+        }
+    }
+}
+
+fn array_of(elt: String) -> String {
+    if elt.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        alloc::format!("{}[]", elt)
+    } else {
+        alloc::format!("({})[]", elt)
+    }
+}
+
+/// A property name, quoted if it isn't an identifier.
+fn property(label: &str) -> String {
+    let ident = label.chars().enumerate().all(|(ix, c)| {
+        c == '_' || c == '$' || c.is_ascii_alphabetic() || (ix != 0 && c.is_ascii_digit())
+    });
+    if ident && !label.is_empty() {
+        label.into()
+    } else {
+        string_literal(label)
+    }
+}
+
+/// `s` as a string literal, escaped as JSON is, which is also valid TypeScript.
+fn string_literal(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            // older JavaScript doesn't allow the line separators in strings either
+            '\0'..='\u{1f}' | '\u{2028}' | '\u{2029}' => {
+                out.push_str(&alloc::format!("\\u{:04x}", c as u32))
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,893 @@
+use crate::metadata::*;
+use crate::place::Place;
+use crate::Db;
+
+use alloc::{boxed::Box, string::String, string::ToString, vec::Vec};
+use core::convert::TryFrom;
+use serde::de::{DeserializeSeed, Error as deError};
+use serde::ser::{
+    SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+    SerializeTupleStruct, SerializeTupleVariant,
+};
+
+/// An owned, dynamically typed value.
+///
+/// Values of types that only exist as a `ReflectedType` (for example, ones built at runtime
+/// and registered with `Db::insert`) have no Rust type to deserialize into. They can be
+/// deserialized into a `ReflectValue` instead with `Db::deserialize_value`, inspected, and
+/// serialized back out through its `Serialize` impl.
+///
+/// Leaf types without a reflected type, and fields with a `#[reflect(with = "...")]` hook, are
+/// deserialized with `deserialize_any`, and so only round-trip through self-describing formats.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReflectValue {
+    Unit,
+    Bool(bool),
+    Char(char),
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    U64(u64),
+    I64(i64),
+    U128(u128),
+    I128(i128),
+    F32(f32),
+    F64(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Option(Option<Box<ReflectValue>>),
+    /// A variable-length sequence, such as a `Vec`.
+    Seq(Vec<ReflectValue>),
+    /// A fixed-length sequence, such as a tuple or a `[T; n]`.
+    Tuple(Vec<ReflectValue>),
+    Map(Vec<(ReflectValue, ReflectValue)>),
+    /// `struct Foo;`
+    UnitStruct(&'static str),
+    /// `struct Foo(T);`
+    Newtype(&'static str, Box<ReflectValue>),
+    /// `struct Foo(T, U, ...);`
+    TupleStruct(&'static str, Vec<ReflectValue>),
+    /// `struct Foo { ... }`, fields in declaration order keyed by their serde label.
+    Struct(&'static str, Vec<(&'static str, ReflectValue)>),
+    /// One arm of `enum Foo { ... }`.
+    ///
+    /// `data` is `Unit`, `Newtype`, `TupleStruct` or `Struct` according to the kind of variant.
+    Variant {
+        name: &'static str,
+        label: &'static str,
+        variant_index: u32,
+        data: Box<ReflectValue>,
+    },
+}
+
+impl ReflectValue {
+    /// Look up a named field of a struct or struct variant, or a string key of a map.
+    pub fn get(&self, field: &str) -> Option<&ReflectValue> {
+        match self {
+            ReflectValue::Struct(_, fields) => fields
+                .iter()
+                .find(|(label, _)| *label == field)
+                .map(|(_, val)| val),
+            ReflectValue::Map(entries) => entries
+                .iter()
+                .find(|(key, _)| matches!(key, ReflectValue::Str(k) if k == field))
+                .map(|(_, val)| val),
+            ReflectValue::Variant { data, .. } => data.get(field),
+            _ => None,
+        }
+    }
+
+    /// Like `get`, but mutable.
+    pub fn get_mut(&mut self, field: &str) -> Option<&mut ReflectValue> {
+        match self {
+            ReflectValue::Struct(_, fields) => fields
+                .iter_mut()
+                .find(|(label, _)| *label == field)
+                .map(|(_, val)| val),
+            ReflectValue::Map(entries) => entries
+                .iter_mut()
+                .find(|(key, _)| matches!(key, ReflectValue::Str(k) if k == field))
+                .map(|(_, val)| val),
+            ReflectValue::Variant { data, .. } => data.get_mut(field),
+            _ => None,
+        }
+    }
+
+    /// Look up a positional element of a sequence, tuple, tuple struct or tuple variant.
+    ///
+    /// A newtype has a single element at index 0.
+    pub fn at(&self, ix: usize) -> Option<&ReflectValue> {
+        match self {
+            ReflectValue::Seq(elts)
+            | ReflectValue::Tuple(elts)
+            | ReflectValue::TupleStruct(_, elts) => elts.get(ix),
+            ReflectValue::Newtype(_, inner) if ix == 0 => Some(inner),
+            ReflectValue::Variant { data, .. } => data.at(ix),
+            _ => None,
+        }
+    }
+
+    /// Like `at`, but mutable.
+    pub fn at_mut(&mut self, ix: usize) -> Option<&mut ReflectValue> {
+        match self {
+            ReflectValue::Seq(elts)
+            | ReflectValue::Tuple(elts)
+            | ReflectValue::TupleStruct(_, elts) => elts.get_mut(ix),
+            ReflectValue::Newtype(_, inner) if ix == 0 => Some(inner),
+            ReflectValue::Variant { data, .. } => data.at_mut(ix),
+            _ => None,
+        }
+    }
+
+    /// The label of the active variant, if this is an enum value.
+    pub fn variant(&self) -> Option<&'static str> {
+        match self {
+            ReflectValue::Variant { label, .. } => Some(label),
+            _ => None,
+        }
+    }
+}
+
+impl<'db> Db<'db> {
+    /// Deserialize a value of type `typ` without needing a Rust type for it.
+    ///
+    /// `typ` need not be registered, but any types it refers to must be.
+    pub fn deserialize_value<'de, D>(
+        &self,
+        typ: &ReflectedType<'db>,
+        src: D,
+    ) -> Result<ReflectValue, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        ValueSeed::Type(self, typ).deserialize(src)
+    }
+}
+
+/// Seed for deserializing a `ReflectValue` of a particular reflected type or shape.
+///
+/// Useful when the dynamic value is nested inside some other `Deserialize` implementation.
+/// Otherwise, you're better off using `Db::deserialize_value`.
+pub enum ValueSeed<'a, 'db> {
+    Type(&'a Db<'db>, &'a ReflectedType<'db>),
+    Shape(&'a Db<'db>, &'a DataShape<'db>),
+    /// Whatever a self-describing format has, as for a field with a `#[reflect(with = "...")]`
+    /// hook.
+    Any,
+}
+
+impl<'de> DeserializeSeed<'de> for ValueSeed<'_, '_> {
+    type Value = ReflectValue;
+
+    fn deserialize<D>(self, src: D) -> Result<ReflectValue, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        match self {
+            ValueSeed::Type(db, typ) => match &typ.typ {
+                ItemDeclaration::Newtype(inner) => src.deserialize_newtype_struct(
+                    typ.name,
+                    ItemVisitor(db, typ, ItemKind::Newtype(inner)),
+                ),
+                ItemDeclaration::Struct(VariantData::Unit) => {
+                    src.deserialize_unit_struct(typ.name, ItemVisitor(db, typ, ItemKind::Unit))
+                }
+                ItemDeclaration::Struct(VariantData::Tuple(fields)) if fields.len() == 1 => src
+                    .deserialize_newtype_struct(
+                        typ.name,
+                        ItemVisitor(db, typ, ItemKind::Tuple(fields)),
+                    ),
+                ItemDeclaration::Struct(VariantData::Tuple(fields)) => src
+                    .deserialize_tuple_struct(
+                        typ.name,
+                        fields.len(),
+                        ItemVisitor(db, typ, ItemKind::Tuple(fields)),
+                    ),
+                ItemDeclaration::Struct(VariantData::Fields {
+                    labels_for_serde,
+                    fields,
+                }) => src.deserialize_struct(
+                    typ.name,
+                    labels_for_serde,
+                    ItemVisitor(db, typ, ItemKind::Fields(labels_for_serde, fields)),
+                ),
+                ItemDeclaration::Enum {
+                    variant_labels_for_serde,
+                    variants,
+                } => src.deserialize_enum(
+                    typ.name,
+                    variant_labels_for_serde,
+                    EnumValueVisitor(db, typ, variant_labels_for_serde, variants),
+                ),
+            },
+            ValueSeed::Shape(db, shape) => match shape {
+                DataShape::Leaf(id) => match db.leaf_type(*id) {
+                    Some(typ) => ValueSeed::Type(db, typ).deserialize(src),
+                    None => src.deserialize_any(AnyVisitor),
+                },
+                DataShape::Builtin(builtin) => deserialize_builtin(*builtin, src),
+                &DataShape::FixedArray(id, len) => {
+                    src.deserialize_tuple(len, ArrayVisitor(db, id, len))
+                }
+                DataShape::Tuple(fields) => src.deserialize_tuple(
+                    fields.len(),
+                    SeqFieldsVisitor(
+                        db,
+                        field_places(db, fields.iter().map(|f| (attrs_of!(f), &*f.shape)))?,
+                        ReflectValue::Tuple,
+                    ),
+                ),
+                DataShape::Slice(_) | DataShape::Ref(_) => Err(deError::custom(
+                    "cannot deserialize a borrowed shape into an owned value",
+                )),
+            },
+            ValueSeed::Any => src.deserialize_any(AnyVisitor),
+        }
+    }
+}
+
+fn deserialize_builtin<'de, D>(builtin: RustBuiltin, src: D) -> Result<ReflectValue, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let v = BuiltinVisitor(builtin);
+    match builtin {
+        RustBuiltin::U8 => src.deserialize_u8(v),
+        RustBuiltin::I8 => src.deserialize_i8(v),
+        RustBuiltin::U16 => src.deserialize_u16(v),
+        RustBuiltin::I16 => src.deserialize_i16(v),
+        RustBuiltin::U32 => src.deserialize_u32(v),
+        RustBuiltin::I32 => src.deserialize_i32(v),
+        RustBuiltin::U64 => src.deserialize_u64(v),
+        RustBuiltin::I64 => src.deserialize_i64(v),
+        RustBuiltin::U128 => src.deserialize_u128(v),
+        RustBuiltin::I128 => src.deserialize_i128(v),
+        RustBuiltin::BOOLIN => src.deserialize_bool(v),
+        RustBuiltin::CHAR => src.deserialize_char(v),
+    }
+}
+
+/// Read the value of a builtin out of memory.
+///
+/// SAFETY: `ptr` must point to an initialized value of the builtin's type.
+pub(crate) unsafe fn read_builtin(builtin: RustBuiltin, ptr: *const u8) -> ReflectValue {
+    match builtin {
+        RustBuiltin::U8 => ReflectValue::U8(ptr.read()),
+        RustBuiltin::I8 => ReflectValue::I8(ptr.cast::<i8>().read()),
+        RustBuiltin::U16 => ReflectValue::U16(ptr.cast::<u16>().read()),
+        RustBuiltin::I16 => ReflectValue::I16(ptr.cast::<i16>().read()),
+        RustBuiltin::U32 => ReflectValue::U32(ptr.cast::<u32>().read()),
+        RustBuiltin::I32 => ReflectValue::I32(ptr.cast::<i32>().read()),
+        RustBuiltin::U64 => ReflectValue::U64(ptr.cast::<u64>().read()),
+        RustBuiltin::I64 => ReflectValue::I64(ptr.cast::<i64>().read()),
+        RustBuiltin::U128 => ReflectValue::U128(ptr.cast::<u128>().read()),
+        RustBuiltin::I128 => ReflectValue::I128(ptr.cast::<i128>().read()),
+        RustBuiltin::BOOLIN => ReflectValue::Bool(ptr.cast::<bool>().read()),
+        RustBuiltin::CHAR => ReflectValue::Char(ptr.cast::<char>().read()),
+    }
+}
+
+/// Build the value of an integer builtin, failing if `v` doesn't fit.
+pub(crate) fn builtin_int(builtin: RustBuiltin, v: i128) -> Option<ReflectValue> {
+    Some(match builtin {
+        RustBuiltin::U8 => ReflectValue::U8(u8::try_from(v).ok()?),
+        RustBuiltin::I8 => ReflectValue::I8(i8::try_from(v).ok()?),
+        RustBuiltin::U16 => ReflectValue::U16(u16::try_from(v).ok()?),
+        RustBuiltin::I16 => ReflectValue::I16(i16::try_from(v).ok()?),
+        RustBuiltin::U32 => ReflectValue::U32(u32::try_from(v).ok()?),
+        RustBuiltin::I32 => ReflectValue::I32(i32::try_from(v).ok()?),
+        RustBuiltin::U64 => ReflectValue::U64(u64::try_from(v).ok()?),
+        RustBuiltin::I64 => ReflectValue::I64(i64::try_from(v).ok()?),
+        RustBuiltin::U128 => ReflectValue::U128(u128::try_from(v).ok()?),
+        RustBuiltin::I128 => ReflectValue::I128(v),
+        RustBuiltin::BOOLIN | RustBuiltin::CHAR => return None,
+    })
+}
+
+struct BuiltinVisitor(RustBuiltin);
+impl<'de> serde::de::Visitor<'de> for BuiltinVisitor {
+    type Value = ReflectValue;
+
+    fn visit_bool<E: deError>(self, v: bool) -> Result<ReflectValue, E> {
+        match self.0 {
+            RustBuiltin::BOOLIN => Ok(ReflectValue::Bool(v)),
+            _ => Err(E::invalid_type(serde::de::Unexpected::Bool(v), &self)),
+        }
+    }
+    fn visit_i64<E: deError>(self, v: i64) -> Result<ReflectValue, E> {
+        self.visit_i128(v as i128)
+    }
+    fn visit_i128<E: deError>(self, v: i128) -> Result<ReflectValue, E> {
+        builtin_int(self.0, v)
+            .ok_or_else(|| E::invalid_value(serde::de::Unexpected::Signed(v as i64), &self))
+    }
+    fn visit_u64<E: deError>(self, v: u64) -> Result<ReflectValue, E> {
+        self.visit_i128(v as i128)
+    }
+    fn visit_u128<E: deError>(self, v: u128) -> Result<ReflectValue, E> {
+        match self.0 {
+            RustBuiltin::U128 => Ok(ReflectValue::U128(v)),
+            _ => i128::try_from(v)
+                .map_err(|_| E::invalid_value(serde::de::Unexpected::Other("u128"), &self))
+                .and_then(|v| self.visit_i128(v)),
+        }
+    }
+    fn visit_char<E: deError>(self, v: char) -> Result<ReflectValue, E> {
+        match self.0 {
+            RustBuiltin::CHAR => Ok(ReflectValue::Char(v)),
+            _ => Err(E::invalid_type(serde::de::Unexpected::Char(v), &self)),
+        }
+    }
+    fn visit_str<E: deError>(self, v: &str) -> Result<ReflectValue, E> {
+        let mut chars = v.chars();
+        match (self.0, chars.next(), chars.next()) {
+            (RustBuiltin::CHAR, Some(c), None) => Ok(ReflectValue::Char(c)),
+            _ => Err(E::invalid_type(serde::de::Unexpected::Str(v), &self)),
+        }
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(fmt, "a {:?}", self.0)
+    }
+}
+
+struct ArrayVisitor<'a, 'db>(&'a Db<'db>, core::any::TypeId, usize);
+impl<'de> serde::de::Visitor<'de> for ArrayVisitor<'_, '_> {
+    type Value = ReflectValue;
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<ReflectValue, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let ArrayVisitor(db, id, len) = self;
+        let elt = element_shape(id);
+        let mut elts = Vec::with_capacity(len);
+        for ix in 0..len {
+            match seq.next_element_seed(ValueSeed::Shape(db, &elt))? {
+                Some(v) => elts.push(v),
+                None => return Err(A::Error::invalid_length(ix, &self)),
+            }
+        }
+        Ok(ReflectValue::Tuple(elts))
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(fmt, "an array of length {}", self.2)
+    }
+}
+
+/// The shape of the elements of a `DataShape::FixedArray`.
+pub(crate) fn element_shape<'db>(id: core::any::TypeId) -> DataShape<'db> {
+    RustBuiltin::of(id).map_or(DataShape::Leaf(id), DataShape::Builtin)
+}
+
+/// The places of fields with the given attributes and shapes, or an error if one of them asks
+/// for a hook that doesn't fit.
+fn field_places<'a, 'db: 'a, E: deError>(
+    db: &Db<'db>,
+    fields: impl Iterator<Item = (Option<&'a [Attr]>, &'a DataShape<'db>)>,
+) -> Result<Vec<Place<'a, 'db>>, E> {
+    fields
+        .map(|(attrs, shape)| db.field_place(attrs, shape).map_err(E::custom))
+        .collect()
+}
+
+/// Visits a sequence of values in known places, wrapping them up with `wrap`.
+struct SeqFieldsVisitor<'a, 'db, W>(&'a Db<'db>, Vec<Place<'a, 'db>>, W);
+impl<'a, 'db: 'a, 'de, W> serde::de::Visitor<'de> for SeqFieldsVisitor<'a, 'db, W>
+where
+    W: FnOnce(Vec<ReflectValue>) -> ReflectValue,
+{
+    type Value = ReflectValue;
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<ReflectValue, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let SeqFieldsVisitor(db, places, wrap) = self;
+        let mut elts = Vec::with_capacity(places.len());
+        for (ix, place) in places.into_iter().enumerate() {
+            match seq.next_element_seed(place.seed(db))? {
+                Some(v) => elts.push(v),
+                None => {
+                    return Err(A::Error::invalid_length(
+                        ix,
+                        &"a sequence with an element for each field",
+                    ))
+                }
+            }
+        }
+        Ok(wrap(elts))
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(fmt, "a sequence of {} elements", self.1.len())
+    }
+}
+
+/// Visits the named fields of a struct or struct variant, in any order.
+fn visit_fields_map<'de, 'db, A>(
+    db: &Db<'db>,
+    labels_for_serde: &'static [&'static str],
+    fields: &[Field<'db>],
+    mut map: A,
+) -> Result<Vec<(&'static str, ReflectValue)>, A::Error>
+where
+    A: serde::de::MapAccess<'de>,
+{
+    let mut vals: Vec<Option<ReflectValue>> = fields.iter().map(|_| None).collect();
+    while let Some(key) = map.next_key::<String>()? {
+        match fields
+            .iter()
+            .enumerate()
+            .position(|(ix, f)| field_label(labels_for_serde, ix, f) == key)
+        {
+            Some(ix) => {
+                if vals[ix].is_some() {
+                    return Err(A::Error::duplicate_field(field_label(
+                        labels_for_serde,
+                        ix,
+                        &fields[ix],
+                    )));
+                }
+                let place = db
+                    .field_place(attrs_of!(fields[ix]), &fields[ix].shape)
+                    .map_err(A::Error::custom)?;
+                vals[ix] = Some(map.next_value_seed(place.seed(db))?);
+            }
+            // skipped, as serde_derive does
+            None => {
+                map.next_value::<serde::de::IgnoredAny>()?;
+            }
+        }
+    }
+    fields
+        .iter()
+        .enumerate()
+        .zip(vals)
+        .map(|((ix, f), val)| {
+            let label = field_label(labels_for_serde, ix, f);
+            // a missing `Option` is `None`, as serde_derive reads it
+            let none = match f.shape {
+                DataShape::Leaf(id)
+                    if matches!(db.field_hook(attrs_of!(f), &f.shape), Ok(None)) =>
+                {
+                    db.option_leaf(id).map(|_| ReflectValue::Option(None))
+                }
+                _ => None,
+            };
+            val.or(none)
+                .map(|val| (label, val))
+                .ok_or_else(|| A::Error::missing_field(label))
+        })
+        .collect()
+}
+
+/// The label serde knows field `ix` by.
+pub(crate) fn field_label(
+    labels_for_serde: &'static [&'static str],
+    ix: usize,
+    field: &Field<'_>,
+) -> &'static str {
+    labels_for_serde.get(ix).copied().unwrap_or(field.name)
+}
+
+enum ItemKind<'a, 'db> {
+    Unit,
+    Newtype(&'a ReflectedType<'db>),
+    Tuple(&'a [TupleField<'db>]),
+    Fields(&'static [&'static str], &'a [Field<'db>]),
+}
+
+struct ItemVisitor<'a, 'db>(&'a Db<'db>, &'a ReflectedType<'db>, ItemKind<'a, 'db>);
+impl<'de> serde::de::Visitor<'de> for ItemVisitor<'_, '_> {
+    type Value = ReflectValue;
+
+    fn visit_unit<E: deError>(self) -> Result<ReflectValue, E> {
+        match self.2 {
+            ItemKind::Unit => Ok(ReflectValue::UnitStruct(self.1.name)),
+            _ => Err(E::invalid_type(serde::de::Unexpected::Unit, &self)),
+        }
+    }
+
+    fn visit_newtype_struct<D>(self, src: D) -> Result<ReflectValue, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let ItemVisitor(db, typ, kind) = self;
+        let inner = match kind {
+            ItemKind::Newtype(inner) => ValueSeed::Type(db, inner).deserialize(src)?,
+            ItemKind::Tuple([field]) => db
+                .field_place(attrs_of!(field), &field.shape)
+                .map_err(deError::custom)?
+                .seed(db)
+                .deserialize(src)?,
+            _ => return Err(deError::custom("visited a newtype when not expecting one")),
+        };
+        Ok(ReflectValue::Newtype(typ.name, Box::new(inner)))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<ReflectValue, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let ItemVisitor(db, typ, kind) = self;
+        match kind {
+            ItemKind::Newtype(inner) => match seq.next_element_seed(ValueSeed::Type(db, inner))? {
+                Some(v) => Ok(ReflectValue::Newtype(typ.name, Box::new(v))),
+                None => Err(A::Error::invalid_length(0, &"a newtype")),
+            },
+            ItemKind::Tuple([field]) => {
+                let place = db
+                    .field_place(attrs_of!(field), &field.shape)
+                    .map_err(A::Error::custom)?;
+                match seq.next_element_seed(place.seed(db))? {
+                    Some(v) => Ok(ReflectValue::Newtype(typ.name, Box::new(v))),
+                    None => Err(A::Error::invalid_length(0, &"a newtype")),
+                }
+            }
+            ItemKind::Tuple(fields) => {
+                let places = field_places(db, fields.iter().map(|f| (attrs_of!(f), &*f.shape)))?;
+                SeqFieldsVisitor(db, places, |elts| ReflectValue::TupleStruct(typ.name, elts))
+                    .visit_seq(seq)
+            }
+            ItemKind::Fields(labels_for_serde, fields) => {
+                let places = field_places(db, fields.iter().map(|f| (attrs_of!(f), &f.shape)))?;
+                let vals = SeqFieldsVisitor(db, places, ReflectValue::Tuple).visit_seq(seq)?;
+                match vals {
+                    ReflectValue::Tuple(vals) => Ok(ReflectValue::Struct(
+                        typ.name,
+                        fields
+                            .iter()
+                            .enumerate()
+                            .map(|(ix, f)| field_label(labels_for_serde, ix, f))
+                            .zip(vals)
+                            .collect(),
+                    )),
+                    _ => unreachable!(),
+                }
+            }
+            ItemKind::Unit => Err(deError::custom("visited a sequence for a unit struct")),
+        }
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<ReflectValue, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        let ItemVisitor(db, typ, kind) = self;
+        match kind {
+            ItemKind::Fields(labels_for_serde, fields) => Ok(ReflectValue::Struct(
+                typ.name,
+                visit_fields_map(db, labels_for_serde, fields, map)?,
+            )),
+            _ => Err(deError::custom(
+                "visited a map when not expecting named fields",
+            )),
+        }
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(fmt, "a value of type {}", self.1.name)
+    }
+}
+
+struct EnumValueVisitor<'a, 'db>(
+    &'a Db<'db>,
+    &'a ReflectedType<'db>,
+    &'static [&'static str],
+    &'a [EnumArm<'db>],
+);
+impl<'de> serde::de::Visitor<'de> for EnumValueVisitor<'_, '_> {
+    type Value = ReflectValue;
+
+    fn visit_enum<A>(self, data: A) -> Result<ReflectValue, A::Error>
+    where
+        A: serde::de::EnumAccess<'de>,
+    {
+        use serde::de::VariantAccess;
+        let EnumValueVisitor(db, typ, labels, variants) = self;
+        let (ix, variant) = data.variant_seed(ArmIx(labels, variants))?;
+        let arm = &variants[ix];
+        let data = match &arm.variant {
+            VariantData::Unit => {
+                variant.unit_variant()?;
+                ReflectValue::Unit
+            }
+            VariantData::Tuple(fields) if fields.len() == 1 => {
+                let place = db
+                    .field_place(attrs_of!(fields[0]), &fields[0].shape)
+                    .map_err(A::Error::custom)?;
+                ReflectValue::Newtype(
+                    arm.label,
+                    Box::new(variant.newtype_variant_seed(place.seed(db))?),
+                )
+            }
+            VariantData::Tuple(fields) => variant.tuple_variant(
+                fields.len(),
+                SeqFieldsVisitor(
+                    db,
+                    field_places(db, fields.iter().map(|f| (attrs_of!(f), &*f.shape)))?,
+                    |elts| ReflectValue::TupleStruct(arm.label, elts),
+                ),
+            )?,
+            VariantData::Fields {
+                labels_for_serde,
+                fields,
+            } => variant.struct_variant(
+                labels_for_serde,
+                ItemVisitor(db, typ, ItemKind::Fields(labels_for_serde, fields)),
+            )?,
+        };
+        let data = match data {
+            ReflectValue::Struct(_, fields) => ReflectValue::Struct(arm.label, fields),
+            data => data,
+        };
+        Ok(ReflectValue::Variant {
+            name: typ.name,
+            label: arm.label,
+            variant_index: arm.variant_index as u32,
+            data: Box::new(data),
+        })
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(fmt, "a variant of {}", self.1.name)
+    }
+}
+
+/// Finds the index of an arm from its label or variant index.
+pub(crate) struct ArmIx<'a, 'db>(pub &'static [&'static str], pub &'a [EnumArm<'db>]);
+impl<'de> serde::de::Visitor<'de> for ArmIx<'_, '_> {
+    type Value = usize;
+
+    fn visit_u64<E: deError>(self, v: u64) -> Result<usize, E> {
+        self.1
+            .iter()
+            .position(|arm| arm.variant_index as u64 == v)
+            .ok_or_else(|| E::unknown_variant(&v.to_string(), self.0))
+    }
+    fn visit_str<E: deError>(self, v: &str) -> Result<usize, E> {
+        self.1
+            .iter()
+            .position(|arm| arm.label == v)
+            .ok_or_else(|| E::unknown_variant(v, self.0))
+    }
+    fn visit_bytes<E: deError>(self, v: &[u8]) -> Result<usize, E> {
+        self.1
+            .iter()
+            .position(|arm| arm.label.as_bytes() == v)
+            .ok_or_else(|| {
+                E::unknown_variant(
+                    core::str::from_utf8(v).unwrap_or("<non-utf8 variant>"),
+                    self.0,
+                )
+            })
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fmt.write_str("an enum variant")
+    }
+}
+impl<'de> DeserializeSeed<'de> for ArmIx<'_, '_> {
+    type Value = usize;
+    fn deserialize<D>(self, deserializer: D) -> Result<usize, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+/// Builds a `ReflectValue` out of whatever a self-describing format has to offer.
+pub(crate) struct AnyVisitor;
+impl<'de> serde::de::Visitor<'de> for AnyVisitor {
+    type Value = ReflectValue;
+
+    fn visit_bool<E: deError>(self, v: bool) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::Bool(v))
+    }
+    fn visit_i8<E: deError>(self, v: i8) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::I8(v))
+    }
+    fn visit_i16<E: deError>(self, v: i16) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::I16(v))
+    }
+    fn visit_i32<E: deError>(self, v: i32) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::I32(v))
+    }
+    fn visit_i64<E: deError>(self, v: i64) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::I64(v))
+    }
+    fn visit_i128<E: deError>(self, v: i128) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::I128(v))
+    }
+    fn visit_u8<E: deError>(self, v: u8) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::U8(v))
+    }
+    fn visit_u16<E: deError>(self, v: u16) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::U16(v))
+    }
+    fn visit_u32<E: deError>(self, v: u32) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::U32(v))
+    }
+    fn visit_u64<E: deError>(self, v: u64) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::U64(v))
+    }
+    fn visit_u128<E: deError>(self, v: u128) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::U128(v))
+    }
+    fn visit_f32<E: deError>(self, v: f32) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::F32(v))
+    }
+    fn visit_f64<E: deError>(self, v: f64) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::F64(v))
+    }
+    fn visit_char<E: deError>(self, v: char) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::Char(v))
+    }
+    fn visit_str<E: deError>(self, v: &str) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::Str(v.into()))
+    }
+    fn visit_string<E: deError>(self, v: String) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::Str(v))
+    }
+    fn visit_bytes<E: deError>(self, v: &[u8]) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::Bytes(v.into()))
+    }
+    fn visit_byte_buf<E: deError>(self, v: Vec<u8>) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::Bytes(v))
+    }
+    fn visit_none<E: deError>(self) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::Option(None))
+    }
+    fn visit_some<D>(self, src: D) -> Result<ReflectValue, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Ok(ReflectValue::Option(Some(Box::new(
+            src.deserialize_any(AnyVisitor)?,
+        ))))
+    }
+    fn visit_unit<E: deError>(self) -> Result<ReflectValue, E> {
+        Ok(ReflectValue::Unit)
+    }
+    fn visit_newtype_struct<D>(self, src: D) -> Result<ReflectValue, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        src.deserialize_any(AnyVisitor)
+    }
+    fn visit_seq<A>(self, mut seq: A) -> Result<ReflectValue, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let mut elts = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(elt) = seq.next_element_seed(AnySeed)? {
+            elts.push(elt);
+        }
+        Ok(ReflectValue::Seq(elts))
+    }
+    fn visit_map<A>(self, mut map: A) -> Result<ReflectValue, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some(entry) = map.next_entry_seed(AnySeed, AnySeed)? {
+            entries.push(entry);
+        }
+        Ok(ReflectValue::Map(entries))
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fmt.write_str("any value")
+    }
+}
+
+pub(crate) struct AnySeed;
+impl<'de> DeserializeSeed<'de> for AnySeed {
+    type Value = ReflectValue;
+    fn deserialize<D>(self, src: D) -> Result<ReflectValue, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        src.deserialize_any(AnyVisitor)
+    }
+}
+
+impl serde::Serialize for ReflectValue {
+    fn serialize<S>(&self, dst: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            ReflectValue::Unit => dst.serialize_unit(),
+            ReflectValue::Bool(v) => dst.serialize_bool(*v),
+            ReflectValue::Char(v) => dst.serialize_char(*v),
+            ReflectValue::U8(v) => dst.serialize_u8(*v),
+            ReflectValue::I8(v) => dst.serialize_i8(*v),
+            ReflectValue::U16(v) => dst.serialize_u16(*v),
+            ReflectValue::I16(v) => dst.serialize_i16(*v),
+            ReflectValue::U32(v) => dst.serialize_u32(*v),
+            ReflectValue::I32(v) => dst.serialize_i32(*v),
+            ReflectValue::U64(v) => dst.serialize_u64(*v),
+            ReflectValue::I64(v) => dst.serialize_i64(*v),
+            ReflectValue::U128(v) => dst.serialize_u128(*v),
+            ReflectValue::I128(v) => dst.serialize_i128(*v),
+            ReflectValue::F32(v) => dst.serialize_f32(*v),
+            ReflectValue::F64(v) => dst.serialize_f64(*v),
+            ReflectValue::Str(v) => dst.serialize_str(v),
+            ReflectValue::Bytes(v) => dst.serialize_bytes(v),
+            ReflectValue::Option(None) => dst.serialize_none(),
+            ReflectValue::Option(Some(v)) => dst.serialize_some(v),
+            ReflectValue::Seq(elts) => {
+                let mut seq = dst.serialize_seq(Some(elts.len()))?;
+                for elt in elts {
+                    seq.serialize_element(elt)?;
+                }
+                seq.end()
+            }
+            ReflectValue::Tuple(elts) => {
+                let mut tup = dst.serialize_tuple(elts.len())?;
+                for elt in elts {
+                    tup.serialize_element(elt)?;
+                }
+                tup.end()
+            }
+            ReflectValue::Map(entries) => {
+                let mut map = dst.serialize_map(Some(entries.len()))?;
+                for (k, v) in entries {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+            ReflectValue::UnitStruct(name) => dst.serialize_unit_struct(name),
+            ReflectValue::Newtype(name, inner) => dst.serialize_newtype_struct(name, inner),
+            ReflectValue::TupleStruct(name, elts) => {
+                let mut tup = dst.serialize_tuple_struct(name, elts.len())?;
+                for elt in elts {
+                    tup.serialize_field(elt)?;
+                }
+                tup.end()
+            }
+            ReflectValue::Struct(name, fields) => {
+                let mut struc = dst.serialize_struct(name, fields.len())?;
+                for (label, val) in fields {
+                    struc.serialize_field(label, val)?;
+                }
+                struc.end()
+            }
+            ReflectValue::Variant {
+                name,
+                label,
+                variant_index,
+                data,
+            } => match &**data {
+                ReflectValue::Unit => dst.serialize_unit_variant(name, *variant_index, label),
+                ReflectValue::Newtype(_, inner) => {
+                    dst.serialize_newtype_variant(name, *variant_index, label, inner)
+                }
+                ReflectValue::TupleStruct(_, elts) => {
+                    let mut tup =
+                        dst.serialize_tuple_variant(name, *variant_index, label, elts.len())?;
+                    for elt in elts {
+                        tup.serialize_field(elt)?;
+                    }
+                    tup.end()
+                }
+                ReflectValue::Struct(_, fields) => {
+                    let mut struc =
+                        dst.serialize_struct_variant(name, *variant_index, label, fields.len())?;
+                    for (field_label, val) in fields {
+                        struc.serialize_field(field_label, val)?;
+                    }
+                    struc.end()
+                }
+                other => dst.serialize_newtype_variant(name, *variant_index, label, other),
+            },
+        }
+    }
+}
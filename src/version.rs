@@ -0,0 +1,195 @@
+use crate::convert::ValueError;
+use crate::metadata::*;
+use crate::value::{AnySeed, ReflectValue};
+use crate::Db;
+
+use alloc::string::String;
+use core::any::{Any, TypeId};
+use core::marker::PhantomData;
+use serde::de::{DeserializeSeed, Error as deError};
+use serde::ser::SerializeStruct;
+
+/// Turns a value written by version `N` of a type into one for version `N + 1`.
+pub type Migration = fn(ReflectValue) -> Result<ReflectValue, String>;
+
+const ENVELOPE_FIELDS: &[&str] = &["version", "data"];
+
+impl<'db> Db<'db> {
+    /// Schema version of the registered type `T`.
+    ///
+    /// With the `attrs` feature this is set with `#[reflect(version = N)]` on the type.
+    /// Types without one are at version 0.
+    pub fn version_of<T: Any>(&self) -> Result<u32, ValueError> {
+        let typ = self
+            .known_type(TypeId::of::<T>())
+            .ok_or_else(|| ValueError("type missing from db, cannot find version".into()))?;
+        version(typ)
+    }
+
+    /// Use `migrate` to bring values of `T` written at version `from` up to version `from + 1`.
+    pub fn register_migration<T: Any>(&mut self, from: u32, migrate: Migration) -> &mut Db<'db> {
+        self.migrations.insert((TypeId::of::<T>(), from), migrate);
+        self
+    }
+
+    /// Serialize a value along with the version of its type, as a struct with `version` and
+    /// `data` fields, for reading back with `deserialize_versioned`.
+    pub fn serialize_versioned<S: serde::Serializer, T: Any>(
+        &self,
+        s: S,
+        val: &T,
+    ) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+        let version = self.version_of::<T>().map_err(S::Error::custom)?;
+        let mut envelope = s.serialize_struct("Versioned", 2)?;
+        envelope.serialize_field("version", &version)?;
+        envelope.serialize_field("data", &Data(self, val))?;
+        envelope.end()
+    }
+
+    /// Deserialize a value written by `serialize_versioned`, possibly by an older version of
+    /// its type.
+    ///
+    /// Data at the current version is deserialized directly. Older data is read as a
+    /// `ReflectValue`, which needs a self-describing format, and passed through the registered
+    /// migrations one version at a time before being converted to `T`.
+    pub fn deserialize_versioned<'de, T: Any, D>(&self, src: D) -> Result<T, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let current = self.version_of::<T>().map_err(deError::custom)?;
+        src.deserialize_struct(
+            "Versioned",
+            ENVELOPE_FIELDS,
+            Envelope::<T>(self, current, PhantomData),
+        )
+    }
+
+    /// Bring `val`, written at version `from` of `T`, up to version `to`.
+    pub fn migrate<T: Any>(
+        &self,
+        mut val: ReflectValue,
+        from: u32,
+        to: u32,
+    ) -> Result<ReflectValue, ValueError> {
+        if from > to {
+            return Err(ValueError(alloc::format!(
+                "data is from version {}, newer than version {}",
+                from,
+                to
+            )));
+        }
+        for version in from..to {
+            let migrate = self
+                .lookup(|db| &db.migrations, &(TypeId::of::<T>(), version))
+                .ok_or_else(|| {
+                    ValueError(alloc::format!("no migration from version {}", version))
+                })?;
+            val = migrate(val).map_err(|e| {
+                ValueError(alloc::format!("migrating from version {}: {}", version, e))
+            })?;
+        }
+        Ok(val)
+    }
+}
+
+#[allow(unused_variables)]
+fn version(typ: &ReflectedType<'_>) -> Result<u32, ValueError> {
+    #[cfg(feature = "attrs")]
+    if let Some(val) = Attr::reflect_option(&typ.attrs, "version") {
+        return match val {
+            &RustPrimitive::Int(n) if n >= 0 && n <= u32::MAX as i128 => Ok(n as u32),
+            other => Err(ValueError(alloc::format!(
+                "`{:?}` is not a version of {}",
+                other,
+                typ.name
+            ))),
+        };
+    }
+    Ok(0)
+}
+
+struct Data<'a, 'db, T>(&'a Db<'db>, &'a T);
+
+impl<T: Any> serde::Serialize for Data<'_, '_, T> {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(s, self.1)
+    }
+}
+
+/// Deserializes a `T` in place, via reflection.
+struct Direct<'a, 'db, T>(&'a Db<'db>, PhantomData<T>);
+
+impl<'de, T: Any> DeserializeSeed<'de> for Direct<'_, '_, T> {
+    type Value = T;
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, src: D) -> Result<T, D::Error> {
+        self.0.deserialize(src)
+    }
+}
+
+/// Visits the `(version, data)` envelope, for a type currently at the given version.
+struct Envelope<'a, 'db, T>(&'a Db<'db>, u32, PhantomData<T>);
+
+impl<T: Any> Envelope<'_, '_, T> {
+    fn upgrade<E: deError>(&self, val: ReflectValue, from: u32) -> Result<T, E> {
+        let db = self.0;
+        db.migrate::<T>(val, from, self.1)
+            .and_then(|val| db.from_value(val))
+            .map_err(E::custom)
+    }
+}
+
+impl<'de, T: Any> serde::de::Visitor<'de> for Envelope<'_, '_, T> {
+    type Value = T;
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<T, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let version: u32 = seq
+            .next_element()?
+            .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+        if version == self.1 {
+            return seq
+                .next_element_seed(Direct(self.0, PhantomData))?
+                .ok_or_else(|| A::Error::invalid_length(1, &self));
+        }
+        let val = seq
+            .next_element_seed(AnySeed)?
+            .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+        self.upgrade(val, version)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<T, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        let mut version = None;
+        // data seen before the version, to be migrated once the version is known
+        let mut early = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "version" => version = Some(map.next_value::<u32>()?),
+                "data" => match version {
+                    Some(v) if v == self.1 => {
+                        let val = map.next_value_seed(Direct(self.0, PhantomData))?;
+                        while map.next_key::<serde::de::IgnoredAny>()?.is_some() {
+                            map.next_value::<serde::de::IgnoredAny>()?;
+                        }
+                        return Ok(val);
+                    }
+                    _ => early = Some(map.next_value_seed(AnySeed)?),
+                },
+                other => return Err(A::Error::unknown_field(other, ENVELOPE_FIELDS)),
+            }
+        }
+        let version = version.ok_or_else(|| A::Error::missing_field("version"))?;
+        let val = early.ok_or_else(|| A::Error::missing_field("data"))?;
+        self.upgrade(val, version)
+    }
+
+    fn expecting(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fmt.write_str("a version number and data")
+    }
+}
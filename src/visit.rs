@@ -0,0 +1,526 @@
+use crate::convert::{ValueError, ValueSerializer};
+use crate::hook::Hook;
+use crate::metadata::*;
+use crate::path::{FieldPath, PathSegment};
+use crate::place::{write_builtin, Place};
+use crate::value::{element_shape, field_label, read_builtin, ReflectValue};
+use crate::{active_arm, Db, TypedLocation, TypedOutputLocation};
+
+use core::{
+    any::{Any, TypeId},
+    marker::PhantomData,
+};
+use serde::de::DeserializeSeed;
+
+/// Whether to go inside the thing just visited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    /// Don't visit anything inside this.
+    Skip,
+}
+
+/// A generic algorithm over reflected values, driven by `Db::walk`.
+///
+/// Every method has a default that does nothing and keeps going, so implement just the ones
+/// you care about. `path` is always the location of the thing being visited, relative to the
+/// value passed to `Db::walk`.
+#[allow(unused_variables)]
+pub trait ReflectVisitor {
+    /// A struct, tuple struct, unit struct or newtype. Its fields are visited next.
+    fn visit_struct(&mut self, path: &FieldPath, typ: &ReflectedType<'_>) -> Flow {
+        Flow::Continue
+    }
+    /// A named field of a struct or struct variant. Its value is visited next.
+    fn visit_field(&mut self, path: &FieldPath, field: &Field<'_>) -> Flow {
+        Flow::Continue
+    }
+    /// A positional field of a tuple, tuple struct or tuple variant. Its value is visited next.
+    fn visit_tuple_field(&mut self, path: &FieldPath, field: &TupleField<'_>) -> Flow {
+        Flow::Continue
+    }
+    /// An enum. Its active variant is visited next.
+    fn visit_enum(&mut self, path: &FieldPath, typ: &ReflectedType<'_>) -> Flow {
+        Flow::Continue
+    }
+    /// The active variant of an enum. Its fields are visited next.
+    fn visit_variant(&mut self, path: &FieldPath, arm: &EnumArm<'_>) -> Flow {
+        Flow::Continue
+    }
+    /// A fixed-size array of `len` elements. The elements are visited next.
+    fn visit_seq(&mut self, path: &FieldPath, len: usize) -> Flow {
+        Flow::Continue
+    }
+    /// A builtin value.
+    fn visit_builtin(&mut self, path: &FieldPath, builtin: RustBuiltin, value: ReflectValue) {}
+    /// A leaf, which reflection doesn't see inside of, or a field with a hook.
+    fn visit_leaf(&mut self, path: &FieldPath, leaf: Leaf<'_, '_>) {}
+}
+
+/// A leaf value encountered by a `ReflectVisitor`.
+pub struct Leaf<'a, 'db> {
+    db: &'a Db<'db>,
+    id: TypeId,
+    /// The hook of the field the leaf is in, which reads and writes it instead.
+    hook: Option<Hook>,
+    ptr: *mut u8,
+}
+
+impl Leaf<'_, '_> {
+    /// Type id of the leaf.
+    pub fn type_id(&self) -> TypeId {
+        self.id
+    }
+
+    /// The leaf, if it is a `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        if self.id == TypeId::of::<T>() {
+            // SAFETY: the type ids match, and the walk holds a borrow of the value.
+            Some(unsafe { &*self.ptr.cast::<T>() })
+        } else {
+            None
+        }
+    }
+
+    /// The leaf as a `ReflectValue`, through its `Serialize` implementation or its field's
+    /// hook.
+    pub fn to_value(&self) -> Result<ReflectValue, ValueError> {
+        // SAFETY: the walk holds a borrow of the leaf, which has type id `self.id`.
+        unsafe { leaf_value(self.db, self.id, self.hook, self.ptr) }
+    }
+}
+
+/// Like `ReflectVisitor`, with mutable access to builtins and leaves.
+///
+/// This is for transformations like trimming every string or zeroing every secret, over any
+/// registered type. Structure can't be changed from inside the walk, but a whole enum can be
+/// replaced from the outside with `Db::from_value` and friends.
+#[allow(unused_variables)]
+pub trait ReflectVisitorMut {
+    /// A struct, tuple struct, unit struct or newtype. Its fields are visited next.
+    fn visit_struct(&mut self, path: &FieldPath, typ: &ReflectedType<'_>) -> Flow {
+        Flow::Continue
+    }
+    /// A named field of a struct or struct variant. Its value is visited next.
+    fn visit_field(&mut self, path: &FieldPath, field: &Field<'_>) -> Flow {
+        Flow::Continue
+    }
+    /// A positional field of a tuple, tuple struct or tuple variant. Its value is visited next.
+    fn visit_tuple_field(&mut self, path: &FieldPath, field: &TupleField<'_>) -> Flow {
+        Flow::Continue
+    }
+    /// An enum. Its active variant is visited next.
+    fn visit_enum(&mut self, path: &FieldPath, typ: &ReflectedType<'_>) -> Flow {
+        Flow::Continue
+    }
+    /// The active variant of an enum. Its fields are visited next.
+    fn visit_variant(&mut self, path: &FieldPath, arm: &EnumArm<'_>) -> Flow {
+        Flow::Continue
+    }
+    /// A fixed-size array of `len` elements. The elements are visited next.
+    fn visit_seq(&mut self, path: &FieldPath, len: usize) -> Flow {
+        Flow::Continue
+    }
+    /// A builtin value, which may be changed.
+    fn visit_builtin(&mut self, path: &FieldPath, builtin: BuiltinMut<'_>) {}
+    /// A leaf, or a field with a hook, which may be changed.
+    fn visit_leaf(&mut self, path: &FieldPath, leaf: LeafMut<'_, '_>) {}
+}
+
+/// A builtin value encountered by a `ReflectVisitorMut`.
+pub struct BuiltinMut<'a> {
+    builtin: RustBuiltin,
+    ptr: *mut u8,
+    _data: PhantomData<&'a mut ()>,
+}
+
+impl BuiltinMut<'_> {
+    /// Which builtin this is.
+    pub fn builtin(&self) -> RustBuiltin {
+        self.builtin
+    }
+
+    /// Current value.
+    pub fn value(&self) -> ReflectValue {
+        // SAFETY: the walk holds a unique borrow of a value of this builtin.
+        unsafe { read_builtin(self.builtin, self.ptr) }
+    }
+
+    /// Overwrite with `val`, which must be the same kind of builtin.
+    pub fn set(&mut self, val: ReflectValue) -> Result<(), ValueError> {
+        // SAFETY: as in `value`; builtins have no drop glue.
+        unsafe { write_builtin(self.builtin, self.ptr, val) }
+    }
+
+    /// The builtin, if it is a `T`.
+    pub fn downcast_mut<T: Any>(&mut self) -> Option<&mut T> {
+        if RustBuiltin::of(TypeId::of::<T>()) == Some(self.builtin) {
+            // SAFETY: same type, and the walk holds a unique borrow of the value.
+            Some(unsafe { &mut *self.ptr.cast::<T>() })
+        } else {
+            None
+        }
+    }
+}
+
+/// A leaf value encountered by a `ReflectVisitorMut`.
+pub struct LeafMut<'a, 'db> {
+    db: &'a Db<'db>,
+    id: TypeId,
+    /// The hook of the field the leaf is in, which reads and writes it instead.
+    hook: Option<Hook>,
+    ptr: *mut u8,
+}
+
+impl LeafMut<'_, '_> {
+    /// Type id of the leaf.
+    pub fn type_id(&self) -> TypeId {
+        self.id
+    }
+
+    /// The leaf, if it is a `T`.
+    pub fn downcast_mut<T: Any>(&mut self) -> Option<&mut T> {
+        if self.id == TypeId::of::<T>() {
+            // SAFETY: the type ids match, and the walk holds a unique borrow of the value.
+            Some(unsafe { &mut *self.ptr.cast::<T>() })
+        } else {
+            None
+        }
+    }
+
+    /// The leaf as a `ReflectValue`, through its `Serialize` implementation or its field's
+    /// hook.
+    pub fn to_value(&self) -> Result<ReflectValue, ValueError> {
+        // SAFETY: the walk holds a borrow of the leaf, which has type id `self.id`.
+        unsafe { leaf_value(self.db, self.id, self.hook, self.ptr) }
+    }
+
+    /// Replace the leaf with one deserialized from `val`, dropping the old one.
+    ///
+    /// If deserialization fails the leaf is left alone.
+    pub fn set(&mut self, val: ReflectValue) -> Result<(), ValueError> {
+        let shape = element_shape(self.id);
+        let place = match self.hook {
+            Some(hook) => Place::Hooked(hook, &shape),
+            None => Place::Shape(&shape),
+        };
+        let val = place.seed(self.db).deserialize(val)?;
+        // SAFETY: the walk holds a unique borrow of an initialized leaf.
+        unsafe { self.db.replace(place, self.ptr, val) }
+    }
+}
+
+/// SAFETY: `ptr` must point to a value of type `id`, which `hook` is for if there is one.
+unsafe fn leaf_value(
+    db: &Db<'_>,
+    id: TypeId,
+    hook: Option<Hook>,
+    ptr: *const u8,
+) -> Result<ReflectValue, ValueError> {
+    match hook {
+        Some(hook) => hook.serialize(ValueSerializer, ptr),
+        None => db.serialize_leaf_at(ValueSerializer, id, ptr),
+    }
+}
+
+impl<'db> Db<'db> {
+    /// Visit every part of a value of a registered type, depth first in declaration order.
+    pub fn walk<T: Any, V: ReflectVisitor + ?Sized>(
+        &self,
+        value: &T,
+        visitor: &mut V,
+    ) -> Result<(), ValueError> {
+        let typ = self
+            .known_type(TypeId::of::<T>())
+            .ok_or_else(|| ValueError("type missing from db, cannot walk".into()))?;
+        // SAFETY: the pointer comes from a reference to the registered type, and a shared
+        // visitor never writes through it.
+        unsafe { self.walk_ptr(typ, value as *const T as *mut u8, Shared(visitor)) }
+    }
+
+    /// Like `walk`, for a value at some location.
+    pub fn walk_at<V: ReflectVisitor + ?Sized>(
+        &self,
+        src: TypedLocation<'db, '_>,
+        visitor: &mut V,
+    ) -> Result<(), ValueError> {
+        // SAFETY: TypedLocation contract
+        unsafe { self.walk_ptr(src.typ, src.ptr as *mut u8, Shared(visitor)) }
+    }
+
+    /// Visit every part of a value of a registered type, allowing builtins and leaves to be
+    /// changed in place.
+    pub fn walk_mut<T: Any, V: ReflectVisitorMut + ?Sized>(
+        &self,
+        value: &mut T,
+        visitor: &mut V,
+    ) -> Result<(), ValueError> {
+        let typ = self
+            .known_type(TypeId::of::<T>())
+            .ok_or_else(|| ValueError("type missing from db, cannot walk".into()))?;
+        // SAFETY: the pointer comes from a unique reference to the registered type.
+        unsafe { self.walk_ptr(typ, value as *mut T as *mut u8, Exclusive(visitor)) }
+    }
+
+    /// Like `walk_mut`, for an initialized value at some location.
+    ///
+    /// # Safety
+    /// `dst` must hold an initialized value of its type, borrowed for the whole walk.
+    pub unsafe fn walk_at_mut<V: ReflectVisitorMut + ?Sized>(
+        &self,
+        dst: TypedOutputLocation<'db, '_>,
+        visitor: &mut V,
+    ) -> Result<(), ValueError> {
+        self.walk_ptr(dst.typ, dst.ptr, Exclusive(visitor))
+    }
+
+    unsafe fn walk_ptr<C: Callbacks>(
+        &self,
+        typ: &ReflectedType<'db>,
+        ptr: *mut u8,
+        callbacks: C,
+    ) -> Result<(), ValueError> {
+        Walker {
+            db: self,
+            path: FieldPath::default(),
+            callbacks,
+        }
+        .item(typ, ptr)
+    }
+}
+
+/// The visitor methods the walker needs, over both kinds of visitor.
+trait Callbacks {
+    fn visit_struct(&mut self, path: &FieldPath, typ: &ReflectedType<'_>) -> Flow;
+    fn visit_field(&mut self, path: &FieldPath, field: &Field<'_>) -> Flow;
+    fn visit_tuple_field(&mut self, path: &FieldPath, field: &TupleField<'_>) -> Flow;
+    fn visit_enum(&mut self, path: &FieldPath, typ: &ReflectedType<'_>) -> Flow;
+    fn visit_variant(&mut self, path: &FieldPath, arm: &EnumArm<'_>) -> Flow;
+    fn visit_seq(&mut self, path: &FieldPath, len: usize) -> Flow;
+    /// SAFETY: `ptr` must point to a value of the builtin.
+    unsafe fn visit_builtin(&mut self, path: &FieldPath, builtin: RustBuiltin, ptr: *mut u8);
+    /// SAFETY: `ptr` must point to a value of type `id`, which `hook` is for if there is one.
+    unsafe fn visit_leaf(
+        &mut self,
+        path: &FieldPath,
+        db: &Db<'_>,
+        id: TypeId,
+        hook: Option<Hook>,
+        ptr: *mut u8,
+    );
+}
+
+struct Shared<'a, V: ?Sized>(&'a mut V);
+struct Exclusive<'a, V: ?Sized>(&'a mut V);
+
+macro_rules! forward_structure {
+    () => {
+        fn visit_struct(&mut self, path: &FieldPath, typ: &ReflectedType<'_>) -> Flow {
+            self.0.visit_struct(path, typ)
+        }
+        fn visit_field(&mut self, path: &FieldPath, field: &Field<'_>) -> Flow {
+            self.0.visit_field(path, field)
+        }
+        fn visit_tuple_field(&mut self, path: &FieldPath, field: &TupleField<'_>) -> Flow {
+            self.0.visit_tuple_field(path, field)
+        }
+        fn visit_enum(&mut self, path: &FieldPath, typ: &ReflectedType<'_>) -> Flow {
+            self.0.visit_enum(path, typ)
+        }
+        fn visit_variant(&mut self, path: &FieldPath, arm: &EnumArm<'_>) -> Flow {
+            self.0.visit_variant(path, arm)
+        }
+        fn visit_seq(&mut self, path: &FieldPath, len: usize) -> Flow {
+            self.0.visit_seq(path, len)
+        }
+    };
+}
+
+impl<V: ReflectVisitor + ?Sized> Callbacks for Shared<'_, V> {
+    forward_structure!();
+
+    unsafe fn visit_builtin(&mut self, path: &FieldPath, builtin: RustBuiltin, ptr: *mut u8) {
+        self.0
+            .visit_builtin(path, builtin, read_builtin(builtin, ptr))
+    }
+
+    unsafe fn visit_leaf(
+        &mut self,
+        path: &FieldPath,
+        db: &Db<'_>,
+        id: TypeId,
+        hook: Option<Hook>,
+        ptr: *mut u8,
+    ) {
+        self.0.visit_leaf(path, Leaf { db, id, hook, ptr })
+    }
+}
+
+impl<V: ReflectVisitorMut + ?Sized> Callbacks for Exclusive<'_, V> {
+    forward_structure!();
+
+    unsafe fn visit_builtin(&mut self, path: &FieldPath, builtin: RustBuiltin, ptr: *mut u8) {
+        self.0.visit_builtin(
+            path,
+            BuiltinMut {
+                builtin,
+                ptr,
+                _data: PhantomData,
+            },
+        )
+    }
+
+    unsafe fn visit_leaf(
+        &mut self,
+        path: &FieldPath,
+        db: &Db<'_>,
+        id: TypeId,
+        hook: Option<Hook>,
+        ptr: *mut u8,
+    ) {
+        self.0.visit_leaf(path, LeafMut { db, id, hook, ptr })
+    }
+}
+
+struct Walker<'a, 'db, C> {
+    db: &'a Db<'db>,
+    path: FieldPath,
+    callbacks: C,
+}
+
+impl<'db, C: Callbacks> Walker<'_, 'db, C> {
+    fn nested<R>(&mut self, seg: PathSegment, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.path.0.push(seg);
+        let r = f(self);
+        self.path.0.pop();
+        r
+    }
+
+    /// SAFETY: `ptr` must point to a value described by `typ`.
+    unsafe fn item(&mut self, typ: &ReflectedType<'db>, ptr: *mut u8) -> Result<(), ValueError> {
+        match &typ.typ {
+            ItemDeclaration::Newtype(inner) => {
+                if self.callbacks.visit_struct(&self.path, typ) == Flow::Continue {
+                    self.item(inner, ptr)?;
+                }
+                Ok(())
+            }
+            ItemDeclaration::Struct(data) => {
+                if self.callbacks.visit_struct(&self.path, typ) == Flow::Continue {
+                    self.variant_data(data, ptr)?;
+                }
+                Ok(())
+            }
+            ItemDeclaration::Enum { variants, .. } => {
+                if self.callbacks.visit_enum(&self.path, typ) == Flow::Skip {
+                    return Ok(());
+                }
+                let arm = active_arm(variants, ptr).map_err(|e| ValueError(e.into()))?;
+                self.nested(PathSegment::Variant(arm.label), |me| {
+                    if me.callbacks.visit_variant(&me.path, arm) == Flow::Continue {
+                        me.variant_data(&arm.variant, ptr)
+                    } else {
+                        Ok(())
+                    }
+                })
+            }
+        }
+    }
+
+    /// SAFETY: `ptr` must point to the base of a value holding `data`.
+    unsafe fn variant_data(
+        &mut self,
+        data: &VariantData<'db>,
+        ptr: *mut u8,
+    ) -> Result<(), ValueError> {
+        match data {
+            VariantData::Unit => Ok(()),
+            VariantData::Fields {
+                labels_for_serde,
+                fields,
+            } => {
+                for (ix, f) in fields.iter().enumerate() {
+                    self.nested(
+                        PathSegment::Field(field_label(labels_for_serde, ix, f)),
+                        |me| {
+                            if me.callbacks.visit_field(&me.path, f) == Flow::Continue {
+                                me.field(attrs_of!(f), &f.shape, ptr.add(f.offset))
+                            } else {
+                                Ok(())
+                            }
+                        },
+                    )?;
+                }
+                Ok(())
+            }
+            VariantData::Tuple(fields) => self.tuple(fields, ptr),
+        }
+    }
+
+    unsafe fn tuple(&mut self, fields: &[TupleField<'db>], ptr: *mut u8) -> Result<(), ValueError> {
+        for (ix, f) in fields.iter().enumerate() {
+            self.nested(PathSegment::Index(ix), |me| {
+                if me.callbacks.visit_tuple_field(&me.path, f) == Flow::Continue {
+                    me.field(attrs_of!(f), &f.shape, ptr.add(f.offset))
+                } else {
+                    Ok(())
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    /// A field with a hook is visited as a leaf, whatever its shape.
+    ///
+    /// SAFETY: `ptr` must point to a value described by `shape`.
+    unsafe fn field(
+        &mut self,
+        attrs: Option<&[Attr]>,
+        shape: &DataShape<'db>,
+        ptr: *mut u8,
+    ) -> Result<(), ValueError> {
+        match self.db.field_hook(attrs, shape)? {
+            Some(hook) => {
+                self.callbacks
+                    .visit_leaf(&self.path, self.db, hook.field, Some(hook), ptr);
+                Ok(())
+            }
+            None => self.shape(shape, ptr),
+        }
+    }
+
+    /// SAFETY: `ptr` must point to a value described by `shape`.
+    unsafe fn shape(&mut self, shape: &DataShape<'db>, ptr: *mut u8) -> Result<(), ValueError> {
+        let db = self.db;
+        match shape {
+            &DataShape::Builtin(builtin) => {
+                self.callbacks.visit_builtin(&self.path, builtin, ptr);
+                Ok(())
+            }
+            &DataShape::Leaf(id) => match db.leaf_type(id) {
+                Some(typ) => self.item(typ, ptr),
+                None => {
+                    self.callbacks.visit_leaf(&self.path, db, id, None, ptr);
+                    Ok(())
+                }
+            },
+            &DataShape::FixedArray(id, len) => {
+                if self.callbacks.visit_seq(&self.path, len) == Flow::Skip {
+                    return Ok(());
+                }
+                let elt = element_shape(id);
+                let stride = db.stride(id).map_err(ValueError)?;
+                for ix in 0..len {
+                    self.nested(PathSegment::Index(ix), |me| {
+                        me.shape(&elt, ptr.add(ix * stride))
+                    })?;
+                }
+                Ok(())
+            }
+            DataShape::Tuple(fields) => self.tuple(fields, ptr),
+            DataShape::Slice(_) | DataShape::Ref(_) => {
+                Err(ValueError("cannot walk through borrowed data".into()))
+            }
+        }
+    }
+}
@@ -1,25 +1,30 @@
+#![allow(non_local_definitions)]
+
 use serde_reflect::{Db, Reflect};
 use proptest_derive::Arbitrary;
 
+mod common;
+
 // this crate tries to exercise every feature of serde-reflect
 
 // can derive for generic types!
-#[derive(Reflect)]
+#[derive(Reflect, Arbitrary, Debug, PartialEq)]
 struct Newtype<T>(T);
 
-#[derive(Reflect)]
+#[derive(Reflect, Arbitrary, Debug, PartialEq)]
 
 struct UnitLike;
-#[derive(Reflect)]
+#[derive(Reflect, Arbitrary, Debug, PartialEq)]
 
 struct TupleLike(String, Vec<u8>);
-#[derive(Reflect)]
+#[derive(Reflect, Arbitrary, Debug, PartialEq)]
 
 struct RealStruct {
     field: u8,
 }
 
-#[derive(Reflect)]
+#[derive(Reflect, Arbitrary, Debug, PartialEq)]
+#[repr(u16)]
 enum BigFinalType {
     UnitVariant,
     StructUnit(UnitLike),
@@ -33,9 +38,17 @@ enum BigFinalType {
 fn main() {
     let mut db = Db::new();
     BigFinalType::register(&mut db);
-    proptest::proptest!(|(b: BigFinalType)| {
+    UnitLike::register(&mut db);
+    TupleLike::register(&mut db);
+    RealStruct::register(&mut db);
+    Newtype::<()>::register(&mut db);
+    db.register_serde_leaf::<String>();
+    db.register_serde_leaf::<Vec<u8>>();
+    db.register_serde_leaf::<usize>();
+    db.register_serde_leaf::<()>();
+    proptest::proptest!(common::config(), |(b: BigFinalType)| {
         let json_val = db.serialize(serde_json::value::Serializer, &b)?;
-        let deser = db.deserialize(json_val);
-        b == deser
+        let deser: BigFinalType = db.deserialize(json_val)?;
+        proptest::prop_assert_eq!(b, deser);
     });
 }
@@ -0,0 +1,92 @@
+#![cfg(feature = "proptest")]
+
+use proptest::prelude::*;
+use proptest::test_runner::TestRunner;
+use serde_derive::Serialize;
+use serde_reflect::*;
+use std::cell::Cell;
+
+#[derive(Reflect, Serialize, Debug, PartialEq)]
+struct Point {
+    x: i32,
+    y: u8,
+}
+
+#[allow(dead_code)]
+#[derive(Reflect, Serialize, Debug, PartialEq)]
+#[repr(u16)]
+enum Shape {
+    Empty,
+    Circle { center: Point, radius: u16 },
+    Line(Point, Point),
+    Label(String),
+}
+
+#[derive(Reflect, Serialize, Debug, PartialEq)]
+struct Drawing {
+    title: String,
+    shapes: [Shape; 3],
+    pair: (char, bool),
+    scale: Scale,
+}
+
+#[derive(Reflect, Serialize, Debug, PartialEq)]
+struct Scale(u128);
+
+#[derive(Reflect, Debug)]
+struct Unstrategic {
+    blob: Vec<u8>,
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Drawing::register(&mut db);
+    Shape::register(&mut db);
+    Point::register(&mut db);
+    Scale::register(&mut db);
+    Unstrategic::register(&mut db);
+    db.register_serde_leaf::<String>();
+    db.register_serde_leaf::<Vec<u8>>();
+    db.register_leaf_strategy(".{0,8}");
+
+    // generated values are real values, which reflection serializes like serde does
+    let seen: [Cell<bool>; 4] = Default::default();
+    TestRunner::default().run(&db.arbitrary::<Drawing>()?, |drawing| {
+        for shape in &drawing.shapes {
+            seen[match shape {
+                Shape::Empty => 0,
+                Shape::Circle { .. } => 1,
+                Shape::Line(..) => 2,
+                Shape::Label(_) => 3,
+            }]
+            .set(true);
+        }
+        prop_assert!(drawing.title.chars().count() <= 8);
+        prop_assert_eq!(
+            db.to_compact(&drawing).unwrap(),
+            postcard::to_allocvec(&drawing).unwrap()
+        );
+        Ok(())
+    })?;
+    assert!(seen.iter().all(Cell::get));
+
+    // failures shrink towards the smallest value
+    let err = TestRunner::default()
+        .run(&db.arbitrary::<Point>()?, |point| {
+            prop_assert!(point.x < 100);
+            Ok(())
+        })
+        .unwrap_err();
+    assert!(err.to_string().contains("x: 100"), "{}", err);
+
+    // leaves need a registered strategy
+    let err = db.arbitrary::<Unstrategic>().err().unwrap();
+    assert!(err.0.contains("Vec<u8>"), "{}", err);
+    db.register_leaf_strategy(proptest::collection::vec(any::<u8>(), 0..4));
+    TestRunner::default().run(&db.arbitrary::<Unstrategic>()?, |val| {
+        prop_assert!(val.blob.len() < 4);
+        Ok(())
+    })?;
+    Ok(())
+}
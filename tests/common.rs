@@ -2,6 +2,20 @@ use serde_reflect::*;
 use proptest::prelude::*;
 use serde::{Deserialize, Serialize};
 
+/// Proptest's configuration, with few enough cases to finish under Miri.
+pub fn config() -> ProptestConfig {
+    if cfg!(miri) {
+        ProptestConfig {
+            cases: 4,
+            failure_persistence: None,
+            ..ProptestConfig::default()
+        }
+    } else {
+        ProptestConfig::default()
+    }
+}
+
+#[allow(dead_code)]
 pub fn single_type<
     T: Arbitrary
         + Reflect
@@ -13,7 +27,7 @@ pub fn single_type<
 >() {
     let mut db = Db::new();
     T::register(&mut db);
-    proptest::proptest!(|(orig: T)| {
+    proptest::proptest!(config(), |(orig: T)| {
         let json_val = db.serialize(serde_json::value::Serializer, &orig)?;
         let deser: T = db.deserialize(json_val)?;
         prop_assert_eq!(orig, deser)
@@ -0,0 +1,66 @@
+use serde_derive::{Deserialize, Serialize};
+use serde_reflect::*;
+
+#[derive(Reflect, Serialize, Deserialize, Debug, PartialEq)]
+#[repr(u16)]
+enum Reading {
+    Missing,
+    Celsius(i16),
+    Wind { speed: u32, bearing: u16 },
+    Gust(u8, u8),
+}
+
+#[derive(Reflect, Serialize, Deserialize, Debug, PartialEq)]
+struct Station {
+    id: u64,
+    name: String,
+    note: Option<String>,
+    history: Vec<u16>,
+    readings: [Reading; 4],
+    position: (i32, i32),
+    online: bool,
+    code: char,
+    total: i128,
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Station::register(&mut db);
+    Reading::register(&mut db);
+    db.register_serde_leaf::<String>();
+    db.register_serde_leaf::<Option<String>>();
+    db.register_serde_leaf::<Vec<u16>>();
+
+    let station = Station {
+        id: 300,
+        name: "hilltop".into(),
+        note: Some("windy".into()),
+        history: vec![1, 200, 40000],
+        readings: [
+            Reading::Missing,
+            Reading::Celsius(-12),
+            Reading::Wind {
+                speed: 1 << 20,
+                bearing: 270,
+            },
+            Reading::Gust(3, 250),
+        ],
+        position: (-5, i32::MAX),
+        online: true,
+        code: 'ñ',
+        total: i128::MIN,
+    };
+    let bytes = db.to_compact(&station)?;
+    assert_eq!(bytes, postcard::to_allocvec(&station)?);
+    assert_eq!(db.from_compact::<Station>(&bytes)?, station);
+
+    // truncated and oversized input are errors
+    assert!(db
+        .from_compact::<Station>(&bytes[..bytes.len() - 1])
+        .is_err());
+    let mut longer = bytes.clone();
+    longer.push(0);
+    assert!(db.from_compact::<Station>(&longer).is_err());
+    Ok(())
+}
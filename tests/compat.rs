@@ -0,0 +1,75 @@
+use serde_reflect::*;
+
+mod v1 {
+    use serde_reflect::*;
+
+    #[allow(dead_code)]
+    #[derive(Reflect)]
+    #[repr(u16)]
+    pub enum Shape {
+        Empty,
+        Circle { radius: u8 },
+        Square(u16),
+    }
+
+    #[derive(Reflect)]
+    pub struct Drawing {
+        pub title: String,
+        pub shapes: [Shape; 2],
+        pub origin: (i32, u8),
+        pub scale: u16,
+    }
+}
+
+mod v2 {
+    use serde_reflect::*;
+
+    #[allow(dead_code)]
+    #[derive(Reflect)]
+    #[repr(u16)]
+    pub enum Shape {
+        Empty,
+        Circle { radius: u32 },
+        Line(u8, u8),
+    }
+
+    #[derive(Reflect)]
+    pub struct Drawing {
+        pub title: String,
+        pub shapes: [Shape; 2],
+        pub origin: (u8, i32),
+        pub layer: u8,
+    }
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    v1::Drawing::register(&mut db);
+    v1::Shape::register(&mut db);
+    v2::Drawing::register(&mut db);
+    v2::Shape::register(&mut db);
+    db.register_serde_leaf::<String>();
+
+    let before = db.describe::<v1::Drawing>()?;
+    let after = db.describe::<v2::Drawing>()?;
+    assert!(check_compatibility(&before, &before).is_empty());
+
+    let breaks = check_compatibility(&before, &after);
+    let found: Vec<String> = breaks.iter().map(|b| b.to_string()).collect();
+    assert_eq!(
+        found,
+        [
+            "shapes[]::Circle.radius: integer changed from u8 to u32 (breaks forward compatibility)",
+            "shapes[]: variant `Square` removed (breaks backward compatibility)",
+            "shapes[]: variant `Line` added (breaks forward compatibility)",
+            "origin: tuple fields reordered (breaks backward and forward compatibility)",
+            ".: field `scale` removed (breaks forward compatibility)",
+            ".: field `layer` added (breaks backward compatibility)",
+        ]
+    );
+    assert!(breaks
+        .iter()
+        .any(|b| b.kind == BreakKind::FieldAdded("layer".into())));
+    Ok(())
+}
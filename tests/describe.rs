@@ -0,0 +1,121 @@
+use serde_json::json;
+use serde_reflect::*;
+use std::any::TypeId;
+
+#[allow(dead_code)]
+#[derive(Reflect)]
+#[repr(u16)]
+enum Shape {
+    Empty,
+    Circle { radius: u8 },
+}
+
+#[derive(Reflect)]
+struct Drawing {
+    title: String,
+    shapes: [Shape; 2],
+    origin: (i32, i32),
+}
+
+// stand-ins for the types of a process we only have the schema of
+struct DrawingMarker;
+struct ShapeMarker;
+struct PairMarker;
+struct WrapperMarker;
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Drawing::register(&mut db);
+    Shape::register(&mut db);
+    db.register_serde_leaf::<String>();
+
+    let schema = db.describe::<Drawing>()?;
+    let names: Vec<&str> = schema.iter().map(|desc| desc.name.as_str()).collect();
+    assert_eq!(names, ["Drawing", "Shape"]);
+    #[cfg(feature = "descriptions")]
+    let schema: Vec<TypeDescription> = serde_json::from_str(&serde_json::to_string(&schema)?)?;
+    let mut remote = Db::new();
+    remote.register_serde_leaf::<String>();
+    let markers = |name: &str| match name {
+        "Drawing" => Some(TypeId::of::<DrawingMarker>()),
+        "Shape" => Some(TypeId::of::<ShapeMarker>()),
+        _ => None,
+    };
+    // SAFETY: the markers are only used to find the types to deserialize values of
+    unsafe { remote.insert_described(&schema, markers)? };
+
+    let data = json!({
+        "title": "doodle",
+        "shapes": ["Empty", { "Circle": { "radius": 3 } }],
+        "origin": [-1, 1],
+    });
+    let typ = remote.known_type(TypeId::of::<DrawingMarker>()).unwrap();
+    let val = remote.deserialize_value(typ, data.clone())?;
+    assert_eq!(
+        val.get("shapes")
+            .and_then(|s| s.at(1))
+            .and_then(|s| s.variant()),
+        Some("Circle")
+    );
+    assert_eq!(serde_json::to_value(&val)?, data);
+
+    // names nobody can resolve are an error, and registered types are never replaced
+    unsafe {
+        assert!(Db::new().insert_described(&schema, |_| None).is_err());
+        let err = remote.insert_described(&schema, markers).unwrap_err();
+        assert_eq!(err.0, "`Drawing` is already registered");
+    }
+
+    // layouts which can't hold their fields, and types which contain themselves, are rejected
+    let pair = |offset, inner: &str| TypeDescription {
+        name: "Pair".into(),
+        size: 8,
+        align: 4,
+        item: ItemDescription::Struct(VariantDescription::Tuple(vec![
+            TupleFieldDescription {
+                offset: 0,
+                shape: ShapeDescription::Type("i32".into()),
+            },
+            TupleFieldDescription {
+                offset,
+                shape: ShapeDescription::Type(inner.into()),
+            },
+        ])),
+    };
+    let wrapper = |inner: &str| TypeDescription {
+        name: "Wrapper".into(),
+        size: 8,
+        align: 4,
+        item: ItemDescription::Newtype(inner.into()),
+    };
+    let load = |schema: &[TypeDescription]| {
+        // SAFETY: the markers are never used at all
+        unsafe {
+            Db::new().insert_described(schema, |name| match name {
+                "Pair" => Some(TypeId::of::<PairMarker>()),
+                "Wrapper" => Some(TypeId::of::<WrapperMarker>()),
+                _ => None,
+            })
+        }
+        .map_err(|err| err.0)
+    };
+    assert_eq!(
+        load(&[pair(6, "u32")]),
+        Err("a field of `Pair` at offset 6 doesn't fit its layout".into())
+    );
+    assert_eq!(
+        load(&[pair(2, "u32")]),
+        Err("a field of `Pair` at offset 2 doesn't fit its layout".into())
+    );
+    assert_eq!(
+        load(&[pair(0, "u32")]),
+        Err("fields of `Pair` overlap".into())
+    );
+    assert_eq!(
+        load(&[pair(4, "u32"), wrapper("Wrapper")]),
+        Err("`Wrapper` contains itself".into())
+    );
+    assert_eq!(load(&[pair(4, "u32"), wrapper("Pair")]), Ok(()));
+    Ok(())
+}
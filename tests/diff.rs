@@ -0,0 +1,78 @@
+use serde_reflect::*;
+
+#[allow(dead_code)]
+#[derive(Reflect)]
+#[repr(u16)]
+enum Status {
+    Idle,
+    Busy { job: u32 },
+}
+
+#[derive(Reflect)]
+struct Shift {
+    hours: u8,
+}
+
+#[derive(Reflect)]
+struct Worker {
+    id: u16,
+    status: Status,
+    load: [u8; 3],
+    shifts: [Shift; 2],
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Worker::register(&mut db);
+    Status::register(&mut db);
+    Shift::register(&mut db);
+
+    let a = Worker {
+        id: 1,
+        status: Status::Busy { job: 10 },
+        load: [1, 2, 3],
+        shifts: [Shift { hours: 8 }, Shift { hours: 6 }],
+    };
+    let b = Worker {
+        id: 1,
+        status: Status::Busy { job: 11 },
+        load: [1, 5, 3],
+        shifts: [Shift { hours: 8 }, Shift { hours: 4 }],
+    };
+    let changes = db.diff(&a, &b)?;
+    let paths: Vec<String> = changes.iter().map(|c| c.path().to_string()).collect();
+    assert_eq!(paths, ["status::Busy.job", "load[1]", "shifts[1].hours"]);
+    assert!(matches!(
+        &changes[1],
+        Change::Element {
+            before: ReflectValue::U8(2),
+            after: ReflectValue::U8(5),
+            ..
+        }
+    ));
+    // differences inside struct elements are in the array too
+    assert!(matches!(
+        &changes[2],
+        Change::Element {
+            before: ReflectValue::U8(6),
+            after: ReflectValue::U8(4),
+            ..
+        }
+    ));
+
+    let c = Worker {
+        status: Status::Idle,
+        ..b
+    };
+    assert_eq!(
+        db.diff(&a, &c)?[0],
+        Change::Variant {
+            path: FieldPath(vec![PathSegment::Field("status")]),
+            before: "Busy",
+            after: "Idle",
+        }
+    );
+    assert!(db.diff(&a, &a)?.is_empty());
+    Ok(())
+}
@@ -1,5 +1,58 @@
 use serde_reflect::*;
+use std::{alloc::Layout, any::TypeId, borrow::Cow};
 
-fn main() {
-    let db = Db::new();
+// a type that only exists as reflection metadata, as if loaded from a schema file
+struct Marker;
+
+const POINT_FIELDS: &[Field<'static>] = &[
+    Field {
+        offset: 0,
+        name: "x",
+        #[cfg(feature = "attrs")]
+        attrs: Cow::Borrowed(&[]),
+        shape: DataShape::Builtin(RustBuiltin::U8),
+    },
+    Field {
+        offset: 4,
+        name: "y",
+        #[cfg(feature = "attrs")]
+        attrs: Cow::Borrowed(&[]),
+        shape: DataShape::Builtin(RustBuiltin::I32),
+    },
+];
+
+fn point() -> ReflectedType<'static> {
+    ReflectedType {
+        id: TypeId::of::<Marker>(),
+        name: "Point",
+        layout: Layout::new::<(u8, i32)>(),
+        typ: ItemDeclaration::Struct(VariantData::Fields {
+            labels_for_serde: &["x", "y"],
+            fields: Cow::Borrowed(POINT_FIELDS),
+        }),
+        #[cfg(feature = "attrs")]
+        attrs: Cow::Borrowed(&[]),
+    }
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let typ = point();
+    let mut db = Db::new();
+    db.insert(TypeId::of::<Marker>(), Cow::Owned(point()));
+
+    let json = serde_json::json!({ "y": -12, "x": 7 });
+    let mut val = db.deserialize_value(&typ, json)?;
+    assert_eq!(val.get("x"), Some(&ReflectValue::U8(7)));
+    assert_eq!(val.get("y"), Some(&ReflectValue::I32(-12)));
+
+    *val.get_mut("x").unwrap() = ReflectValue::U8(9);
+    let out = serde_json::to_value(&val)?;
+    assert_eq!(out, serde_json::json!({ "x": 9, "y": -12 }));
+
+    // out of range for the field's width
+    assert!(db
+        .deserialize_value(&typ, serde_json::json!({ "x": 300, "y": 0 }))
+        .is_err());
+    Ok(())
 }
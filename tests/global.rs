@@ -0,0 +1,34 @@
+#![cfg(feature = "global")]
+
+use serde_reflect::*;
+use std::any::TypeId;
+
+#[derive(Reflect, Debug, PartialEq)]
+struct Greeting {
+    text: String,
+    times: u8,
+}
+
+// generic types aren't registered globally
+#[derive(Reflect)]
+struct Wrapper<T: 'static>(T);
+
+register_global!(|db| {
+    db.register_serde_leaf::<String>();
+});
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let db = Db::global();
+    assert!(db.known_type(TypeId::of::<Greeting>()).is_some());
+    assert!(db.known_type(TypeId::of::<Wrapper<u8>>()).is_none());
+
+    let hello = Greeting {
+        text: "hello".into(),
+        times: 2,
+    };
+    let back: Greeting = db.from_value(db.to_value(&hello)?)?;
+    assert_eq!(back, hello);
+    assert!(std::ptr::eq(db, Db::global()));
+    Ok(())
+}
@@ -0,0 +1,96 @@
+#![cfg(feature = "attrs")]
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::json;
+use serde_reflect::*;
+
+/// Milliseconds since the epoch, written as seconds with a fraction.
+struct Seconds;
+
+impl SerdeWith for Seconds {
+    type Field = u64;
+
+    fn serialize<S: Serializer>(millis: &u64, s: S) -> Result<S::Ok, S::Error> {
+        (*millis as f64 / 1000.0).serialize(s)
+    }
+
+    fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
+        Ok((f64::deserialize(d)? * 1000.0).round() as u64)
+    }
+}
+
+#[derive(Reflect, Debug, PartialEq)]
+struct Event {
+    id: u64,
+    #[reflect(with = "seconds")]
+    at: u64,
+}
+
+#[derive(Reflect, Debug, PartialEq)]
+struct Broken {
+    #[reflect(with = "seconds")]
+    flag: bool,
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Event::register(&mut db);
+    Broken::register(&mut db);
+    db.register_with::<Seconds>("seconds");
+    let plans = db.compile();
+
+    // only the field with the hook is encoded differently
+    let event = Event { id: 1500, at: 1500 };
+    let json = plans.serialize(serde_json::value::Serializer, &event)?;
+    assert_eq!(json, json!({"id": 1500, "at": 1.5}));
+    let back: Event = plans.deserialize(json.clone())?;
+    assert_eq!(back, event);
+
+    let bytes = db.to_compact(&event)?;
+    assert_eq!(bytes, postcard::to_allocvec(&(1500u64, 1.5f64))?);
+    assert_eq!(db.from_compact::<Event>(&bytes)?, event);
+
+    // and so is every other path through reflection
+    assert_eq!(db.serialize(serde_json::value::Serializer, &event)?, json);
+    assert_eq!(db.deserialize::<Event, _>(json.clone())?, event);
+    let val = db.to_value(&event)?;
+    assert_eq!(val.get("at"), Some(&ReflectValue::F64(1.5)));
+    assert_eq!(db.from_value::<Event>(val)?, event);
+
+    let later = Event { id: 1500, at: 2250 };
+    assert_eq!(
+        db.diff(&event, &later)?,
+        vec![Change::Leaf {
+            path: FieldPath(vec![PathSegment::Field("at")]),
+            before: ReflectValue::F64(1.5),
+            after: ReflectValue::F64(2.25),
+        }]
+    );
+
+    let mut patched = Event { id: 1500, at: 1500 };
+    db.apply_patch(&mut patched, json!({"at": 2.25}))?;
+    assert_eq!(patched, later);
+
+    struct Leaves(Vec<ReflectValue>);
+    impl ReflectVisitor for Leaves {
+        fn visit_leaf(&mut self, _: &FieldPath, leaf: Leaf<'_, '_>) {
+            self.0.push(leaf.to_value().unwrap());
+        }
+    }
+    let mut leaves = Leaves(Vec::new());
+    db.walk(&event, &mut leaves)?;
+    assert_eq!(leaves.0, vec![ReflectValue::F64(1.5)]);
+
+    // schemas can't see what a hook writes
+    let schema = serde_json::to_value(db.json_schema::<Event>()?)?;
+    assert_eq!(schema["$defs"]["Event"]["properties"]["at"], json!(true));
+    assert!(db.typescript::<Event>()?.contains("  at: unknown;"));
+    assert!(db.proto_schema::<Event>().is_err());
+
+    assert!(plans
+        .serialize(serde_json::value::Serializer, &Broken { flag: true })
+        .is_err());
+    assert!(db.to_value(&Broken { flag: true }).is_err());
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use serde::Serialize;
+use serde_json::json;
+use serde_reflect::*;
+
+#[derive(Reflect, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+#[repr(u16)]
+#[serde(rename_all = "lowercase")]
+enum Shape {
+    Empty,
+    Circle { radius: u8 },
+    Pair(u8, bool),
+}
+
+#[derive(Reflect, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct Drawing {
+    title: String,
+    nickname: Option<String>,
+    shapes: [Shape; 2],
+    #[serde(rename = "z")]
+    layer_index: Option<u32>,
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Drawing::register(&mut db);
+    Shape::register(&mut db);
+    db.register_serde_leaf::<String>();
+    db.register_serde_leaf::<Option<String>>();
+    db.register_serde_leaf::<Option<u32>>();
+    db.register_leaf_schema::<Option<String>>(LeafSchema {
+        schema: json!({ "type": ["string", "null"] }).serialize(ValueSerializer)?,
+        optional: true,
+    });
+
+    let schema = serde_json::to_value(db.json_schema::<Drawing>()?)?;
+    let byte = json!({ "type": "integer", "minimum": 0, "maximum": 255 });
+    assert_eq!(
+        schema,
+        json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "Drawing",
+            "$ref": "#/$defs/Drawing",
+            "$defs": {
+                "Drawing": {
+                    "type": "object",
+                    "properties": {
+                        "title": { "type": "string" },
+                        "nickname": { "type": ["string", "null"] },
+                        "shapes": {
+                            "type": "array",
+                            "items": { "$ref": "#/$defs/Shape" },
+                            "minItems": 2,
+                            "maxItems": 2,
+                        },
+                        "z": { "anyOf": [
+                            { "type": "integer", "minimum": 0, "maximum": u32::MAX },
+                            { "type": "null" },
+                        ] },
+                    },
+                    "required": ["title", "shapes"],
+                },
+                "Shape": {
+                    "oneOf": [
+                        { "type": "string", "enum": ["empty"] },
+                        {
+                            "type": "object",
+                            "properties": { "circle": {
+                                "type": "object",
+                                "properties": { "radius": byte },
+                                "required": ["radius"],
+                            } },
+                            "required": ["circle"],
+                            "additionalProperties": false,
+                        },
+                        {
+                            "type": "object",
+                            "properties": { "pair": {
+                                "type": "array",
+                                "prefixItems": [byte, { "type": "boolean" }],
+                                "items": false,
+                                "minItems": 2,
+                                "maxItems": 2,
+                            } },
+                            "required": ["pair"],
+                            "additionalProperties": false,
+                        },
+                    ]
+                },
+            },
+        })
+    );
+
+    // fields left out of `required` may be left out
+    let drawing = json!({ "title": "", "shapes": ["empty", { "pair": [1, true] }] });
+    let read: Drawing = db.deserialize(drawing.clone())?;
+    assert_eq!(read, serde_json::from_value(drawing)?);
+    assert_eq!(read.layer_index, None);
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use serde_reflect::*;
+
+#[derive(Reflect, Debug, PartialEq)]
+struct Event {
+    id: u32,
+    at: Stamp,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+struct Stamp(u64);
+
+#[derive(Reflect, Debug, PartialEq)]
+struct Alarm {
+    level: Level,
+}
+
+#[derive(Reflect, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+#[serde(transparent)]
+struct Level {
+    volume: u8,
+}
+
+fn stamp(typ: &str) -> LeafTypeScript {
+    LeafTypeScript {
+        typ: typ.into(),
+        optional: false,
+    }
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut base = Db::new();
+    Event::register(&mut base);
+    base.register_serde_leaf::<Stamp>();
+    base.register_leaf_typescript::<Stamp>(stamp("number"));
+    let base = base.freeze();
+
+    // a layered db sees everything in its base, and can override leaves
+    let mut layer = Db::with_base(base.clone());
+    layer.register_leaf_typescript::<Stamp>(stamp("string"));
+    let event = Event {
+        id: 1,
+        at: Stamp(7),
+    };
+    let back: Event = layer.from_value(layer.to_value(&event)?)?;
+    assert_eq!(back, event);
+    assert!(layer.typescript::<Event>()?.contains("at: string;"));
+    assert!(base.typescript::<Event>()?.contains("at: number;"));
+
+    // a layer can have serde handle a type its base reflects
+    let mut base = Db::new();
+    Alarm::register(&mut base);
+    Level::register(&mut base);
+    let base = base.freeze();
+    let mut layer = Db::with_base(base.clone());
+    layer.register_serde_leaf::<Level>();
+    let alarm = Alarm {
+        level: Level { volume: 11 },
+    };
+    let json = |db: &Db<'_>| db.serialize(serde_json::value::Serializer, &alarm);
+    assert_eq!(
+        json(&base)?,
+        serde_json::json!({ "level": { "volume": 11 } })
+    );
+    assert_eq!(json(&layer)?, serde_json::json!({ "level": 11 }));
+    assert_eq!(
+        layer.from_compact::<Alarm>(&layer.to_compact(&alarm)?)?,
+        alarm
+    );
+
+    let plugin = || {
+        let mut plugin = Db::new();
+        plugin.register_leaf_typescript::<Stamp>(stamp("Date"));
+        plugin
+    };
+    let mut db = Db::new();
+    Event::register(&mut db);
+    db.register_serde_leaf::<Stamp>();
+    db.register_leaf_typescript::<Stamp>(stamp("number"));
+
+    let err = db.merge(plugin(), ConflictPolicy::Error).unwrap_err();
+    assert!(err.0.contains("Stamp"), "{}", err.0);
+    assert!(db.typescript::<Event>()?.contains("at: number;"));
+
+    db.merge(plugin(), ConflictPolicy::Keep)?;
+    assert!(db.typescript::<Event>()?.contains("at: number;"));
+
+    db.merge(plugin(), ConflictPolicy::Replace)?;
+    assert!(db.typescript::<Event>()?.contains("at: Date;"));
+    Ok(())
+}
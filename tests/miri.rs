@@ -0,0 +1,229 @@
+//! Round trips through every `DataShape`, small enough to run under `cargo miri test`, which
+//! checks the pointer arithmetic behind reflection for undefined behavior.
+
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use serde_reflect::*;
+use std::num::NonZeroU64;
+
+#[derive(Reflect, Serialize, Deserialize, Debug, PartialEq)]
+struct Builtins {
+    a: u8,
+    b: u16,
+    c: u32,
+    d: u64,
+    e: u128,
+    f: i8,
+    g: i16,
+    h: i32,
+    i: i64,
+    j: i128,
+    k: f32,
+    l: f64,
+    m: bool,
+    n: char,
+}
+
+#[derive(Reflect, Serialize, Deserialize, Debug, PartialEq)]
+struct Meters(u32);
+
+#[derive(Reflect, Serialize, Deserialize, Debug, PartialEq)]
+struct Point {
+    x: i16,
+    y: i16,
+}
+
+#[derive(Reflect, Serialize, Deserialize, Debug, PartialEq)]
+#[repr(u16)]
+enum Inner {
+    Off,
+    Level(u8),
+    At { point: Point, label: String },
+}
+
+#[derive(Reflect, Serialize, Deserialize, Debug, PartialEq)]
+#[repr(u16)]
+enum Outer {
+    Nothing,
+    One(Inner),
+    Two(Inner, Meters),
+    Many { inners: [Inner; 2], id: NonZeroU64 },
+}
+
+#[derive(Reflect, Serialize, Deserialize, Debug, PartialEq)]
+struct Everything {
+    builtins: Builtins,
+    distance: Meters,
+    points: [Point; 3],
+    names: [String; 2],
+    bytes: [u8; 5],
+    pair: (u8, i64, char),
+    outer: [Outer; 4],
+    blob: Vec<u8>,
+}
+
+#[derive(Reflect)]
+struct Borrowed {
+    name: &'static str,
+    items: &'static [u8],
+}
+
+fn db() -> Db<'static> {
+    let mut db = Db::new();
+    Everything::register(&mut db);
+    Builtins::register(&mut db);
+    Meters::register(&mut db);
+    Point::register(&mut db);
+    Inner::register(&mut db);
+    Outer::register(&mut db);
+    Borrowed::register(&mut db);
+    db.register_serde_leaf::<String>();
+    db.register_serde_leaf::<Vec<u8>>();
+    db.register_serde_leaf::<NonZeroU64>();
+    db.register_serde_leaf::<f32>();
+    db.register_serde_leaf::<f64>();
+    db
+}
+
+fn everything() -> Everything {
+    let at = |x, label: &str| Inner::At {
+        point: Point { x, y: -x },
+        label: label.into(),
+    };
+    Everything {
+        builtins: Builtins {
+            a: u8::MAX,
+            b: 0xbeef,
+            c: 7,
+            d: u64::MAX - 1,
+            e: u64::MAX.into(),
+            f: i8::MIN,
+            g: -2,
+            h: i32::MIN + 1,
+            i: 1 << 40,
+            j: i64::MIN.into(),
+            k: 1.5,
+            l: -0.25,
+            m: true,
+            n: '✓',
+        },
+        distance: Meters(12),
+        points: [
+            Point { x: 1, y: 2 },
+            Point { x: -3, y: 4 },
+            Point { x: 5, y: i16::MIN },
+        ],
+        names: ["left".into(), String::new()],
+        bytes: [1, 2, 3, 4, 5],
+        pair: (9, -9, 'q'),
+        outer: [
+            Outer::Nothing,
+            Outer::One(Inner::Level(3)),
+            Outer::Two(at(7, "seven"), Meters(70)),
+            Outer::Many {
+                inners: [Inner::Off, at(-1, "minus")],
+                id: NonZeroU64::new(42).unwrap(),
+            },
+        ],
+        blob: vec![0, 255, 128],
+    }
+}
+
+#[test]
+fn plans() -> Result<(), anyhow::Error> {
+    let db = db();
+    let plans = db.compile();
+    let val = everything();
+    let json = plans.serialize(serde_json::value::Serializer, &val)?;
+    assert_eq!(json, serde_json::to_value(&val)?);
+    let back: Everything = plans.deserialize(json)?;
+    assert_eq!(back, val);
+
+    // a bad variant part way through drops what was read, and touches nothing else
+    let mut bad = serde_json::to_value(&val)?;
+    bad["outer"][3]["Many"]["inners"][1] = json!({ "Nope": 1 });
+    assert!(plans.deserialize::<Everything, _>(bad).is_err());
+
+    // borrowed fields are refused without being read
+    let borrowed = Borrowed {
+        name: "static",
+        items: &[1, 2],
+    };
+    assert!(plans
+        .serialize(serde_json::value::Serializer, &borrowed)
+        .is_err());
+    assert!(plans
+        .deserialize::<Borrowed, _>(json!({ "name": "x", "items": [] }))
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn compact() -> Result<(), anyhow::Error> {
+    let db = db();
+    let val = everything();
+    let bytes = db.to_compact(&val)?;
+    assert_eq!(bytes, postcard::to_allocvec(&val)?);
+    assert_eq!(db.from_compact::<Everything>(&bytes)?, val);
+    for len in [0, 1, bytes.len() / 2, bytes.len() - 1] {
+        assert!(db.from_compact::<Everything>(&bytes[..len]).is_err());
+    }
+    Ok(())
+}
+
+#[test]
+fn pod() -> Result<(), anyhow::Error> {
+    let db = db();
+    assert!(db.is_pod::<Point>());
+    assert!(!db.is_pod::<Inner>());
+    let point = Point { x: -7, y: 300 };
+    let bytes = db.to_pod_bytes(&point)?;
+    assert_eq!(db.from_pod_bytes::<Point>(&bytes)?, point);
+    Ok(())
+}
+
+struct Bump;
+
+impl ReflectVisitorMut for Bump {
+    fn visit_builtin(&mut self, _: &FieldPath, mut builtin: BuiltinMut<'_>) {
+        if let Some(n) = builtin.downcast_mut::<i16>() {
+            *n = n.wrapping_add(1);
+        }
+    }
+
+    fn visit_leaf(&mut self, _: &FieldPath, mut leaf: LeafMut<'_, '_>) {
+        if let Some(s) = leaf.downcast_mut::<String>() {
+            s.push('!');
+        }
+    }
+}
+
+#[test]
+fn in_place() -> Result<(), anyhow::Error> {
+    let db = db();
+    let mut val = everything();
+    db.walk_mut(&mut val, &mut Bump)?;
+    assert_eq!(val.points[2].y, i16::MIN + 1);
+    assert_eq!(val.names[1], "!");
+    match &val.outer[3] {
+        Outer::Many { inners, .. } => assert_eq!(
+            inners[1],
+            Inner::At {
+                point: Point { x: 0, y: 2 },
+                label: "minus!".into(),
+            }
+        ),
+        other => panic!("wrong variant {:?}", other),
+    }
+
+    // replacing a variant drops the old one's leaves
+    db.apply_json_patch(
+        &mut val,
+        json!([{ "op": "replace", "path": "/outer/2", "value": { "One": "Off" } }]),
+    )?;
+    assert_eq!(val.outer[2], Outer::One(Inner::Off));
+
+    let changes = db.diff(&everything(), &val)?;
+    assert!(!changes.is_empty());
+    Ok(())
+}
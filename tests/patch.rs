@@ -0,0 +1,107 @@
+use serde_json::json;
+use serde_reflect::*;
+
+#[derive(Reflect, Debug, PartialEq)]
+struct Limits {
+    max: u32,
+    burst: u16,
+}
+
+#[derive(Reflect, Debug, PartialEq)]
+struct Endpoint {
+    path: String,
+    limits: Limits,
+    tags: [u8; 2],
+    alias: Option<String>,
+    previous: Option<String>,
+    retries: Option<u32>,
+}
+
+fn endpoint() -> Endpoint {
+    Endpoint {
+        path: "/users".into(),
+        limits: Limits { max: 100, burst: 5 },
+        tags: [1, 2],
+        alias: None,
+        previous: Some("/accounts".into()),
+        retries: Some(3),
+    }
+}
+
+#[test]
+fn merge_patch() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Endpoint::register(&mut db);
+    Limits::register(&mut db);
+    db.register_serde_leaf::<String>();
+    db.register_serde_leaf::<Option<String>>();
+    db.register_serde_leaf::<Option<u32>>();
+
+    let mut e = endpoint();
+    db.apply_patch(
+        &mut e,
+        json!({ "limits": { "burst": 9 }, "path": "/people" }),
+    )?;
+    assert_eq!(e.path, "/people");
+    assert_eq!(e.limits, Limits { max: 100, burst: 9 });
+
+    // a bad patch leaves the field it failed on alone
+    assert!(db
+        .apply_patch(&mut e, json!({ "limits": { "max": -1 } }))
+        .is_err());
+    assert_eq!(e.limits.max, 100);
+    assert!(db.apply_patch(&mut e, json!({ "nope": 1 })).is_err());
+    Ok(())
+}
+
+#[test]
+fn json_patch() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Endpoint::register(&mut db);
+    Limits::register(&mut db);
+    db.register_serde_leaf::<String>();
+    db.register_serde_leaf::<Option<String>>();
+    db.register_serde_leaf::<Option<u32>>();
+
+    let mut e = endpoint();
+    db.apply_json_patch(
+        &mut e,
+        json!([
+            { "op": "test", "path": "/limits/max", "value": 100 },
+            { "op": "test", "path": "/retries", "value": 3 },
+            { "op": "replace", "path": "/tags/1", "value": 7 },
+            { "op": "copy", "from": "/tags/1", "path": "/tags/0" },
+            { "op": "replace", "path": "/path", "value": "/admins" },
+            { "op": "move", "from": "/previous", "path": "/alias" },
+        ]),
+    )?;
+    assert_eq!(e.tags, [7, 7]);
+    assert_eq!(e.path, "/admins");
+    assert_eq!(e.alias.as_deref(), Some("/accounts"));
+    assert_eq!(e.previous, None);
+
+    // moves out of required fields or into themselves fail without touching anything
+    for op in [
+        json!({ "op": "move", "from": "/path", "path": "/alias" }),
+        json!({ "op": "move", "from": "/limits", "path": "/limits/max" }),
+    ] {
+        assert!(db.apply_json_patch(&mut e, json!([op])).is_err());
+    }
+    assert_eq!(e.path, "/admins");
+    assert_eq!(e.alias.as_deref(), Some("/accounts"));
+
+    // a replacement failing partway drops what it had built
+    let mut bad = serde_json::to_value(db.to_value(&endpoint())?)?;
+    bad["limits"]["max"] = json!(-1);
+    let replace = json!([{ "op": "replace", "path": "", "value": bad }]);
+    assert!(db.apply_json_patch(&mut e, replace).is_err());
+    assert_eq!(e.path, "/admins");
+
+    assert!(db
+        .apply_json_patch(
+            &mut e,
+            json!([{ "op": "test", "path": "/limits/burst", "value": 6 }])
+        )
+        .is_err());
+    Ok(())
+}
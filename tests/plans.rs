@@ -0,0 +1,112 @@
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use serde_reflect::*;
+use std::{alloc::Layout, any::TypeId, borrow::Cow};
+
+#[derive(Reflect, Serialize, Deserialize, Debug, PartialEq)]
+#[repr(u16)]
+enum Shape {
+    Empty,
+    Circle { radius: u8 },
+    Square(u16),
+    Line(i32, i32),
+}
+
+#[derive(Reflect, Serialize, Deserialize, Debug, PartialEq)]
+struct Layer(u32);
+
+#[derive(Reflect, Serialize, Deserialize, Debug, PartialEq)]
+struct Drawing {
+    title: String,
+    layer: Layer,
+    shapes: [Shape; 3],
+    origin: (i64, char),
+    visible: bool,
+}
+
+/// Described by hand below, with the same label for both fields.
+#[repr(C)]
+struct Twins(u8, u8);
+
+fn twins() -> ReflectedType<'static> {
+    let field = |offset| Field {
+        offset,
+        name: "twin",
+        #[cfg(feature = "attrs")]
+        attrs: Cow::Borrowed(&[]),
+        shape: DataShape::Builtin(RustBuiltin::U8),
+    };
+    ReflectedType {
+        id: TypeId::of::<Twins>(),
+        name: "Twins",
+        layout: Layout::new::<Twins>(),
+        typ: ItemDeclaration::Struct(VariantData::Fields {
+            labels_for_serde: &["twin", "twin"],
+            fields: Cow::Owned(vec![field(0), field(1)]),
+        }),
+        #[cfg(feature = "attrs")]
+        attrs: Cow::Borrowed(&[]),
+    }
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Drawing::register(&mut db);
+    Layer::register(&mut db);
+    Shape::register(&mut db);
+    db.register_serde_leaf::<String>();
+    let plans = db.compile();
+
+    let drawing = Drawing {
+        title: "doodle".into(),
+        layer: Layer(7),
+        shapes: [
+            Shape::Empty,
+            Shape::Circle { radius: 3 },
+            Shape::Line(-1, 1),
+        ],
+        origin: (-40, 'x'),
+        visible: true,
+    };
+    let json = plans.serialize(serde_json::value::Serializer, &drawing)?;
+    assert_eq!(json, serde_json::to_value(&drawing)?);
+
+    let back: Drawing = plans.deserialize(json)?;
+    assert_eq!(back, drawing);
+
+    // fields can come in any order, and variants by index
+    let shuffled = json!({
+        "visible": false,
+        "origin": [0, 'o'],
+        "shapes": [{ "Square": 2 }, "Empty", "Empty"],
+        "layer": 1,
+        "title": "",
+    });
+    let read: Drawing = plans.deserialize(shuffled.clone())?;
+    assert_eq!(read, serde_json::from_value(shuffled)?);
+
+    // unknown fields are skipped, as serde_derive does
+    let mut unknown = serde_json::to_value(&drawing)?;
+    unknown["colour"] = json!({ "r": 255 });
+    let read: Drawing = plans.deserialize(unknown.clone())?;
+    assert_eq!(read, serde_json::from_value(unknown)?);
+    let missing = json!({ "title": "", "layer": 1 });
+    let err = plans.deserialize::<Drawing, _>(missing).err().unwrap();
+    assert!(err.to_string().contains("missing field `shapes`"));
+
+    // types that weren't registered when compiling have no plan
+    assert!(Db::new()
+        .compile()
+        .serialize(serde_json::value::Serializer, &drawing)
+        .is_err());
+
+    // types with a label twice compile, but have no plan
+    db.insert(TypeId::of::<Twins>(), Cow::Owned(twins()));
+    let err = db
+        .compile()
+        .serialize(serde_json::value::Serializer, &Twins(1, 2))
+        .unwrap_err();
+    assert_eq!(err.to_string(), "`Twins` has two fields labelled `twin`");
+    Ok(())
+}
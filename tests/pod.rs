@@ -0,0 +1,102 @@
+use serde_derive::Serialize;
+use serde_reflect::*;
+
+// without repr(C) the fields may be reordered, making them no longer plain old data
+#[derive(Reflect, Serialize, Debug, PartialEq)]
+#[repr(C)]
+struct Pixel {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+}
+
+#[derive(Reflect, Serialize, Debug, PartialEq)]
+#[repr(C)]
+struct Tile {
+    id: u8,
+    pixels: [Pixel; 4],
+}
+
+#[derive(Reflect, Debug, PartialEq)]
+#[repr(C)]
+struct Header {
+    version: u32,
+    flags: [u16; 2],
+    visible: bool,
+    tag: [u8; 3],
+}
+
+#[derive(Reflect)]
+#[repr(C)]
+struct Padded {
+    small: u8,
+    big: u32,
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Pixel::register(&mut db);
+    Tile::register(&mut db);
+    Header::register(&mut db);
+    Padded::register(&mut db);
+
+    assert!(db.is_pod::<Tile>());
+    assert!(db.is_pod::<Header>());
+    assert!(!db.is_pod::<Padded>());
+
+    let header = Header {
+        version: 0x0102_0304,
+        flags: [1, 0xff00],
+        visible: true,
+        tag: *b"abc",
+    };
+    let bytes = db.to_pod_bytes(&header)?;
+    assert_eq!(
+        bytes,
+        [4, 3, 2, 1, 1, 0, 0, 0xff, 1, b'a', b'b', b'c'].to_vec()
+    );
+    assert_eq!(db.from_pod_bytes::<Header>(&bytes)?, header);
+
+    let mut bad = bytes.clone();
+    bad[8] = 2;
+    assert!(db.from_pod_bytes::<Header>(&bad).is_err());
+    assert!(db.from_pod_bytes::<Header>(&bytes[1..]).is_err());
+    assert!(db.to_pod_bytes(&Padded { small: 1, big: 2 }).is_err());
+
+    // single byte data is copied as is by the compact codec
+    let tile = Tile {
+        id: 9,
+        pixels: [
+            Pixel {
+                r: 1,
+                g: 2,
+                b: 3,
+                a: 4,
+            },
+            Pixel {
+                r: 5,
+                g: 6,
+                b: 7,
+                a: 8,
+            },
+            Pixel {
+                r: 9,
+                g: 10,
+                b: 11,
+                a: 12,
+            },
+            Pixel {
+                r: 13,
+                g: 14,
+                b: 15,
+                a: 255,
+            },
+        ],
+    };
+    let bytes = db.to_compact(&tile)?;
+    assert_eq!(bytes, postcard::to_allocvec(&tile)?);
+    assert_eq!(db.from_compact::<Tile>(&bytes)?, tile);
+    Ok(())
+}
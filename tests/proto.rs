@@ -0,0 +1,103 @@
+use serde_reflect::*;
+
+#[allow(dead_code)]
+#[derive(Reflect)]
+#[repr(u16)]
+enum Shape {
+    Empty,
+    Circle { radius: u8 },
+    Pair(i16, bool),
+}
+
+#[derive(Reflect)]
+struct Id(u64);
+
+#[derive(Reflect)]
+struct Drawing {
+    id: Id,
+    title: String,
+    shapes: [Shape; 2],
+}
+
+#[derive(Reflect)]
+struct Blob {
+    bytes: Vec<u8>,
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Drawing::register(&mut db);
+    Shape::register(&mut db);
+    Id::register(&mut db);
+    db.register_serde_leaf::<String>();
+
+    assert_eq!(
+        db.proto_schema::<Drawing>()?,
+        r#"syntax = "proto3";
+
+message Drawing {
+  uint64 id = 1;
+  string title = 2;
+  repeated Shape shapes = 3;
+}
+
+message Shape {
+  message Empty {
+  }
+  message Circle {
+    uint32 radius = 1;
+  }
+  message Pair {
+    int32 field_0 = 1;
+    bool field_1 = 2;
+  }
+  oneof value {
+    Empty empty = 1;
+    Circle circle = 2;
+    Pair pair = 3;
+  }
+}
+"#
+    );
+
+    // leaves without a protobuf type are named in the error
+    Blob::register(&mut db);
+    db.register_serde_leaf::<Vec<u8>>();
+    let err = db.proto_schema::<Blob>().unwrap_err();
+    assert_eq!(
+        err.0,
+        "no protobuf type registered for leaf alloc::vec::Vec<u8>"
+    );
+    Ok(())
+}
+
+#[cfg(feature = "attrs")]
+#[test]
+fn tags() -> Result<(), anyhow::Error> {
+    #[derive(Reflect)]
+    struct Tagged {
+        #[reflect(tag = 7)]
+        a: u32,
+        b: bool,
+        #[reflect(tag = 3)]
+        c: i64,
+    }
+
+    #[derive(Reflect)]
+    struct Clash {
+        #[reflect(tag = 2)]
+        a: u32,
+        b: bool,
+    }
+
+    let mut db = Db::new();
+    Tagged::register(&mut db);
+    Clash::register(&mut db);
+    assert_eq!(
+        db.proto_schema::<Tagged>()?,
+        "syntax = \"proto3\";\n\nmessage Tagged {\n  uint32 a = 7;\n  bool b = 2;\n  int64 c = 3;\n}\n"
+    );
+    assert!(db.proto_schema::<Clash>().is_err());
+    Ok(())
+}
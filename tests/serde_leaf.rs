@@ -0,0 +1,47 @@
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use serde_reflect::*;
+use std::num::NonZeroU64;
+
+/// No `Default`, so it can only be a leaf if registration doesn't need one.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Handle {
+    path: String,
+}
+
+#[derive(Reflect, Debug, PartialEq)]
+struct Open {
+    id: NonZeroU64,
+    handle: Handle,
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Open::register(&mut db);
+    db.register_serde_leaf::<NonZeroU64>();
+    db.register_serde_leaf::<Handle>();
+
+    let open = Open {
+        id: NonZeroU64::new(3).unwrap(),
+        handle: Handle {
+            path: "/tmp/x".into(),
+        },
+    };
+    let expected = json!({"id": 3, "handle": {"path": "/tmp/x"}});
+    assert_eq!(
+        db.serialize(serde_json::value::Serializer, &open)?,
+        expected
+    );
+    assert_eq!(
+        db.compile()
+            .serialize(serde_json::value::Serializer, &open)?,
+        expected
+    );
+    let back: Open = db.deserialize(expected)?;
+    assert_eq!(back, open);
+    assert!(db
+        .deserialize::<Open, _>(json!({"id": 0, "handle": {"path": ""}}))
+        .is_err());
+    Ok(())
+}
@@ -1,4 +1,4 @@
-#![feature(const_type_id)]
+#![allow(non_local_definitions)]
 
 use serde_reflect_derive::Reflect;
 use proptest_derive::Arbitrary;
@@ -15,35 +15,38 @@ struct ComplicatedThing;
 #[derive(Reflect)]
 struct Dynamic;
 
-impl Reflect for ComplicatedThing {
+unsafe impl Reflect for ComplicatedThing {
+    type Key = Self;
     fn rust_type() -> StaticType {
         Cow::Borrowed(Self::RUST_TYPE)
     }
-    fn register(db: &mut Db<'_, '_>) {
+    fn register(db: &mut Db<'_>) {
         db.register_const::<Self>();
     }
 }
 impl StaticReflect for ComplicatedThing {
     const RUST_TYPE: &'static ReflectedType<'static> = &ReflectedType {
+        #[cfg(feature = "attrs")]
         attrs: Cow::Borrowed(&[]),
         id: TypeId::of::<ComplicatedThing>(),
         layout: Layout::new::<ComplicatedThing>(),
         name: "ComplicatedThing",
-        shape: DataShape::Struct(VariantData::Unit),
+        typ: ItemDeclaration::Struct(VariantData::Unit),
     };
 }
 
 #[test]
 fn main() -> Result<(), anyhow::Error> {
     common::single_type::<ComplicatedThing>();
+    // outlives the db that borrows it
+    let rfltyp = demote_static(Cow::Borrowed(ComplicatedThing::RUST_TYPE)).into_owned();
     let mut db = Db::new();
     // ensure we can insert a &'static Reflected<'static> and still use it dynamically
     db.insert(
         TypeId::of::<ComplicatedThing>(),
-        Cow::Borrowed(ComplicatedThing::RUST_TYPE),
+        demote_static(Cow::Borrowed(ComplicatedThing::RUST_TYPE)),
     );
     // ok, now we can insert a borrow of a stack local...
-    let rfltyp = ComplicatedThing::RUST_TYPE.clone();
     db.insert(TypeId::of::<ComplicatedThing>(), Cow::Borrowed(&rfltyp));
     db.register_const::<ComplicatedThing>();
     db.register_type::<Dynamic>();
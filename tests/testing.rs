@@ -0,0 +1,75 @@
+#![cfg(feature = "testing")]
+
+use serde::{Deserialize, Serialize, Serializer};
+use serde_reflect::testing::*;
+use serde_reflect::*;
+
+#[derive(Reflect, Serialize, Deserialize, Debug, PartialEq)]
+struct Reading {
+    sensor: String,
+    at: u64,
+    value: Temperature,
+    flags: [bool; 2],
+    kind: Kind,
+}
+
+#[derive(Reflect, Serialize, Deserialize, Debug, PartialEq)]
+#[repr(u16)]
+enum Kind {
+    Manual,
+    Scheduled { every: u32 },
+    Triggered(i16, char),
+}
+
+#[derive(Reflect, Serialize, Deserialize, Debug, PartialEq)]
+struct Plain {
+    name: String,
+    count: u32,
+    ok: bool,
+}
+
+/// Written as a string by hand, which reflection knows nothing about.
+#[derive(Reflect, Deserialize, Debug, PartialEq)]
+struct Temperature(i32);
+
+impl Serialize for Temperature {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(&format_args!("{}C", self.0))
+    }
+}
+
+fn db() -> Db<'static> {
+    let mut db = Db::new();
+    Reading::register(&mut db);
+    Plain::register(&mut db);
+    Kind::register(&mut db);
+    Temperature::register(&mut db);
+    db.register_serde_leaf::<String>();
+    db.register_leaf_strategy("[a-z]{0,6}");
+    db
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let db = db();
+    check_type::<Kind>(&db)?;
+    check_type::<Plain>(&db)?;
+
+    // a hand-written impl which disagrees with the reflected type is caught
+    let reading = Reading {
+        sensor: "attic".into(),
+        at: 1_600_000_000,
+        value: Temperature(21),
+        flags: [true, false],
+        kind: Kind::Triggered(-4, 'x'),
+    };
+    let err = check_value(&db, &reading).unwrap_err();
+    assert!(err.0.starts_with("Json: reflection wrote"), "{}", err);
+    assert!(check_type::<Reading>(&db).is_err());
+
+    // input serde rejects is not held against reflection
+    check_input::<Kind>(&db, Format::Json, br#"{"Nope": 1}"#)?;
+    check_input::<Kind>(&db, Format::Json, br#"{"Scheduled": {"every": 3}}"#)?;
+    check_input::<Kind>(&db, Format::Postcard, &[2, 7, 1, b'y'])?;
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use serde_reflect::*;
+use std::sync::OnceLock;
+
+#[derive(Reflect, Debug, PartialEq)]
+struct Job {
+    id: u32,
+    name: String,
+}
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+static DB: OnceLock<FrozenDb<'static>> = OnceLock::new();
+
+fn db() -> &'static FrozenDb<'static> {
+    DB.get_or_init(|| {
+        let mut db = Db::new();
+        Job::register(&mut db);
+        db.register_serde_leaf::<String>();
+        db.freeze()
+    })
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    assert_send_sync::<Db<'static>>();
+    assert_send_sync::<FrozenDb<'static>>();
+    assert_send_sync::<Plans>();
+
+    let workers: Vec<_> = (0..4)
+        .map(|id| {
+            let db = db().clone();
+            std::thread::spawn(move || {
+                let job = Job {
+                    id,
+                    name: format!("job {}", id),
+                };
+                let json = db
+                    .plans()
+                    .serialize(serde_json::value::Serializer, &job)
+                    .unwrap();
+                let back: Job = db.from_value(db.to_value(&job).unwrap()).unwrap();
+                assert_eq!(back, job);
+                json
+            })
+        })
+        .collect();
+    for (id, worker) in workers.into_iter().enumerate() {
+        let json = worker.join().unwrap();
+        assert_eq!(json["name"], format!("job {}", id));
+    }
+    Ok(())
+}
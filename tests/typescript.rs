@@ -0,0 +1,131 @@
+use serde_reflect::*;
+use std::{alloc::Layout, any::TypeId, borrow::Cow};
+
+#[allow(dead_code)]
+#[derive(Reflect)]
+#[repr(u16)]
+enum Shape {
+    Empty,
+    Circle { radius: u8 },
+    Pair(u8, bool),
+}
+
+#[derive(Reflect)]
+struct Id(u64);
+
+#[derive(Reflect)]
+struct Totals {
+    count: u64,
+    sum: i128,
+}
+
+#[derive(Reflect)]
+struct Drawing {
+    id: Id,
+    title: String,
+    nickname: Option<String>,
+    shapes: [Shape; 2],
+}
+
+// an enum whose labels aren't Rust identifiers, as if loaded from a schema file
+struct Quirky;
+
+fn arm(discriminant: u16, label: &'static str, variant: VariantData<'static>) -> EnumArm<'static> {
+    EnumArm {
+        label,
+        variant_index: discriminant,
+        discriminant,
+        #[cfg(feature = "attrs")]
+        attrs: Cow::Borrowed(&[]),
+        variant,
+    }
+}
+
+fn quirky() -> ReflectedType<'static> {
+    let labels = &[
+        "say \"hi\"",
+        "tab\tand\u{1}",
+        "line\u{2028}break",
+        "back\\slash",
+    ];
+    let byte = TupleField {
+        offset: 2,
+        #[cfg(feature = "attrs")]
+        attrs: Cow::Borrowed(&[]),
+        shape: Box::new(DataShape::Builtin(RustBuiltin::U8)),
+    };
+    ReflectedType {
+        id: TypeId::of::<Quirky>(),
+        name: "Quirky",
+        layout: Layout::new::<(u16, u8)>(),
+        typ: ItemDeclaration::Enum {
+            variant_labels_for_serde: labels,
+            variants: Cow::Owned(vec![
+                arm(0, labels[0], VariantData::Unit),
+                arm(1, labels[1], VariantData::Unit),
+                arm(2, labels[2], VariantData::Unit),
+                arm(3, labels[3], VariantData::Tuple(Cow::Owned(vec![byte]))),
+            ]),
+        },
+        #[cfg(feature = "attrs")]
+        attrs: Cow::Borrowed(&[]),
+    }
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Drawing::register(&mut db);
+    Shape::register(&mut db);
+    Id::register(&mut db);
+    db.register_serde_leaf::<String>();
+    db.register_serde_leaf::<Option<String>>();
+    db.register_leaf_typescript::<Option<String>>(LeafTypeScript {
+        typ: "string | null".into(),
+        optional: true,
+    });
+
+    assert_eq!(
+        db.typescript::<Drawing>()?,
+        r#"export interface Drawing {
+  id: Id;
+  title: string;
+  nickname?: string | null;
+  shapes: Shape[];
+}
+
+export type Id = number;
+
+export type Shape =
+  | "Empty"
+  | { Circle: {
+    radius: number;
+  } }
+  | { Pair: [number, boolean] };
+"#
+    );
+
+    // 64- and 128-bit integers are numbers too, as `JSON.parse` reads them
+    Totals::register(&mut db);
+    assert_eq!(
+        db.typescript::<Totals>()?,
+        r#"export interface Totals {
+  count: number;
+  sum: number;
+}
+"#
+    );
+
+    // labels are escaped as JSON strings, not Rust ones
+    db.insert(TypeId::of::<Quirky>(), Cow::Owned(quirky()));
+    assert_eq!(
+        db.typescript::<Quirky>()?,
+        r#"export type Quirky =
+  | "say \"hi\""
+  | "tab\tand\u0001"
+  | "line\u2028break"
+  | { "back\\slash": number };
+"#
+    );
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use serde_reflect::*;
+
+#[derive(Reflect, Debug, PartialEq)]
+#[repr(u16)]
+enum Mode {
+    Fast,
+    Slow,
+}
+
+#[derive(Reflect, Debug, PartialEq)]
+struct Settings {
+    level: u8,
+    mode: Mode,
+}
+
+fn s(v: &str) -> ReflectValue {
+    ReflectValue::Str(v.into())
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Settings::register(&mut db);
+    Mode::register(&mut db);
+
+    let orig = Settings {
+        level: 3,
+        mode: Mode::Slow,
+    };
+    let val = db.to_value(&orig)?;
+    assert_eq!(val.get("level"), Some(&ReflectValue::U8(3)));
+    assert_eq!(
+        val.get("mode").and_then(ReflectValue::variant),
+        Some("Slow")
+    );
+    let back: Settings = db.from_value(val)?;
+    assert_eq!(back, orig);
+
+    // a loosely typed tree, as a scripting layer might produce
+    let loose = ReflectValue::Map(vec![
+        (s("level"), ReflectValue::I64(7)),
+        (s("mode"), s("Fast")),
+    ]);
+    let coerced: Settings = db.from_value(loose)?;
+    assert_eq!(
+        coerced,
+        Settings {
+            level: 7,
+            mode: Mode::Fast
+        }
+    );
+
+    let too_wide = ReflectValue::Map(vec![
+        (s("level"), ReflectValue::U64(256)),
+        (s("mode"), s("Fast")),
+    ]);
+    assert!(db.from_value::<Settings>(too_wide).is_err());
+    Ok(())
+}
@@ -0,0 +1,75 @@
+#![cfg(feature = "attrs")]
+
+use serde_json::json;
+use serde_reflect::*;
+
+// version 0 had `name: String`, version 1 split it into first and last
+#[derive(Reflect, Debug, PartialEq)]
+#[reflect(version = 2)]
+struct Person {
+    first: String,
+    last: String,
+    age: u16,
+}
+
+fn split_name(mut val: ReflectValue) -> Result<ReflectValue, String> {
+    let fields = match &mut val {
+        ReflectValue::Map(fields) => fields,
+        _ => return Err("expected a map".into()),
+    };
+    let ix = fields
+        .iter()
+        .position(|(k, _)| *k == ReflectValue::Str("name".into()))
+        .ok_or("no name")?;
+    let name = match fields.remove(ix).1 {
+        ReflectValue::Str(name) => name,
+        _ => return Err("name is not a string".into()),
+    };
+    let mut parts = name.splitn(2, ' ');
+    let first = parts.next().unwrap_or("").to_string();
+    let last = parts.next().unwrap_or("").to_string();
+    fields.push((ReflectValue::Str("first".into()), ReflectValue::Str(first)));
+    fields.push((ReflectValue::Str("last".into()), ReflectValue::Str(last)));
+    Ok(val)
+}
+
+// version 1 had no age
+fn add_age(mut val: ReflectValue) -> Result<ReflectValue, String> {
+    if let ReflectValue::Map(fields) = &mut val {
+        fields.push((ReflectValue::Str("age".into()), ReflectValue::U16(0)));
+    }
+    Ok(val)
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Person::register(&mut db);
+    db.register_serde_leaf::<String>();
+    db.register_migration::<Person>(0, split_name)
+        .register_migration::<Person>(1, add_age);
+    assert_eq!(db.version_of::<Person>()?, 2);
+
+    let ada = Person {
+        first: "Ada".into(),
+        last: "Lovelace".into(),
+        age: 36,
+    };
+    let written = db.serialize_versioned(serde_json::value::Serializer, &ada)?;
+    assert_eq!(written["version"], 2);
+    assert_eq!(db.deserialize_versioned::<Person, _>(written)?, ada);
+
+    let old = json!({ "version": 0, "data": { "name": "Grace Hopper" } });
+    assert_eq!(
+        db.deserialize_versioned::<Person, _>(old)?,
+        Person {
+            first: "Grace".into(),
+            last: "Hopper".into(),
+            age: 0,
+        }
+    );
+
+    let future = json!({ "version": 3, "data": {} });
+    assert!(db.deserialize_versioned::<Person, _>(future).is_err());
+    Ok(())
+}
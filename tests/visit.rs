@@ -0,0 +1,86 @@
+use serde_reflect::*;
+
+#[allow(dead_code)]
+#[derive(Reflect)]
+#[repr(u16)]
+enum Auth {
+    Anonymous,
+    Password { user: String, secret: String },
+}
+
+#[derive(Reflect)]
+struct Service {
+    port: u16,
+    auth: Auth,
+    weights: [u8; 2],
+}
+
+#[derive(Default)]
+struct Strings {
+    paths: Vec<String>,
+    bytes: usize,
+    builtins: usize,
+}
+
+impl ReflectVisitor for Strings {
+    fn visit_builtin(&mut self, _: &FieldPath, _: RustBuiltin, _: ReflectValue) {
+        self.builtins += 1;
+    }
+
+    fn visit_leaf(&mut self, path: &FieldPath, leaf: Leaf<'_, '_>) {
+        if let Some(s) = leaf.downcast_ref::<String>() {
+            self.paths.push(path.to_string());
+            self.bytes += s.len();
+        }
+    }
+}
+
+struct SkipAuth(usize);
+
+impl ReflectVisitor for SkipAuth {
+    fn visit_enum(&mut self, _: &FieldPath, _: &ReflectedType<'_>) -> Flow {
+        Flow::Skip
+    }
+
+    fn visit_builtin(&mut self, _: &FieldPath, _: RustBuiltin, _: ReflectValue) {
+        self.0 += 1;
+    }
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Service::register(&mut db);
+    Auth::register(&mut db);
+    db.register_serde_leaf::<String>();
+
+    let svc = Service {
+        port: 8080,
+        auth: Auth::Password {
+            user: "admin".into(),
+            secret: "hunter2".into(),
+        },
+        weights: [3, 4],
+    };
+    let mut strings = Strings::default();
+    db.walk(&svc, &mut strings)?;
+    assert_eq!(
+        strings.paths,
+        ["auth::Password.user", "auth::Password.secret"]
+    );
+    assert_eq!(strings.bytes, 12);
+    assert_eq!(strings.builtins, 3);
+
+    let mut skip = SkipAuth(0);
+    db.walk(&svc, &mut skip)?;
+    assert_eq!(skip.0, 3);
+
+    let anon = Service {
+        auth: Auth::Anonymous,
+        ..svc
+    };
+    let mut strings = Strings::default();
+    db.walk(&anon, &mut strings)?;
+    assert!(strings.paths.is_empty());
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use serde_reflect::*;
+
+#[derive(Reflect, Debug, PartialEq)]
+struct Account {
+    name: String,
+    retries: u16,
+    token: [u8; 3],
+    note: (String, bool),
+}
+
+struct Scrub;
+
+impl ReflectVisitorMut for Scrub {
+    fn visit_builtin(&mut self, path: &FieldPath, mut builtin: BuiltinMut<'_>) {
+        if let Some(n) = builtin.downcast_mut::<u16>() {
+            *n = (*n).min(5);
+        } else if path.to_string().starts_with("token") {
+            builtin.set(ReflectValue::U8(0)).unwrap();
+        }
+    }
+
+    fn visit_leaf(&mut self, _: &FieldPath, mut leaf: LeafMut<'_, '_>) {
+        if let Some(s) = leaf.downcast_mut::<String>() {
+            *s = s.trim().into();
+        }
+    }
+}
+
+#[test]
+fn main() -> Result<(), anyhow::Error> {
+    let mut db = Db::new();
+    Account::register(&mut db);
+    db.register_serde_leaf::<String>();
+
+    let mut acct = Account {
+        name: "  root ".into(),
+        retries: 40,
+        token: [9, 8, 7],
+        note: (" hi".into(), true),
+    };
+    db.walk_mut(&mut acct, &mut Scrub)?;
+    assert_eq!(
+        acct,
+        Account {
+            name: "root".into(),
+            retries: 5,
+            token: [0, 0, 0],
+            note: ("hi".into(), true),
+        }
+    );
+    Ok(())
+}